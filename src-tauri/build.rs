@@ -14,7 +14,7 @@ fn ensure_icon() -> std::io::Result<()> {
         let rgba = img.to_rgba8();
         let (w, h) = rgba.dimensions();
         let mut icon_dir = ico::IconDir::new(ico::ResourceType::Icon);
-        let icon_img = ico::IconImage::from_rgba_data(w as u32, h as u32, rgba.into_raw());
+        let icon_img = ico::IconImage::from_rgba_data(w, h, rgba.into_raw());
         icon_dir.add_entry(ico::IconDirEntry::encode(&icon_img).unwrap());
         let mut file = fs::File::create(&ico_path)?;
         icon_dir.write(&mut file)?;
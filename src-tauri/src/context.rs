@@ -0,0 +1,170 @@
+//! Fits chat history into a model's context window. Token counts are estimates
+//! (no tokenizer per model): one token per CJK character, about four bytes per
+//! token for everything else. The leading system prompt and the latest turn are
+//! always sent; older messages go newest-first while they fit.
+
+use crate::http::HttpClient;
+use crate::logger::{self, Level};
+use crate::providers;
+use crate::search::is_cjk;
+use crate::{AppConfig, Message};
+
+/// Assumed when neither the settings, the server nor our tables know the model.
+const DEFAULT_CONTEXT: u32 = 8192;
+/// Upper bound of the tokens kept free for the reply.
+const MAX_REPLY_RESERVE: u32 = 8192;
+/// Role markers and separators the chat template adds around each message.
+const MESSAGE_OVERHEAD: u32 = 4;
+
+/// Rough token count of one message.
+pub fn estimate_tokens(message: &Message) -> u32 {
+  let (cjk, other) = message.content.chars().fold((0u32, 0u32), |(cjk, other), c| {
+    if is_cjk(c) { (cjk + 1, other) } else { (cjk, other + c.len_utf8() as u32) }
+  });
+  cjk + other.div_ceil(4) + MESSAGE_OVERHEAD
+}
+
+/// Context window of `model` in tokens: `contextTokens` from the settings when set,
+/// else what the provider reports.
+pub async fn context_length(client: &HttpClient, config: &AppConfig, model: &str) -> u32 {
+  match config.context_tokens.filter(|n| *n > 0) {
+    Some(n) => n,
+    None => providers::get(&config.provider).context_length(client, config, model).await.unwrap_or(DEFAULT_CONTEXT),
+  }
+}
+
+/// Tokens of prompt a `context`-token window takes, leaving a reserve for the reply.
+pub fn budget(context: u32) -> u32 {
+  context - (context / 4).min(MAX_REPLY_RESERVE)
+}
+
+/// The messages to send within `budget` tokens, and how many were left out.
+/// Leading system messages and everything from the last user message on are kept
+/// even when they alone exceed the budget.
+pub fn fit(messages: &[Message], budget: u32) -> (Vec<Message>, usize) {
+  let system = messages.iter().take_while(|m| m.role == "system").count();
+  let latest = messages
+    .iter()
+    .rposition(|m| m.role == "user")
+    .filter(|i| *i >= system)
+    .unwrap_or(messages.len().saturating_sub(1).max(system));
+  let pinned: u32 = messages[..system].iter().chain(&messages[latest..]).map(estimate_tokens).sum();
+  let mut left = budget.saturating_sub(pinned);
+  // older turns, newest first, up to the first that does not fit
+  let history = &messages[system..latest];
+  let mut start = history.len();
+  while start > 0 {
+    let cost = estimate_tokens(&history[start - 1]);
+    if cost > left {
+      break;
+    }
+    left -= cost;
+    start -= 1;
+  }
+  // a cut history should open with a prompt, not with half of an exchange
+  if start > 0 {
+    start += history[start..].iter().take_while(|m| m.role != "user").count();
+  }
+  let kept = messages[..system].iter().chain(&history[start..]).chain(&messages[latest..]).cloned().collect();
+  (kept, start)
+}
+
+/// `messages` trimmed to the context window of `model`, and that window; logs what
+/// was left out.
+pub async fn fit_model(client: &HttpClient, config: &AppConfig, model: &str, messages: &[Message], id: Option<&str>) -> (Vec<Message>, u32) {
+  let context = context_length(client, config, model).await;
+  let budget = budget(context);
+  let (kept, dropped) = fit(messages, budget);
+  let tokens: u32 = kept.iter().map(estimate_tokens).sum();
+  if dropped > 0 || tokens > budget {
+    let level = if tokens > budget { Level::Warn } else { Level::Info };
+    logger::log(level, "context", id, "trimmed", serde_json::json!({
+      "model": model,
+      "budget": budget,
+      "tokens": tokens,
+      "dropped": dropped,
+      "kept": kept.len(),
+    }));
+  }
+  (kept, context)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn msg(role: &str, content: &str) -> Message {
+    Message { role: role.to_string(), content: content.to_string() }
+  }
+
+  /// Each message is named by its two-character content, so every one costs 5 tokens.
+  fn conversation(names: &[&str]) -> Vec<Message> {
+    names.iter().map(|n| {
+      let role = match &n[..1] { "s" => "system", "u" => "user", _ => "assistant" };
+      msg(role, n)
+    }).collect()
+  }
+
+  fn names(messages: &[Message]) -> Vec<&str> {
+    messages.iter().map(|m| m.content.as_str()).collect()
+  }
+
+  #[test]
+  fn estimates_cjk_per_character() {
+    assert_eq!(estimate_tokens(&msg("user", "")), MESSAGE_OVERHEAD);
+    assert_eq!(estimate_tokens(&msg("user", "abcdefgh")), 2 + MESSAGE_OVERHEAD);
+    assert_eq!(estimate_tokens(&msg("user", "你好世界")), 4 + MESSAGE_OVERHEAD);
+  }
+
+  #[test]
+  fn budget_reserves_room_for_the_reply() {
+    assert_eq!(budget(8192), 6144);
+    assert_eq!(budget(200_000), 200_000 - MAX_REPLY_RESERVE);
+  }
+
+  #[test]
+  fn fit_empty_and_system_only() {
+    let (kept, dropped) = fit(&[], 100);
+    assert_eq!((kept.len(), dropped), (0, 0));
+    let (kept, dropped) = fit(&conversation(&["s0"]), 0);
+    assert_eq!((names(&kept), dropped), (vec!["s0"], 0));
+  }
+
+  #[test]
+  fn fit_keeps_everything_that_fits() {
+    let messages = conversation(&["a0", "u1", "a1", "u2"]);
+    let (kept, dropped) = fit(&messages, 1000);
+    assert_eq!((names(&kept), dropped), (vec!["a0", "u1", "a1", "u2"], 0));
+  }
+
+  #[test]
+  fn fit_drops_oldest_turns_first() {
+    let messages = conversation(&["s0", "u1", "a1", "u2", "a2", "u3"]);
+    let (kept, dropped) = fit(&messages, 20);
+    assert_eq!((names(&kept), dropped), (vec!["s0", "u2", "a2", "u3"], 2));
+  }
+
+  #[test]
+  fn fit_cut_history_opens_with_a_prompt() {
+    // a2 would fit, but without u2 it answers nothing that is sent
+    let messages = conversation(&["s0", "u1", "a1", "u2", "a2", "u3"]);
+    let (kept, dropped) = fit(&messages, 15);
+    assert_eq!((names(&kept), dropped), (vec!["s0", "u3"], 4));
+  }
+
+  #[test]
+  fn fit_pins_a_latest_turn_larger_than_the_budget() {
+    let mut messages = conversation(&["s0", "u1", "a1"]);
+    messages.push(msg("user", &"x".repeat(4000)));
+    let (kept, dropped) = fit(&messages, 50);
+    assert_eq!(kept.len(), 2);
+    assert_eq!((kept[0].content.as_str(), kept[1].content.len(), dropped), ("s0", 4000, 2));
+  }
+
+  #[test]
+  fn fit_without_a_prompt_pins_the_last_message() {
+    let messages = conversation(&["s0", "a1", "a2"]);
+    let (kept, dropped) = fit(&messages, 10);
+    assert_eq!((names(&kept), dropped), (vec!["s0", "a2"], 1));
+  }
+}
@@ -0,0 +1,788 @@
+//! Conversation history in `conversations.db`, an SQLite database next to the
+//! executable. Replaces `conversations.json`, which is imported once and renamed.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::logger::{self, Level};
+use crate::providers::split_think;
+use crate::search;
+
+/// `SCHEMA[i]` takes a database from `user_version` i to i + 1.
+const SCHEMA: &[&str] = &[SCHEMA_V1, search::SCHEMA, SCHEMA_V3, SCHEMA_V4];
+
+const SCHEMA_V1: &str = "
+  CREATE TABLE conversations (
+    id TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    model TEXT NOT NULL DEFAULT '',
+    provider TEXT NOT NULL DEFAULT '',
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+  );
+  CREATE INDEX conversations_updated ON conversations(updated_at DESC);
+  CREATE TABLE messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    conversation_id TEXT NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    role TEXT NOT NULL,
+    content TEXT NOT NULL,
+    thinking TEXT NOT NULL DEFAULT '',
+    model TEXT,
+    provider TEXT,
+    created_at INTEGER NOT NULL,
+    UNIQUE (conversation_id, position)
+  );
+  CREATE TABLE metadata (
+    conversation_id TEXT NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (conversation_id, key)
+  );
+";
+
+/// Branches: every message hangs off its parent (roots have none), and of several
+/// children the one selected last is on the active branch. `position` stays the
+/// insertion order. Existing conversations become single-branch chains.
+const SCHEMA_V3: &str = "
+  ALTER TABLE messages ADD COLUMN parent_id INTEGER REFERENCES messages(id) ON DELETE CASCADE;
+  ALTER TABLE messages ADD COLUMN selected_at INTEGER NOT NULL DEFAULT 0;
+  UPDATE messages SET parent_id = (
+    SELECT p.id FROM messages p
+    WHERE p.conversation_id = messages.conversation_id AND p.position < messages.position
+    ORDER BY p.position DESC LIMIT 1
+  );
+  CREATE INDEX messages_parent ON messages(parent_id);
+";
+
+/// Every inserted message and branch switch asks for `MAX(selected_at)`; without an
+/// index that scans the whole table, which made large imports quadratic.
+const SCHEMA_V4: &str = "
+  CREATE INDEX messages_selected ON messages(selected_at);
+";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessage {
+  /// Database id; set on messages read back, ignored on messages written.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub id: Option<i64>,
+  pub role: String,
+  /// The answer without its reasoning; inline `<think>` sections are moved to `thinking` on save.
+  pub content: String,
+  #[serde(default)]
+  pub thinking: String,
+  /// Unix milliseconds.
+  #[serde(default)]
+  pub created_at: i64,
+  /// Model and provider that wrote an assistant message.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub model: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub provider: Option<String>,
+  /// Ids of this message and its alternatives (edits, regenerations) in creation
+  /// order; empty when there are none.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub siblings: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Conversation {
+  pub id: String,
+  pub title: String,
+  #[serde(default)]
+  pub model: String,
+  #[serde(default)]
+  pub provider: String,
+  #[serde(default)]
+  pub created_at: i64,
+  #[serde(default)]
+  pub updated_at: i64,
+  /// The active branch, first message first.
+  #[serde(default)]
+  pub messages: Vec<ChatMessage>,
+  #[serde(default)]
+  pub metadata: BTreeMap<String, String>,
+}
+
+/// A row of the conversation list.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationSummary {
+  pub id: String,
+  pub title: String,
+  pub model: String,
+  pub provider: String,
+  pub created_at: i64,
+  pub updated_at: i64,
+  /// Messages on the active branch.
+  pub message_count: i64,
+}
+
+/// Fields of `update_conversation`; unset ones are left alone.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ConversationPatch {
+  pub title: Option<String>,
+  pub model: Option<String>,
+  pub provider: Option<String>,
+}
+
+/// Managed state; the database is opened on first use.
+#[derive(Default)]
+pub struct ConversationDb(Arc<Mutex<Option<Connection>>>);
+
+impl ConversationDb {
+  /// Runs `f` on the open database, opening (and migrating, and importing into) it
+  /// first if needed. SQLite calls block, so this happens on the blocking pool
+  /// rather than on a runtime worker.
+  pub async fn with<T: Send + 'static>(
+    &self,
+    dir: PathBuf,
+    f: impl FnOnce(&mut Connection) -> Result<T, AppError> + Send + 'static,
+  ) -> Result<T, AppError> {
+    let db = Arc::clone(&self.0);
+    tauri::async_runtime::spawn_blocking(move || {
+      let mut guard = db.lock()?;
+      if guard.is_none() {
+        *guard = Some(open(&dir)?);
+      }
+      match guard.as_mut() {
+        Some(conn) => f(conn),
+        None => Err(AppError::internal("conversation database is not open")),
+      }
+    })
+    .await?
+  }
+}
+
+/// Where `conversations.json` lived and `conversations.db` now lives: next to the
+/// executable, or the app data dir when that is unknown.
+pub fn dir(app: &tauri::AppHandle) -> Result<PathBuf, AppError> {
+  use tauri::Manager;
+  if let Some(dir) = std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)) {
+    return Ok(dir);
+  }
+  Ok(app.path().app_local_data_dir()?)
+}
+
+fn open(dir: &Path) -> Result<Connection, AppError> {
+  std::fs::create_dir_all(dir)?;
+  let mut conn = Connection::open(dir.join("conversations.db"))?;
+  conn.pragma_update(None, "journal_mode", "WAL")?;
+  init(&mut conn)?;
+  import_json(&mut conn, dir)?;
+  Ok(conn)
+}
+
+/// What every connection needs before use: foreign keys, the SQL functions the
+/// triggers call, and the current schema.
+pub(crate) fn init(conn: &mut Connection) -> Result<(), AppError> {
+  conn.pragma_update(None, "foreign_keys", true)?;
+  search::register(conn)?;
+  migrate(conn)
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), AppError> {
+  let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+  if version >= SCHEMA.len() {
+    return Ok(());
+  }
+  let tx = conn.transaction()?;
+  for step in &SCHEMA[version..] {
+    tx.execute_batch(step)?;
+  }
+  tx.pragma_update(None, "user_version", SCHEMA.len())?;
+  tx.commit()?;
+  logger::log(Level::Info, "conversations", None, "schema migrated", serde_json::json!({ "from": version, "to": SCHEMA.len() }));
+  Ok(())
+}
+
+/// Moves `conversations.json` into the database once, then renames it so it is not
+/// imported again. Conversations already in the database are left alone.
+fn import_json(conn: &mut Connection, dir: &Path) -> Result<(), AppError> {
+  let path = dir.join("conversations.json");
+  let text = match std::fs::read_to_string(&path) {
+    Ok(text) => text,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+    Err(e) => return Err(e.into()),
+  };
+  let conversations: Vec<Conversation> = match serde_json::from_str(&text) {
+    Ok(list) => list,
+    Err(e) => {
+      // leave the file for the user; a later start would fail the same way
+      logger::log(Level::Warn, "conversations", None, "conversations.json not imported", serde_json::json!({ "error": e.to_string() }));
+      return Ok(());
+    }
+  };
+  let total = conversations.len();
+  let existing = import(conn, conversations)?;
+  std::fs::rename(&path, dir.join("conversations.json.imported"))?;
+  logger::log(Level::Info, "conversations", None, "conversations.json imported", serde_json::json!({ "conversations": total - existing.len() }));
+  Ok(())
+}
+
+/// Stores conversations from elsewhere with their own timestamps, in one transaction.
+/// Ids already in the database are left alone and returned.
+pub fn import(conn: &mut Connection, conversations: Vec<Conversation>) -> Result<Vec<String>, AppError> {
+  let tx = conn.transaction()?;
+  let mut existing = Vec::new();
+  for mut c in conversations {
+    if exists(&tx, &c.id)? {
+      existing.push(c.id);
+      continue;
+    }
+    if c.created_at <= 0 {
+      let first = c.messages.iter().map(|m| m.created_at).filter(|t| *t > 0).min();
+      c.created_at = first.unwrap_or(c.updated_at);
+    }
+    insert_conversation(&tx, &c)?;
+    let last = c.messages.iter().map(|m| m.created_at).max().unwrap_or(0);
+    let updated_at = c.updated_at;
+    append(&tx, &c.id, c.messages)?;
+    // keep the original order of the list instead of the import time; exports
+    // without an update time fall back to their newest message or their creation
+    tx.execute(
+      "UPDATE conversations SET updated_at = MAX(?2, ?3, created_at) WHERE id = ?1",
+      params![c.id, updated_at, last],
+    )?;
+  }
+  tx.commit()?;
+  Ok(existing)
+}
+
+fn now() -> i64 {
+  std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .map(|d| d.as_millis() as i64)
+    .unwrap_or(0)
+}
+
+fn exists(conn: &Connection, id: &str) -> Result<bool, AppError> {
+  Ok(conn.query_row("SELECT 1 FROM conversations WHERE id = ?1", [id], |_| Ok(())).optional()?.is_some())
+}
+
+fn not_found(id: &str) -> AppError {
+  AppError::not_found(format!("no conversation with id '{}'", id))
+}
+
+fn insert_conversation(tx: &Transaction, c: &Conversation) -> Result<(), AppError> {
+  let created_at = if c.created_at > 0 { c.created_at } else { now() };
+  tx.execute(
+    "INSERT INTO conversations (id, title, model, provider, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    params![c.id, c.title, c.model, c.provider, created_at, c.updated_at.max(created_at)],
+  )?;
+  for (key, value) in &c.metadata {
+    tx.execute("INSERT INTO metadata (conversation_id, key, value) VALUES (?1, ?2, ?3)", params![c.id, key, value])?;
+  }
+  Ok(())
+}
+
+/// Adds `messages` after the last one of conversation `id` and bumps its `updated_at`.
+fn append(tx: &Transaction, id: &str, messages: Vec<ChatMessage>) -> Result<(), AppError> {
+  let mut parent = active_branch(&links(tx, id)?).last().copied();
+  let mut last = 0;
+  for m in messages {
+    last = last.max(m.created_at);
+    parent = Some(insert_message(tx, id, parent, m)?);
+  }
+  tx.execute("UPDATE conversations SET updated_at = MAX(updated_at, ?2, ?3) WHERE id = ?1", params![id, last, now()])?;
+  Ok(())
+}
+
+/// Inserts `m` under `parent` as the selected child and returns its id.
+fn insert_message(tx: &Transaction, id: &str, parent: Option<i64>, m: ChatMessage) -> Result<i64, AppError> {
+  let (content, inline) = split_think(&m.content);
+  let thinking = if m.thinking.is_empty() { inline } else { m.thinking };
+  let created_at = if m.created_at > 0 { m.created_at } else { now() };
+  tx.execute(
+    "INSERT INTO messages (conversation_id, position, parent_id, selected_at, role, content, thinking, model, provider, created_at)
+     VALUES (?1, (SELECT COALESCE(MAX(position) + 1, 0) FROM messages WHERE conversation_id = ?1), ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+    params![id, parent, next_selection(tx)?, m.role, content, thinking, m.model, m.provider, created_at],
+  )?;
+  Ok(tx.last_insert_rowid())
+}
+
+/// A `selected_at` later than every one given out before, even within one millisecond.
+fn next_selection(conn: &Connection) -> Result<i64, AppError> {
+  let latest: i64 = conn.query_row("SELECT COALESCE(MAX(selected_at), 0) FROM messages", [], |row| row.get(0))?;
+  Ok(now().max(latest + 1))
+}
+
+/// `(id, parent_id, selected_at)` of every message of conversation `id`, in insertion order.
+fn links(conn: &Connection, id: &str) -> Result<Vec<(i64, Option<i64>, i64)>, AppError> {
+  let mut stmt = conn.prepare("SELECT id, parent_id, selected_at FROM messages WHERE conversation_id = ?1 ORDER BY position")?;
+  let rows = stmt.query_map([id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+  Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Children of each message (`None` for the roots), in insertion order.
+fn children(links: &[(i64, Option<i64>, i64)]) -> HashMap<Option<i64>, Vec<i64>> {
+  let mut out: HashMap<Option<i64>, Vec<i64>> = HashMap::new();
+  for (id, parent, _) in links {
+    out.entry(*parent).or_default().push(*id);
+  }
+  out
+}
+
+/// Ids on the active branch, root first: from the roots down, the child selected last.
+fn active_branch(links: &[(i64, Option<i64>, i64)]) -> Vec<i64> {
+  let selected: HashMap<i64, i64> = links.iter().map(|(id, _, at)| (*id, *at)).collect();
+  let children = children(links);
+  let mut branch = Vec::new();
+  let mut parent = None;
+  while let Some(next) = children.get(&parent).and_then(|c| c.iter().max_by_key(|id| (selected[id], **id))) {
+    branch.push(*next);
+    parent = Some(*next);
+  }
+  branch
+}
+
+pub fn list(conn: &Connection) -> Result<Vec<ConversationSummary>, AppError> {
+  // `active` walks each active branch the way `active_branch` does: from the roots
+  // down, the child with the latest (selected_at, id)
+  let mut stmt = conn.prepare(
+    "WITH RECURSIVE active(conversation_id, id) AS (
+       SELECT m.conversation_id, m.id FROM messages m
+       WHERE m.parent_id IS NULL AND m.id = (
+         SELECT r.id FROM messages r WHERE r.conversation_id = m.conversation_id AND r.parent_id IS NULL
+         ORDER BY r.selected_at DESC, r.id DESC LIMIT 1
+       )
+       UNION ALL
+       SELECT a.conversation_id, m.id FROM active a JOIN messages m ON m.parent_id = a.id
+       WHERE m.id = (SELECT s.id FROM messages s WHERE s.parent_id = a.id ORDER BY s.selected_at DESC, s.id DESC LIMIT 1)
+     ),
+     counts(conversation_id, n) AS (SELECT conversation_id, COUNT(*) FROM active GROUP BY conversation_id)
+     SELECT c.id, c.title, c.model, c.provider, c.created_at, c.updated_at, COALESCE(counts.n, 0)
+     FROM conversations c LEFT JOIN counts ON counts.conversation_id = c.id
+     ORDER BY c.updated_at DESC",
+  )?;
+  let rows = stmt.query_map([], |row| {
+    Ok(ConversationSummary {
+      id: row.get(0)?,
+      title: row.get(1)?,
+      model: row.get(2)?,
+      provider: row.get(3)?,
+      created_at: row.get(4)?,
+      updated_at: row.get(5)?,
+      message_count: row.get(6)?,
+    })
+  })?;
+  Ok(rows.collect::<Result<_, _>>()?)
+}
+
+pub fn get(conn: &Connection, id: &str) -> Result<Conversation, AppError> {
+  let mut c = conn
+    .query_row(
+      "SELECT id, title, model, provider, created_at, updated_at FROM conversations WHERE id = ?1",
+      [id],
+      |row| {
+        Ok(Conversation {
+          id: row.get(0)?,
+          title: row.get(1)?,
+          model: row.get(2)?,
+          provider: row.get(3)?,
+          created_at: row.get(4)?,
+          updated_at: row.get(5)?,
+          messages: Vec::new(),
+          metadata: BTreeMap::new(),
+        })
+      },
+    )
+    .optional()?
+    .ok_or_else(|| not_found(id))?;
+  c.messages = branch(conn, id, None)?;
+  let mut stmt = conn.prepare("SELECT key, value FROM metadata WHERE conversation_id = ?1")?;
+  c.metadata = stmt.query_map([id], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+  Ok(c)
+}
+
+/// Messages of conversation `id` from the root down to `until`, or along the active
+/// branch when that is `None`, each with the ids of its alternatives.
+pub fn branch(conn: &Connection, id: &str, until: Option<i64>) -> Result<Vec<ChatMessage>, AppError> {
+  let links = links(conn, id)?;
+  let parents: HashMap<i64, Option<i64>> = links.iter().map(|(id, parent, _)| (*id, *parent)).collect();
+  let ids = match until {
+    None => active_branch(&links),
+    Some(leaf) => {
+      let mut ids = vec![leaf];
+      let mut current = *parents.get(&leaf).ok_or_else(|| message_not_found(leaf))?;
+      while let Some(parent) = current {
+        ids.push(parent);
+        current = parents.get(&parent).copied().flatten();
+      }
+      ids.reverse();
+      ids
+    }
+  };
+  let children = children(&links);
+  let mut stmt = conn.prepare(
+    "SELECT id, role, content, thinking, created_at, model, provider FROM messages WHERE conversation_id = ?1",
+  )?;
+  let mut messages: HashMap<i64, ChatMessage> = stmt
+    .query_map([id], |row| {
+      Ok(ChatMessage {
+        id: Some(row.get(0)?),
+        role: row.get(1)?,
+        content: row.get(2)?,
+        thinking: row.get(3)?,
+        created_at: row.get(4)?,
+        model: row.get(5)?,
+        provider: row.get(6)?,
+        siblings: Vec::new(),
+      })
+    })?
+    .map(|m| m.map(|m| (m.id.unwrap_or_default(), m)))
+    .collect::<Result<_, _>>()?;
+  Ok(
+    ids
+      .into_iter()
+      .filter_map(|mid| {
+        let mut m = messages.remove(&mid)?;
+        let siblings = &children[&parents[&mid]];
+        if siblings.len() > 1 {
+          m.siblings = siblings.clone();
+        }
+        Some(m)
+      })
+      .collect(),
+  )
+}
+
+fn message_not_found(id: i64) -> AppError {
+  AppError::not_found(format!("no message with id {} in this conversation", id))
+}
+
+/// Role and parent of message `message_id`, which has to belong to conversation `id`.
+fn message(conn: &Connection, id: &str, message_id: i64) -> Result<(String, Option<i64>), AppError> {
+  conn
+    .query_row(
+      "SELECT role, parent_id FROM messages WHERE id = ?1 AND conversation_id = ?2",
+      params![message_id, id],
+      |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()?
+    .ok_or_else(|| message_not_found(message_id))
+}
+
+/// Adds `m` as an alternative to message `of` and makes it the active branch.
+fn add_sibling(conn: &mut Connection, id: &str, of: i64, m: ChatMessage) -> Result<Conversation, AppError> {
+  let (role, parent) = message(conn, id, of)?;
+  if m.role != role {
+    return Err(AppError::invalid_config(format!("an alternative to a {} message has to be one too", role)));
+  }
+  let tx = conn.transaction()?;
+  insert_message(&tx, id, parent, m)?;
+  tx.execute("UPDATE conversations SET updated_at = ?2 WHERE id = ?1", params![id, now()])?;
+  tx.commit()?;
+  get(conn, id)
+}
+
+/// Starts a branch at message `message_id` with `message`, e.g. an edited prompt,
+/// leaving the original and everything after it on the old branch.
+pub fn fork(conn: &mut Connection, id: &str, message_id: i64, message: ChatMessage) -> Result<Conversation, AppError> {
+  add_sibling(conn, id, message_id, message)
+}
+
+/// Stores `reply` as another answer to the prompt of assistant message `message_id`.
+pub fn regenerate(conn: &mut Connection, id: &str, message_id: i64, reply: ChatMessage) -> Result<Conversation, AppError> {
+  if message(conn, id, message_id)?.0 != "assistant" {
+    return Err(AppError::invalid_config("only assistant replies can be regenerated"));
+  }
+  add_sibling(conn, id, message_id, reply)
+}
+
+/// Makes the branch through message `message_id` the active one. Below it, each
+/// message again continues with the child selected last.
+pub fn switch_branch(conn: &Connection, id: &str, message_id: i64) -> Result<Conversation, AppError> {
+  message(conn, id, message_id)?;
+  conn.execute(
+    "WITH RECURSIVE up(id) AS (
+       SELECT ?2
+       UNION ALL
+       SELECT m.parent_id FROM messages m JOIN up ON m.id = up.id WHERE m.parent_id IS NOT NULL
+     )
+     UPDATE messages SET selected_at = ?3 WHERE conversation_id = ?1 AND id IN up",
+    params![id, message_id, next_selection(conn)?],
+  )?;
+  get(conn, id)
+}
+
+/// Stores a new conversation with any messages it already has.
+pub fn create(conn: &mut Connection, mut c: Conversation) -> Result<Conversation, AppError> {
+  if c.id.trim().is_empty() {
+    return Err(AppError::invalid_config("a conversation needs an id"));
+  }
+  let tx = conn.transaction()?;
+  if exists(&tx, &c.id)? {
+    return Err(AppError::invalid_config(format!("conversation '{}' already exists", c.id)));
+  }
+  c.created_at = now();
+  c.updated_at = c.created_at;
+  let messages = std::mem::take(&mut c.messages);
+  insert_conversation(&tx, &c)?;
+  append(&tx, &c.id, messages)?;
+  tx.commit()?;
+  get(conn, &c.id)
+}
+
+pub fn append_messages(conn: &mut Connection, id: &str, messages: Vec<ChatMessage>) -> Result<(), AppError> {
+  let tx = conn.transaction()?;
+  if !exists(&tx, id)? {
+    return Err(not_found(id));
+  }
+  append(&tx, id, messages)?;
+  tx.commit()?;
+  Ok(())
+}
+
+pub fn update(conn: &Connection, id: &str, patch: ConversationPatch) -> Result<(), AppError> {
+  let changed = conn.execute(
+    "UPDATE conversations SET title = COALESCE(?2, title), model = COALESCE(?3, model),
+       provider = COALESCE(?4, provider), updated_at = ?5 WHERE id = ?1",
+    params![id, patch.title, patch.model, patch.provider, now()],
+  )?;
+  if changed == 0 {
+    return Err(not_found(id));
+  }
+  Ok(())
+}
+
+/// Sets (or with `None` removes) one metadata entry of a conversation.
+pub fn set_metadata(conn: &Connection, id: &str, key: &str, value: Option<&str>) -> Result<(), AppError> {
+  if !exists(conn, id)? {
+    return Err(not_found(id));
+  }
+  match value {
+    Some(value) => conn.execute(
+      "INSERT INTO metadata (conversation_id, key, value) VALUES (?1, ?2, ?3)
+       ON CONFLICT (conversation_id, key) DO UPDATE SET value = excluded.value",
+      params![id, key, value],
+    )?,
+    None => conn.execute("DELETE FROM metadata WHERE conversation_id = ?1 AND key = ?2", params![id, key])?,
+  };
+  Ok(())
+}
+
+pub fn delete(conn: &Connection, id: &str) -> Result<bool, AppError> {
+  Ok(conn.execute("DELETE FROM conversations WHERE id = ?1", [id])? > 0)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::error::ErrorCode;
+
+  fn db() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    init(&mut conn).unwrap();
+    conn
+  }
+
+  fn msg(role: &str, content: &str) -> ChatMessage {
+    ChatMessage { role: role.to_string(), content: content.to_string(), ..Default::default() }
+  }
+
+  fn conversation(id: &str, messages: Vec<ChatMessage>) -> Conversation {
+    Conversation {
+      id: id.to_string(),
+      title: format!("title of {}", id),
+      model: "qwen3".into(),
+      provider: "ollama".into(),
+      created_at: 0,
+      updated_at: 0,
+      messages,
+      metadata: BTreeMap::new(),
+    }
+  }
+
+  fn contents(c: &Conversation) -> Vec<&str> {
+    c.messages.iter().map(|m| m.content.as_str()).collect()
+  }
+
+  /// A fresh directory under the system temp dir, removed again on drop.
+  struct TempDir(PathBuf);
+
+  impl TempDir {
+    fn new(name: &str) -> Self {
+      let dir = std::env::temp_dir().join(format!("yao-{}-{}", name, std::process::id()));
+      let _ = std::fs::remove_dir_all(&dir);
+      std::fs::create_dir_all(&dir).unwrap();
+      Self(dir)
+    }
+  }
+
+  impl Drop for TempDir {
+    fn drop(&mut self) {
+      let _ = std::fs::remove_dir_all(&self.0);
+    }
+  }
+
+  #[test]
+  fn create_append_get_delete() {
+    let mut conn = db();
+    let created = create(&mut conn, conversation("c1", vec![msg("user", "hi")])).unwrap();
+    assert_eq!(contents(&created), ["hi"]);
+    assert!(created.created_at > 0);
+    let again = create(&mut conn, conversation("c1", Vec::new())).unwrap_err();
+    assert_eq!(again.code, ErrorCode::InvalidConfig);
+
+    append_messages(&mut conn, "c1", vec![msg("assistant", "<think>greet back</think>hello")]).unwrap();
+    let c = get(&conn, "c1").unwrap();
+    assert_eq!(contents(&c), ["hi", "hello"]);
+    assert_eq!(c.messages[1].thinking, "greet back");
+    assert_eq!((c.title.as_str(), c.model.as_str(), c.provider.as_str()), ("title of c1", "qwen3", "ollama"));
+    assert_eq!(list(&conn).unwrap().iter().map(|s| (s.id.as_str(), s.message_count)).collect::<Vec<_>>(), [("c1", 2)]);
+
+    let missing = append_messages(&mut conn, "nope", vec![msg("user", "?")]).unwrap_err();
+    assert_eq!(missing.code, ErrorCode::NotFound);
+    assert!(delete(&conn, "c1").unwrap());
+    assert!(!delete(&conn, "c1").unwrap());
+    assert_eq!(get(&conn, "c1").unwrap_err().code, ErrorCode::NotFound);
+    let orphans: i64 = conn.query_row("SELECT COUNT(*) FROM messages", [], |row| row.get(0)).unwrap();
+    assert_eq!(orphans, 0);
+  }
+
+  #[test]
+  fn update_and_metadata() {
+    let mut conn = db();
+    create(&mut conn, conversation("c1", Vec::new())).unwrap();
+    update(&conn, "c1", ConversationPatch { title: Some("Renamed".into()), ..Default::default() }).unwrap();
+    set_metadata(&conn, "c1", "pinned", Some("true")).unwrap();
+    let c = get(&conn, "c1").unwrap();
+    assert_eq!((c.title.as_str(), c.model.as_str()), ("Renamed", "qwen3"));
+    assert_eq!(c.metadata.get("pinned").map(String::as_str), Some("true"));
+    set_metadata(&conn, "c1", "pinned", None).unwrap();
+    assert!(get(&conn, "c1").unwrap().metadata.is_empty());
+    assert_eq!(update(&conn, "nope", ConversationPatch::default()).unwrap_err().code, ErrorCode::NotFound);
+  }
+
+  #[test]
+  fn import_json_moves_the_legacy_file() {
+    let tmp = TempDir::new("legacy-json");
+    let mut conn = db();
+    create(&mut conn, conversation("kept", vec![msg("user", "already here")])).unwrap();
+    let legacy = r#"[
+      {"id": "kept", "title": "stale copy", "messages": [{"role": "user", "content": "old"}]},
+      {"id": "old", "title": "From the JSON file", "createdAt": 1700000000000, "updatedAt": 1700000500000,
+       "messages": [
+         {"role": "user", "content": "question", "createdAt": 1700000000000},
+         {"role": "assistant", "content": "<think>hmm</think>answer", "createdAt": 1700000100000}
+       ]}
+    ]"#;
+    std::fs::write(tmp.0.join("conversations.json"), legacy).unwrap();
+    import_json(&mut conn, &tmp.0).unwrap();
+
+    assert!(!tmp.0.join("conversations.json").exists());
+    assert!(tmp.0.join("conversations.json.imported").exists());
+    let kept = get(&conn, "kept").unwrap();
+    assert_eq!((kept.title.as_str(), contents(&kept)), ("title of kept", vec!["already here"]));
+    let old = get(&conn, "old").unwrap();
+    assert_eq!(contents(&old), ["question", "answer"]);
+    assert_eq!(old.messages[1].thinking, "hmm");
+    assert_eq!((old.created_at, old.updated_at), (1_700_000_000_000, 1_700_000_500_000));
+
+    // nothing left to import the second time
+    import_json(&mut conn, &tmp.0).unwrap();
+    assert_eq!(list(&conn).unwrap().len(), 2);
+  }
+
+  #[test]
+  fn import_json_leaves_an_unreadable_file() {
+    let tmp = TempDir::new("broken-json");
+    let mut conn = db();
+    std::fs::write(tmp.0.join("conversations.json"), "{ not json").unwrap();
+    import_json(&mut conn, &tmp.0).unwrap();
+    assert!(tmp.0.join("conversations.json").exists());
+    assert!(list(&conn).unwrap().is_empty());
+  }
+
+  fn ids(c: &Conversation) -> Vec<i64> {
+    c.messages.iter().map(|m| m.id.unwrap()).collect()
+  }
+
+  fn count(conn: &Connection, id: &str) -> i64 {
+    list(conn).unwrap().into_iter().find(|s| s.id == id).unwrap().message_count
+  }
+
+  #[test]
+  fn fork_starts_a_branch_and_switch_returns() {
+    let mut conn = db();
+    let c = create(&mut conn, conversation("c", vec![msg("user", "q1"), msg("assistant", "a1"), msg("user", "q2"), msg("assistant", "a2")])).unwrap();
+    let [_, a1, q2, a2] = ids(&c)[..] else { panic!("{:?}", ids(&c)) };
+
+    let forked = fork(&mut conn, "c", q2, msg("user", "q2 edited")).unwrap();
+    assert_eq!(contents(&forked), ["q1", "a1", "q2 edited"]);
+    let edited = forked.messages[2].id.unwrap();
+    assert_eq!(forked.messages[2].siblings, [q2, edited]);
+    assert_eq!(count(&conn, "c"), 3);
+    append_messages(&mut conn, "c", vec![msg("assistant", "a2 edited")]).unwrap();
+    assert_eq!(contents(&get(&conn, "c").unwrap()), ["q1", "a1", "q2 edited", "a2 edited"]);
+
+    // the old branch is still there, and the walk goes down it again after a switch
+    let back = switch_branch(&conn, "c", q2).unwrap();
+    assert_eq!(contents(&back), ["q1", "a1", "q2", "a2"]);
+    assert_eq!(contents(&get(&conn, "c").unwrap()), ["q1", "a1", "q2", "a2"]);
+    assert_eq!(count(&conn, "c"), 4);
+    let again = switch_branch(&conn, "c", edited).unwrap();
+    assert_eq!(contents(&again), ["q1", "a1", "q2 edited", "a2 edited"]);
+
+    // reading another branch does not switch to it
+    let old = branch(&conn, "c", Some(a2)).unwrap();
+    assert_eq!(old.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), ["q1", "a1", "q2", "a2"]);
+    assert_eq!(contents(&get(&conn, "c").unwrap())[2], "q2 edited");
+    assert!(old[1].siblings.is_empty() && old[1].id == Some(a1));
+  }
+
+  #[test]
+  fn regenerate_adds_an_alternative_answer() {
+    let mut conn = db();
+    let c = create(&mut conn, conversation("c", vec![msg("user", "q"), msg("assistant", "first")])).unwrap();
+    let [q, first] = ids(&c)[..] else { panic!("{:?}", ids(&c)) };
+
+    let c = regenerate(&mut conn, "c", first, msg("assistant", "second")).unwrap();
+    assert_eq!(contents(&c), ["q", "second"]);
+    let second = c.messages[1].id.unwrap();
+    assert_eq!(c.messages[1].siblings, [first, second]);
+    assert_eq!(count(&conn, "c"), 2);
+
+    assert_eq!(contents(&switch_branch(&conn, "c", first).unwrap()), ["q", "first"]);
+    assert_eq!(count(&conn, "c"), 2);
+
+    assert_eq!(regenerate(&mut conn, "c", q, msg("assistant", "x")).unwrap_err().code, ErrorCode::InvalidConfig);
+    assert_eq!(regenerate(&mut conn, "c", first, msg("user", "x")).unwrap_err().code, ErrorCode::InvalidConfig);
+    assert_eq!(fork(&mut conn, "c", q, msg("assistant", "x")).unwrap_err().code, ErrorCode::InvalidConfig);
+  }
+
+  #[test]
+  fn branch_operations_check_the_message() {
+    let mut conn = db();
+    create(&mut conn, conversation("a", vec![msg("user", "in a")])).unwrap();
+    let b = create(&mut conn, conversation("b", vec![msg("user", "in b")])).unwrap();
+    let in_b = ids(&b)[0];
+    assert_eq!(switch_branch(&conn, "a", in_b).unwrap_err().code, ErrorCode::NotFound);
+    assert_eq!(switch_branch(&conn, "a", 9999).unwrap_err().code, ErrorCode::NotFound);
+    assert_eq!(fork(&mut conn, "a", in_b, msg("user", "x")).unwrap_err().code, ErrorCode::NotFound);
+    assert_eq!(branch(&conn, "a", Some(in_b)).unwrap_err().code, ErrorCode::NotFound);
+  }
+
+  #[test]
+  fn active_branch_follows_the_latest_selection() {
+    // 1 -> (2 -> 4, 3); 3 was selected last, so the branch is 1, 3
+    let links = [(1, None, 10), (2, Some(1), 20), (3, Some(1), 30), (4, Some(2), 40)];
+    assert_eq!(active_branch(&links), [1, 3]);
+    // selecting 4 selects its ancestors with it
+    let switched = [(1, None, 50), (2, Some(1), 50), (3, Some(1), 30), (4, Some(2), 50)];
+    assert_eq!(active_branch(&switched), [1, 2, 4]);
+    // equal selection times fall back to the newer id
+    let tied = [(1, None, 10), (2, Some(1), 20), (3, Some(1), 20)];
+    assert_eq!(active_branch(&tied), [1, 3]);
+    assert!(active_branch(&[]).is_empty());
+  }
+}
@@ -0,0 +1,208 @@
+//! The error every command returns and every `*-error` event carries.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::http::{HttpStatus, Timeout};
+
+/// Stable codes the frontend can branch on; messages are for people and may change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ErrorCode {
+  /// Nothing answered at the configured address.
+  ConnectionRefused,
+  /// The server rejected the API key.
+  Unauthorized,
+  /// The server does not have the requested model (or deployment).
+  ModelNotFound,
+  /// Rate limited or out of quota.
+  RateLimited,
+  Timeout,
+  /// Any other failure reported by the provider.
+  ProviderError,
+  /// The request or settings sent by the frontend are unusable.
+  InvalidConfig,
+  /// A stored conversation or message the request names does not exist.
+  NotFound,
+  /// A key is kept in the encrypted vault and it is locked, or the passphrase was wrong.
+  VaultLocked,
+  /// A local failure: files, paths, internal state.
+  Internal,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppError {
+  pub code: ErrorCode,
+  pub message: String,
+  /// Provider the failing call went to.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub provider: Option<String>,
+  /// HTTP status the provider answered with.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub status: Option<u16>,
+}
+
+impl AppError {
+  pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+    Self { code, message: message.into(), provider: None, status: None }
+  }
+
+  pub fn invalid_config(message: impl Into<String>) -> Self {
+    Self::new(ErrorCode::InvalidConfig, message)
+  }
+
+  pub fn not_found(message: impl Into<String>) -> Self {
+    Self::new(ErrorCode::NotFound, message)
+  }
+
+  pub fn internal(message: impl Into<String>) -> Self {
+    Self::new(ErrorCode::Internal, message)
+  }
+
+  /// Classifies a failed call to `provider`.
+  pub fn provider(err: &anyhow::Error, provider: &str) -> Self {
+    Self { provider: Some(provider.to_string()), ..Self::classify(err) }
+  }
+
+  fn classify(err: &anyhow::Error) -> Self {
+    let message = err.to_string();
+    if let Some(http) = err.downcast_ref::<HttpStatus>() {
+      let lower = http.message.to_ascii_lowercase();
+      let code = match http.status.as_u16() {
+        _ if lower.contains("quota") => ErrorCode::RateLimited,
+        401 | 403 => ErrorCode::Unauthorized,
+        404 => ErrorCode::ModelNotFound,
+        429 => ErrorCode::RateLimited,
+        _ if is_missing_model(&lower) => ErrorCode::ModelNotFound,
+        _ => ErrorCode::ProviderError,
+      };
+      return Self { status: Some(http.status.as_u16()), ..Self::new(code, message) };
+    }
+    if err.downcast_ref::<Timeout>().is_some() {
+      return Self::new(ErrorCode::Timeout, message);
+    }
+    for cause in err.chain() {
+      if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+        if e.is_timeout() {
+          return Self::new(ErrorCode::Timeout, message);
+        }
+        if e.is_connect() {
+          return Self::new(ErrorCode::ConnectionRefused, message);
+        }
+      }
+      if let Some(io) = cause.downcast_ref::<std::io::Error>() {
+        if io.kind() == std::io::ErrorKind::ConnectionRefused {
+          return Self::new(ErrorCode::ConnectionRefused, message);
+        }
+      }
+    }
+    // errors reported inside a 200 stream, e.g. Ollama's `{"error":"model 'x' not found"}`
+    if is_missing_model(&message.to_ascii_lowercase()) {
+      return Self::new(ErrorCode::ModelNotFound, message);
+    }
+    Self::new(ErrorCode::ProviderError, message)
+  }
+}
+
+fn is_missing_model(lower: &str) -> bool {
+  lower.contains("model") && (lower.contains("not found") || lower.contains("does not exist"))
+}
+
+impl fmt::Display for AppError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.message)
+  }
+}
+
+/// Local failures only; a failed provider call goes through [`AppError::provider`].
+impl From<anyhow::Error> for AppError {
+  fn from(err: anyhow::Error) -> Self {
+    Self::internal(err.to_string())
+  }
+}
+
+/// Command input that failed to parse.
+impl From<serde_json::Error> for AppError {
+  fn from(err: serde_json::Error) -> Self {
+    Self::invalid_config(err.to_string())
+  }
+}
+
+impl From<std::io::Error> for AppError {
+  fn from(err: std::io::Error) -> Self {
+    Self::internal(err.to_string())
+  }
+}
+
+impl From<rusqlite::Error> for AppError {
+  fn from(err: rusqlite::Error) -> Self {
+    Self::internal(err.to_string())
+  }
+}
+
+impl From<tauri::Error> for AppError {
+  fn from(err: tauri::Error) -> Self {
+    Self::internal(err.to_string())
+  }
+}
+
+impl<T> From<std::sync::PoisonError<T>> for AppError {
+  fn from(err: std::sync::PoisonError<T>) -> Self {
+    Self::internal(err.to_string())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::http::TimeoutPhase;
+  use reqwest::StatusCode;
+
+  fn code_of(err: impl Into<anyhow::Error>) -> (ErrorCode, Option<u16>) {
+    let e = AppError::provider(&err.into(), "openai");
+    assert_eq!(e.provider.as_deref(), Some("openai"));
+    (e.code, e.status)
+  }
+
+  fn status(code: u16, message: &str) -> HttpStatus {
+    HttpStatus::new(StatusCode::from_u16(code).unwrap(), message)
+  }
+
+  #[test]
+  fn classifies_http_statuses() {
+    assert_eq!(code_of(status(401, "Incorrect API key provided")), (ErrorCode::Unauthorized, Some(401)));
+    assert_eq!(code_of(status(403, "forbidden")), (ErrorCode::Unauthorized, Some(403)));
+    assert_eq!(code_of(status(404, "The model `gpt-5x` does not exist")), (ErrorCode::ModelNotFound, Some(404)));
+    assert_eq!(code_of(status(429, "Rate limit reached")), (ErrorCode::RateLimited, Some(429)));
+    assert_eq!(code_of(status(500, "internal error")), (ErrorCode::ProviderError, Some(500)));
+  }
+
+  #[test]
+  fn quota_is_rate_limited_whatever_the_status() {
+    let body = "You exceeded your current quota, please check your plan and billing details.";
+    assert_eq!(code_of(status(403, body)), (ErrorCode::RateLimited, Some(403)));
+    assert_eq!(code_of(status(400, "Quota exceeded for quota metric")), (ErrorCode::RateLimited, Some(400)));
+  }
+
+  #[test]
+  fn missing_model_inside_a_stream() {
+    assert_eq!(code_of(anyhow::anyhow!("model 'llama9' not found, try pulling it first")), (ErrorCode::ModelNotFound, None));
+    assert_eq!(code_of(status(400, "model \"x\" not found")), (ErrorCode::ModelNotFound, Some(400)));
+    assert_eq!(code_of(anyhow::anyhow!("unexpected end of stream")), (ErrorCode::ProviderError, None));
+  }
+
+  #[test]
+  fn classifies_timeouts_and_refused_connections() {
+    let timeout = Timeout { phase: TimeoutPhase::FirstByte, after: std::time::Duration::from_secs(120) };
+    assert_eq!(code_of(timeout), (ErrorCode::Timeout, None));
+    let refused = anyhow::Error::from(std::io::Error::from(std::io::ErrorKind::ConnectionRefused)).context("error sending request");
+    assert_eq!(code_of(refused), (ErrorCode::ConnectionRefused, None));
+  }
+
+  #[test]
+  fn local_failures_are_internal() {
+    let err = AppError::from(anyhow::anyhow!("model 'x' not found"));
+    assert_eq!((err.code, err.provider, err.status), (ErrorCode::Internal, None, None));
+  }
+}
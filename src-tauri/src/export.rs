@@ -0,0 +1,320 @@
+//! Renders a stored conversation for use outside the app: Markdown for pasting into
+//! docs and tickets, a single HTML file that needs nothing else to display, and a
+//! versioned JSON layout that other tools can rely on.
+
+use std::path::Path;
+
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+
+use crate::conversations::{ChatMessage, Conversation};
+use crate::error::AppError;
+use crate::logger::format_ts;
+
+/// Layout version of the JSON export; raised when a field changes meaning or is removed.
+pub const JSON_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+  Markdown,
+  Html,
+  Json,
+}
+
+/// Renders `c` as `format` and writes it to `path`, replacing any file there.
+pub fn export(c: &Conversation, format: ExportFormat, path: &Path) -> Result<(), AppError> {
+  let text = match format {
+    ExportFormat::Markdown => markdown(c),
+    ExportFormat::Html => html(c),
+    ExportFormat::Json => json(c)?,
+  };
+  if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+    std::fs::create_dir_all(dir)?;
+  }
+  std::fs::write(path, text)?;
+  Ok(())
+}
+
+fn role_label(role: &str) -> &str {
+  match role {
+    "user" => "User",
+    "assistant" => "Assistant",
+    "system" => "System",
+    other => other,
+  }
+}
+
+/// "Assistant · llama3 (ollama) · 2024-05-01T10:00:00.000Z"
+fn message_heading(m: &ChatMessage) -> String {
+  let mut parts = vec![role_label(&m.role).to_string()];
+  match (&m.model, &m.provider) {
+    (Some(model), Some(provider)) => parts.push(format!("{} ({})", model, provider)),
+    (Some(model), None) => parts.push(model.clone()),
+    (None, Some(provider)) => parts.push(provider.clone()),
+    (None, None) => {}
+  }
+  if m.created_at > 0 {
+    parts.push(format_ts(m.created_at));
+  }
+  parts.join(" · ")
+}
+
+/// Model, provider and timestamps of the conversation as label/value pairs.
+fn details(c: &Conversation) -> Vec<(&'static str, String)> {
+  let mut out = Vec::new();
+  if !c.model.is_empty() {
+    out.push(("Model", c.model.clone()));
+  }
+  if !c.provider.is_empty() {
+    out.push(("Provider", c.provider.clone()));
+  }
+  if c.created_at > 0 {
+    out.push(("Created", format_ts(c.created_at)));
+  }
+  if c.updated_at > 0 {
+    out.push(("Updated", format_ts(c.updated_at)));
+  }
+  out
+}
+
+pub fn markdown(c: &Conversation) -> String {
+  let mut out = format!("# {}\n\n", c.title);
+  for (label, value) in details(c) {
+    out.push_str(&format!("- **{}:** {}\n", label, value));
+  }
+  for m in &c.messages {
+    out.push_str(&format!("\n---\n\n### {}\n\n", message_heading(m)));
+    if !m.thinking.trim().is_empty() {
+      // blank lines around the body let Markdown inside <details> render on GitHub
+      out.push_str(&format!("<details>\n<summary>Thinking</summary>\n\n{}\n\n</details>\n\n", m.thinking.trim()));
+    }
+    out.push_str(m.content.trim());
+    out.push('\n');
+  }
+  out
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonExport<'a> {
+  format: &'static str,
+  version: u32,
+  exported_at: String,
+  conversation: JsonConversation<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonConversation<'a> {
+  id: &'a str,
+  title: &'a str,
+  model: &'a str,
+  provider: &'a str,
+  created_at: String,
+  updated_at: String,
+  metadata: &'a std::collections::BTreeMap<String, String>,
+  messages: Vec<JsonMessage<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonMessage<'a> {
+  role: &'a str,
+  content: &'a str,
+  thinking: Option<&'a str>,
+  model: Option<&'a str>,
+  provider: Option<&'a str>,
+  created_at: String,
+}
+
+/// Its own structs rather than `Conversation` so internal changes do not leak into
+/// the exported layout; timestamps are ISO 8601 UTC.
+pub fn json(c: &Conversation) -> Result<String, AppError> {
+  let export = JsonExport {
+    format: "yao-conversation",
+    version: JSON_VERSION,
+    exported_at: crate::logger::now(),
+    conversation: JsonConversation {
+      id: &c.id,
+      title: &c.title,
+      model: &c.model,
+      provider: &c.provider,
+      created_at: format_ts(c.created_at),
+      updated_at: format_ts(c.updated_at),
+      metadata: &c.metadata,
+      messages: c
+        .messages
+        .iter()
+        .map(|m| JsonMessage {
+          role: &m.role,
+          content: &m.content,
+          thinking: Some(m.thinking.as_str()).filter(|t| !t.is_empty()),
+          model: m.model.as_deref(),
+          provider: m.provider.as_deref(),
+          created_at: format_ts(m.created_at),
+        })
+        .collect(),
+    },
+  };
+  Ok(serde_json::to_string_pretty(&export)?)
+}
+
+const STYLE: &str = "
+  body { font: 15px/1.6 -apple-system, BlinkMacSystemFont, 'Segoe UI', sans-serif; color: #1f2328; max-width: 860px; margin: 2rem auto; padding: 0 1rem; }
+  header dl { display: grid; grid-template-columns: max-content 1fr; gap: .2rem 1rem; color: #59636e; font-size: 13px; }
+  header dt { font-weight: 600; }
+  header dd { margin: 0; }
+  .message { border-top: 1px solid #d1d9e0; padding: 1rem 0; }
+  .message h2 { font-size: 13px; font-weight: 600; color: #59636e; margin: 0 0 .5rem; }
+  .message.user .body { background: #f6f8fa; border-radius: 8px; padding: .5rem 1rem; }
+  details { color: #59636e; border-left: 3px solid #d1d9e0; padding-left: 1rem; margin-bottom: .5rem; }
+  summary { cursor: pointer; }
+  pre { background: #f6f8fa; border-radius: 6px; padding: .75rem 1rem; overflow-x: auto; }
+  code { font: 13px/1.45 ui-monospace, SFMono-Regular, Menlo, Consolas, monospace; }
+  :not(pre) > code { background: #eff1f3; border-radius: 4px; padding: .1em .3em; }
+  table { border-collapse: collapse; }
+  th, td { border: 1px solid #d1d9e0; padding: .3rem .6rem; }
+  .hl-c { color: #6e7781; font-style: italic; }
+  .hl-s { color: #0a3069; }
+  .hl-n { color: #0550ae; }
+  .hl-k { color: #cf222e; }
+";
+
+pub fn html(c: &Conversation) -> String {
+  let mut out = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+  out.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n");
+  out.push_str(&format!("<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<header>\n", escape(&c.title), STYLE));
+  out.push_str(&format!("<h1>{}</h1>\n<dl>\n", escape(&c.title)));
+  for (label, value) in details(c) {
+    out.push_str(&format!("<dt>{}</dt><dd>{}</dd>\n", label, escape(&value)));
+  }
+  out.push_str("</dl>\n</header>\n");
+  for m in &c.messages {
+    out.push_str(&format!("<section class=\"message {}\">\n<h2>{}</h2>\n", escape(&m.role), escape(&message_heading(m))));
+    if !m.thinking.trim().is_empty() {
+      out.push_str(&format!("<details>\n<summary>Thinking</summary>\n{}</details>\n", render_markdown(&m.thinking)));
+    }
+    out.push_str(&format!("<div class=\"body\">\n{}</div>\n</section>\n", render_markdown(&m.content)));
+  }
+  out.push_str("</body>\n</html>\n");
+  out
+}
+
+/// Markdown to HTML with fenced code highlighted. Raw HTML in the message is shown
+/// as text, so an exported file never runs anything a model wrote.
+fn render_markdown(text: &str) -> String {
+  let mut options = Options::empty();
+  options.insert(Options::ENABLE_TABLES);
+  options.insert(Options::ENABLE_STRIKETHROUGH);
+  options.insert(Options::ENABLE_TASKLISTS);
+  let mut events = Vec::new();
+  // Some(lang) while inside a code block, collecting its text in `code`
+  let mut block: Option<String> = None;
+  let mut code = String::new();
+  for event in Parser::new_ext(text, options) {
+    match event {
+      Event::Start(Tag::CodeBlock(kind)) => {
+        block = Some(match kind {
+          CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or("").to_lowercase(),
+          CodeBlockKind::Indented => String::new(),
+        });
+      }
+      Event::Text(t) if block.is_some() => code.push_str(&t),
+      Event::End(TagEnd::CodeBlock) => {
+        let lang = block.take().unwrap_or_default();
+        let class = if lang.is_empty() { String::new() } else { format!(" class=\"language-{}\"", escape(&lang)) };
+        let body = highlight(&lang, &std::mem::take(&mut code));
+        events.push(Event::Html(format!("<pre><code{}>{}</code></pre>\n", class, body).into()));
+      }
+      Event::Html(raw) | Event::InlineHtml(raw) => events.push(Event::Text(raw)),
+      Event::Start(Tag::Link { link_type, dest_url, title, id }) if dest_url.trim_start().to_lowercase().starts_with("javascript:") => {
+        events.push(Event::Start(Tag::Link { link_type, dest_url: "#".into(), title, id }))
+      }
+      other => events.push(other),
+    }
+  }
+  let mut out = String::new();
+  pulldown_cmark::html::push_html(&mut out, events.into_iter());
+  out
+}
+
+fn escape(text: &str) -> String {
+  let mut out = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '<' => out.push_str("&lt;"),
+      '>' => out.push_str("&gt;"),
+      '&' => out.push_str("&amp;"),
+      '"' => out.push_str("&quot;"),
+      c => out.push(c),
+    }
+  }
+  out
+}
+
+/// Keywords of the languages models write most; one list serves them all.
+const KEYWORDS: &[&str] = &[
+  "as", "async", "await", "break", "case", "catch", "class", "const", "continue", "def", "default", "do", "elif",
+  "else", "enum", "export", "extends", "false", "fn", "for", "from", "func", "function", "if", "impl", "import",
+  "in", "interface", "let", "match", "mod", "mut", "new", "nil", "None", "null", "package", "pub", "return",
+  "self", "static", "struct", "super", "switch", "this", "throw", "trait", "true", "True", "False", "try", "type",
+  "use", "var", "while", "with", "yield", "SELECT", "FROM", "WHERE", "INSERT", "UPDATE", "DELETE", "JOIN",
+];
+
+/// Escaped `code` with comments, strings, numbers and keywords wrapped in `hl-*`
+/// spans. A simple scanner, not a parser: good enough to make code readable.
+fn highlight(lang: &str, code: &str) -> String {
+  if matches!(lang, "" | "text" | "txt" | "plain" | "plaintext" | "markdown" | "md") {
+    return escape(code);
+  }
+  let line_comment = match lang {
+    "python" | "py" | "sh" | "bash" | "shell" | "zsh" | "ruby" | "rb" | "yaml" | "yml" | "toml" | "r" | "perl"
+    | "dockerfile" | "makefile" | "powershell" | "ps1" => "#",
+    "sql" | "lua" | "haskell" | "hs" => "--",
+    _ => "//",
+  };
+  let block_comments = line_comment == "//";
+  let mut out = String::new();
+  let mut rest = code;
+  while let Some(c) = rest.chars().next() {
+    let (class, len) = if rest.starts_with(line_comment) {
+      (Some("c"), rest.find('\n').unwrap_or(rest.len()))
+    } else if block_comments && rest.starts_with("/*") {
+      (Some("c"), rest[2..].find("*/").map_or(rest.len(), |i| i + 4))
+    } else if matches!(c, '"' | '\'' | '`') {
+      (Some("s"), string_len(rest, c))
+    } else if c.is_ascii_digit() {
+      (Some("n"), rest.find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '.' || ch == '_')).unwrap_or(rest.len()))
+    } else if c.is_alphabetic() || c == '_' {
+      let n = rest.find(|ch: char| !(ch.is_alphanumeric() || ch == '_')).unwrap_or(rest.len());
+      (KEYWORDS.contains(&&rest[..n]).then_some("k"), n)
+    } else {
+      (None, c.len_utf8())
+    };
+    let (token, tail) = rest.split_at(len);
+    match class {
+      Some(class) => out.push_str(&format!("<span class=\"hl-{}\">{}</span>", class, escape(token))),
+      None => out.push_str(&escape(token)),
+    }
+    rest = tail;
+  }
+  out
+}
+
+/// Length of the string literal at the start of `s`, opened by `quote`. Only
+/// backticks span lines, so a stray apostrophe colors at most one line.
+fn string_len(s: &str, quote: char) -> usize {
+  let mut chars = s.char_indices().skip(1);
+  while let Some((i, c)) = chars.next() {
+    match c {
+      '\\' => {
+        chars.next();
+      }
+      '\n' if quote != '`' => return i,
+      c if c == quote => return i + c.len_utf8(),
+      _ => {}
+    }
+  }
+  s.len()
+}
@@ -0,0 +1,291 @@
+//! The HTTP client shared by every command, and the timeouts its calls run under.
+
+use std::fmt;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Result;
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use reqwest::{Client, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+
+/// `AppConfig.timeouts`, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Timeouts {
+  /// TCP/TLS connection setup.
+  pub connect_secs: u64,
+  /// From sending the request until the response headers arrive; local models
+  /// may have to be loaded first, so this is generous.
+  pub first_byte_secs: u64,
+  /// Longest gap allowed between two pieces of a response body.
+  pub idle_secs: u64,
+}
+
+impl Default for Timeouts {
+  fn default() -> Self {
+    Self { connect_secs: 10, first_byte_secs: 300, idle_secs: 120 }
+  }
+}
+
+/// Which of the [`Timeouts`] ran out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutPhase {
+  Connect,
+  FirstByte,
+  Idle,
+}
+
+/// A request that ran out of time. Carried inside `anyhow::Error`; callers that
+/// need to tell timeouts apart from other failures downcast to it.
+#[derive(Debug, Clone, Copy)]
+pub struct Timeout {
+  pub phase: TimeoutPhase,
+  pub after: Duration,
+}
+
+impl fmt::Display for Timeout {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let secs = self.after.as_secs();
+    match self.phase {
+      TimeoutPhase::Connect => write!(f, "timed out connecting to the server after {}s", secs),
+      TimeoutPhase::FirstByte => write!(f, "timed out waiting for the server to respond after {}s", secs),
+      TimeoutPhase::Idle => write!(f, "timed out: the server sent nothing for {}s", secs),
+    }
+  }
+}
+
+impl std::error::Error for Timeout {}
+
+/// Rate limiting and gateway failures: the request never reached the model, so
+/// sending it again is safe.
+const TRANSIENT_STATUSES: [u16; 4] = [429, 502, 503, 504];
+
+/// A failed response. [`HttpClient::send`] returns the transient statuses as this
+/// error itself; providers return the others once they have read the body.
+#[derive(Debug, Clone)]
+pub struct HttpStatus {
+  pub status: reqwest::StatusCode,
+  /// The server's `Retry-After`, if it sent one.
+  pub retry_after: Option<Duration>,
+  pub message: String,
+}
+
+impl fmt::Display for HttpStatus {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.message.is_empty() {
+      write!(f, "server answered {}", self.status)
+    } else {
+      write!(f, "{} (HTTP {})", self.message, self.status.as_u16())
+    }
+  }
+}
+
+impl std::error::Error for HttpStatus {}
+
+impl HttpStatus {
+  /// A failed response as reported by a provider, after it extracted the message.
+  pub fn new(status: reqwest::StatusCode, message: impl Into<String>) -> Self {
+    Self { status, retry_after: None, message: message.into() }
+  }
+
+  /// Rate limiting or a gateway failure, worth sending again.
+  pub fn is_transient(&self) -> bool {
+    TRANSIENT_STATUSES.contains(&self.status.as_u16())
+  }
+}
+
+/// A pooled `reqwest::Client` plus the timeouts it was built for. Cheap to clone;
+/// derefs to `Client` so requests are built as usual and sent with [`HttpClient::send`].
+#[derive(Clone)]
+pub struct HttpClient {
+  client: Client,
+  timeouts: Timeouts,
+}
+
+impl HttpClient {
+  fn new(timeouts: Timeouts) -> Self {
+    let client = Client::builder()
+      .connect_timeout(Duration::from_secs(timeouts.connect_secs))
+      .tcp_keepalive(Duration::from_secs(60))
+      .build()
+      .unwrap_or_else(|_| Client::new());
+    Self { client, timeouts }
+  }
+
+  /// Sends the request, failing with [`Timeout`] if the connection or the
+  /// response headers take too long.
+  pub async fn send(&self, req: RequestBuilder) -> Result<Response> {
+    let first_byte = Duration::from_secs(self.timeouts.first_byte_secs);
+    match tokio::time::timeout(first_byte, req.send()).await {
+      Err(_) => Err(Timeout { phase: TimeoutPhase::FirstByte, after: first_byte }.into()),
+      // connect_timeout is the only timeout the client itself enforces
+      Ok(Err(e)) if e.is_timeout() => Err(Timeout {
+        phase: TimeoutPhase::Connect,
+        after: Duration::from_secs(self.timeouts.connect_secs),
+      }.into()),
+      Ok(Err(e)) => Err(e.into()),
+      Ok(Ok(resp)) if TRANSIENT_STATUSES.contains(&resp.status().as_u16()) => {
+        let status = resp.status();
+        let retry_after = resp
+          .headers()
+          .get(reqwest::header::RETRY_AFTER)
+          .and_then(|v| v.to_str().ok())
+          .and_then(parse_retry_after);
+        let text = self.text(resp).await.unwrap_or_default();
+        Err(HttpStatus { status, retry_after, message: error_message(&text) }.into())
+      }
+      Ok(Ok(resp)) => Ok(resp),
+    }
+  }
+
+  /// Reads a whole (non-streamed) body as text, bounded by the idle timeout.
+  pub async fn text(&self, resp: Response) -> Result<String> {
+    let idle = Duration::from_secs(self.timeouts.idle_secs);
+    match tokio::time::timeout(idle, resp.text()).await {
+      Err(_) => Err(Timeout { phase: TimeoutPhase::Idle, after: idle }.into()),
+      Ok(text) => Ok(text?),
+    }
+  }
+
+  /// Reads a whole body as JSON, bounded by the idle timeout.
+  pub async fn json(&self, resp: Response) -> Result<serde_json::Value> {
+    Ok(serde_json::from_str(&self.text(resp).await?)?)
+  }
+
+  /// The body as a stream of chunks that fails with [`Timeout`] when it stalls.
+  pub fn stream(&self, resp: Response) -> BodyStream {
+    BodyStream {
+      inner: Box::pin(resp.bytes_stream()),
+      idle: Duration::from_secs(self.timeouts.idle_secs),
+    }
+  }
+}
+
+impl std::ops::Deref for HttpClient {
+  type Target = Client;
+
+  fn deref(&self) -> &Client {
+    &self.client
+  }
+}
+
+/// See [`HttpClient::stream`].
+pub struct BodyStream {
+  inner: Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>,
+  idle: Duration,
+}
+
+impl BodyStream {
+  pub async fn next(&mut self) -> Option<Result<Bytes>> {
+    match tokio::time::timeout(self.idle, self.inner.next()).await {
+      Err(_) => Some(Err(Timeout { phase: TimeoutPhase::Idle, after: self.idle }.into())),
+      Ok(item) => item.map(|chunk| chunk.map_err(Into::into)),
+    }
+  }
+}
+
+/// The error text of a failed response: the JSON `error.message` / `error` /
+/// `message` most APIs use, else the start of the body.
+fn error_message(text: &str) -> String {
+  if let Ok(v) = serde_json::from_str::<serde_json::Value>(text) {
+    let found = v
+      .get("error")
+      .and_then(|e| e.get("message").or(Some(e)))
+      .or_else(|| v.get("message"))
+      .and_then(|m| m.as_str());
+    if let Some(msg) = found {
+      return msg.to_string();
+    }
+  }
+  text.trim().chars().take(200).collect()
+}
+
+/// `Retry-After` is either delta-seconds or an HTTP date (`Sun, 06 Nov 1994 08:49:37 GMT`).
+fn parse_retry_after(value: &str) -> Option<Duration> {
+  let value = value.trim();
+  if let Ok(secs) = value.parse::<u64>() {
+    return Some(Duration::from_secs(secs));
+  }
+  let parts = value.split_whitespace().collect::<Vec<_>>();
+  let [_, day, month, year, time, "GMT"] = parts[..] else { return None };
+  let month = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"]
+    .iter()
+    .position(|m| *m == month)? as i64 + 1;
+  let (day, year) = (day.parse::<i64>().ok()?, year.parse::<i64>().ok()?);
+  let hms = time.split(':').map(|n| n.parse::<i64>().ok()).collect::<Option<Vec<_>>>()?;
+  let [h, m, s] = hms[..] else { return None };
+  // days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's days_from_civil)
+  let y = if month <= 2 { year - 1 } else { year };
+  let era = y.div_euclid(400);
+  let yoe = y - era * 400;
+  let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+  let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+  let at = (era * 146097 + doe - 719468) * 86400 + h * 3600 + m * 60 + s;
+  let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).ok()?.as_secs() as i64;
+  Some(Duration::from_secs((at - now).max(0) as u64))
+}
+
+/// Tauri managed state holding the shared client. The client is rebuilt only when
+/// the configured timeouts change, so connections are otherwise kept alive and reused.
+pub struct HttpState(Mutex<HttpClient>);
+
+impl Default for HttpState {
+  fn default() -> Self {
+    Self(Mutex::new(HttpClient::new(Timeouts::default())))
+  }
+}
+
+impl HttpState {
+  /// The client for these timeouts.
+  pub fn client(&self, timeouts: Timeouts) -> HttpClient {
+    let Ok(mut current) = self.0.lock() else { return HttpClient::new(timeouts) };
+    if current.timeouts != timeouts {
+      *current = HttpClient::new(timeouts);
+    }
+    current.clone()
+  }
+
+  /// The client as last configured, for calls that carry no `AppConfig`.
+  pub fn current(&self) -> HttpClient {
+    match self.0.lock() {
+      Ok(current) => current.clone(),
+      Err(_) => HttpClient::new(Timeouts::default()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn retry_after_seconds() {
+    assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+    assert_eq!(parse_retry_after(" 0 "), Some(Duration::ZERO));
+  }
+
+  #[test]
+  fn retry_after_date_in_the_past_is_now() {
+    assert_eq!(parse_retry_after("Sun, 06 Nov 1994 08:49:37 GMT"), Some(Duration::ZERO));
+  }
+
+  #[test]
+  fn retry_after_future_date() {
+    // 2099-01-01T00:00:00Z
+    let at = 4_070_908_800;
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    let wait = parse_retry_after("Thu, 01 Jan 2099 00:00:00 GMT").unwrap().as_secs();
+    assert!(wait.abs_diff(at - now) <= 2, "{} vs {}", wait, at - now);
+  }
+
+  #[test]
+  fn retry_after_rejects_garbage() {
+    assert_eq!(parse_retry_after(""), None);
+    assert_eq!(parse_retry_after("soon"), None);
+    assert_eq!(parse_retry_after("Thu, 01 Foo 2099 00:00:00 GMT"), None);
+    assert_eq!(parse_retry_after("Thu, 01 Jan 2099 00:00 GMT"), None);
+  }
+}
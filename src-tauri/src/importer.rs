@@ -0,0 +1,513 @@
+//! Imports chat history exported from ChatGPT (`conversations.json`) and Open WebUI
+//! (the JSON of "Export chats"). Both keep messages as a tree so that edits and
+//! regenerations can branch; only the branch that was on screen is imported.
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::OnceLock;
+
+use regex::Regex;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::conversations::{self, ChatMessage, Conversation};
+use crate::error::AppError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportSource {
+  Chatgpt,
+  OpenWebui,
+}
+
+impl ImportSource {
+  /// Prefix of the ids given to imported conversations, so importing the same
+  /// export twice finds the first copy and two sources never collide.
+  fn id_prefix(self) -> &'static str {
+    match self {
+      ImportSource::Chatgpt => "chatgpt",
+      ImportSource::OpenWebui => "openwebui",
+    }
+  }
+
+  /// Guesses the source from the first entry: ChatGPT entries have a `mapping`,
+  /// Open WebUI entries a `chat`.
+  fn detect(entries: &[Value]) -> Option<ImportSource> {
+    let first = entries.first()?;
+    if first.get("mapping").is_some() {
+      Some(ImportSource::Chatgpt)
+    } else if first.get("chat").is_some() || first.pointer("/history/messages").is_some() {
+      Some(ImportSource::OpenWebui)
+    } else {
+      None
+    }
+  }
+}
+
+/// An export entry that did not become a conversation.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Skipped {
+  pub id: Option<String>,
+  pub title: Option<String>,
+  pub reason: String,
+}
+
+/// Payload of `import_conversations`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+  pub source: ImportSource,
+  /// Ids of the new conversations.
+  pub imported: Vec<String>,
+  pub skipped: Vec<Skipped>,
+  /// Messages left out of imported conversations: system prompts, tool calls,
+  /// attachments without text.
+  pub skipped_messages: usize,
+}
+
+/// Parses the export in `text` and stores every conversation not imported before.
+pub fn import(conn: &mut Connection, text: &str, source: Option<ImportSource>) -> Result<ImportReport, AppError> {
+  let entries = match serde_json::from_str::<Value>(text)? {
+    Value::Array(entries) => entries,
+    // Open WebUI exports a single chat as an object
+    entry @ Value::Object(_) => vec![entry],
+    _ => return Err(AppError::invalid_config("the export is not a list of conversations")),
+  };
+  let source = source
+    .or_else(|| ImportSource::detect(&entries))
+    .ok_or_else(|| AppError::invalid_config("not a ChatGPT or Open WebUI export"))?;
+
+  let mut report = ImportReport { source, imported: Vec::new(), skipped: Vec::new(), skipped_messages: 0 };
+  let mut seen = HashSet::new();
+  let mut parsed = Vec::new();
+  for entry in &entries {
+    let result = match source {
+      ImportSource::Chatgpt => chatgpt(entry),
+      ImportSource::OpenWebui => open_webui(entry),
+    };
+    let title = str_at(entry, "/title").map(str::to_string);
+    match result {
+      Ok((c, left_out)) if seen.insert(c.id.clone()) => {
+        report.skipped_messages += left_out;
+        parsed.push(c);
+      }
+      Ok((c, _)) => report.skipped.push(Skipped { id: Some(c.id), title, reason: "duplicate id in the export".into() }),
+      Err(reason) => report.skipped.push(Skipped { id: entry_id(entry), title, reason }),
+    }
+  }
+
+  let titles: BTreeMap<String, String> = parsed.iter().map(|c| (c.id.clone(), c.title.clone())).collect();
+  let ids: Vec<String> = parsed.iter().map(|c| c.id.clone()).collect();
+  let existing: HashSet<String> = conversations::import(conn, parsed)?.into_iter().collect();
+  for id in ids {
+    if existing.contains(&id) {
+      let title = titles.get(&id).cloned();
+      report.skipped.push(Skipped { id: Some(id), title, reason: "already imported".into() });
+    } else {
+      report.imported.push(id);
+    }
+  }
+  Ok(report)
+}
+
+fn str_at<'a>(v: &'a Value, pointer: &str) -> Option<&'a str> {
+  v.pointer(pointer).and_then(Value::as_str).filter(|s| !s.is_empty())
+}
+
+fn entry_id(entry: &Value) -> Option<String> {
+  str_at(entry, "/conversation_id").or_else(|| str_at(entry, "/id")).map(str::to_string)
+}
+
+/// Unix milliseconds of an export timestamp. Both tools write seconds (ChatGPT as
+/// a float); values already in milliseconds are passed through.
+fn millis(v: Option<&Value>) -> i64 {
+  let Some(t) = v.and_then(Value::as_f64) else { return 0 };
+  if t < 1e11 {
+    (t * 1000.0) as i64
+  } else {
+    t as i64
+  }
+}
+
+/// Follows `parent` links from `leaf` to the root and returns the ids root first.
+/// Stops at a missing node or a cycle instead of looping.
+fn path_to(leaf: &str, parent: impl Fn(&str) -> Option<String>) -> Vec<String> {
+  let mut path = vec![leaf.to_string()];
+  let mut seen: HashSet<String> = path.iter().cloned().collect();
+  while let Some(p) = parent(path.last().map(String::as_str).unwrap_or_default()) {
+    if !seen.insert(p.clone()) {
+      break;
+    }
+    path.push(p);
+  }
+  path.reverse();
+  path
+}
+
+/// One ChatGPT conversation: `mapping` holds message nodes keyed by id, and
+/// `current_node` is the leaf of the branch that was shown. Returns the
+/// conversation and how many of its messages were left out.
+fn chatgpt(entry: &Value) -> Result<(Conversation, usize), String> {
+  let id = entry_id(entry).ok_or("no conversation id")?;
+  let mapping = entry.get("mapping").and_then(Value::as_object).ok_or("no message mapping")?;
+  // without current_node, the newest leaf is what ChatGPT would show
+  let leaf = str_at(entry, "/current_node").map(str::to_string).or_else(|| {
+    mapping
+      .iter()
+      .filter(|(_, node)| node.get("children").and_then(Value::as_array).is_none_or(|c| c.is_empty()))
+      .max_by_key(|(_, node)| millis(node.pointer("/message/create_time")))
+      .map(|(id, _)| id.clone())
+  });
+  let leaf = leaf.ok_or("no messages")?;
+  let path = path_to(&leaf, |id| mapping.get(id).and_then(|n| str_at(n, "/parent")).map(str::to_string));
+
+  let mut messages = Vec::new();
+  let mut left_out = 0;
+  // reasoning arrives as its own "thoughts" message before the answer it belongs to
+  let mut thinking = String::new();
+  let mut model = String::new();
+  for node in path.iter().filter_map(|id| mapping.get(id)) {
+    let Some(m) = node.get("message").filter(|m| !m.is_null()) else { continue };
+    let role = str_at(m, "/author/role").unwrap_or_default();
+    let content_type = str_at(m, "/content/content_type").unwrap_or_default();
+    let hidden = m.pointer("/metadata/is_visually_hidden_from_conversation").and_then(Value::as_bool) == Some(true);
+    if content_type == "thoughts" {
+      for t in m.pointer("/content/thoughts").and_then(Value::as_array).into_iter().flatten() {
+        let part = [str_at(t, "/summary"), str_at(t, "/content")].into_iter().flatten().collect::<Vec<_>>().join("\n\n");
+        push_paragraph(&mut thinking, &part);
+      }
+      continue;
+    }
+    // tool calls are addressed to the tool, answers to "all"
+    let to_tool = str_at(m, "/recipient").is_some_and(|r| r != "all");
+    if hidden || to_tool || !matches!(role, "user" | "assistant") || !matches!(content_type, "text" | "multimodal_text") {
+      // every export starts with a hidden, empty system message; not worth reporting
+      if !(hidden && role == "system") {
+        left_out += 1;
+      }
+      continue;
+    }
+    let text = m
+      .pointer("/content/parts")
+      .and_then(Value::as_array)
+      .into_iter()
+      .flatten()
+      .filter_map(Value::as_str)
+      .collect::<Vec<_>>()
+      .join("\n");
+    if text.trim().is_empty() {
+      left_out += 1;
+      continue;
+    }
+    let slug = str_at(m, "/metadata/model_slug").map(str::to_string);
+    if role == "assistant" {
+      if let Some(slug) = &slug {
+        model = slug.clone();
+      }
+    }
+    messages.push(ChatMessage {
+      role: role.to_string(),
+      content: text,
+      thinking: if role == "assistant" { std::mem::take(&mut thinking) } else { String::new() },
+      created_at: millis(m.get("create_time")),
+      model: slug.filter(|_| role == "assistant"),
+      provider: Some("openai".to_string()).filter(|_| role == "assistant"),
+      ..Default::default()
+    });
+  }
+  if messages.is_empty() {
+    return Err("no user or assistant messages".into());
+  }
+  Ok((
+    Conversation {
+      id: format!("{}-{}", ImportSource::Chatgpt.id_prefix(), id),
+      title: str_at(entry, "/title").unwrap_or("ChatGPT").to_string(),
+      model,
+      provider: "openai".into(),
+      created_at: millis(entry.get("create_time")),
+      updated_at: millis(entry.get("update_time")),
+      messages,
+      metadata: BTreeMap::from([("importedFrom".to_string(), "chatgpt".to_string())]),
+    },
+    left_out,
+  ))
+}
+
+/// One Open WebUI chat. `chat.history` holds messages keyed by id with `parentId`
+/// links and `currentId` as the shown leaf; older exports only have the flat
+/// `chat.messages`.
+fn open_webui(entry: &Value) -> Result<(Conversation, usize), String> {
+  let chat = entry.get("chat").unwrap_or(entry);
+  let id = str_at(entry, "/id").or_else(|| str_at(chat, "/id")).ok_or("no chat id")?;
+  let history = chat.pointer("/history/messages").and_then(Value::as_object);
+  let nodes: Vec<&Value> = match (history, str_at(chat, "/history/currentId")) {
+    (Some(history), Some(leaf)) => {
+      path_to(leaf, |id| history.get(id).and_then(|m| str_at(m, "/parentId")).map(str::to_string))
+        .iter()
+        .filter_map(|id| history.get(id))
+        .collect()
+    }
+    _ => chat.get("messages").and_then(Value::as_array).map(|m| m.iter().collect()).ok_or("no messages")?,
+  };
+
+  let mut messages = Vec::new();
+  let mut left_out = 0;
+  for m in nodes {
+    let role = str_at(m, "/role").unwrap_or_default();
+    let content = str_at(m, "/content").unwrap_or_default();
+    if !matches!(role, "user" | "assistant") || content.trim().is_empty() {
+      left_out += 1;
+      continue;
+    }
+    let (content, thinking) = if role == "assistant" { split_reasoning(content) } else { (content.to_string(), String::new()) };
+    let model = str_at(m, "/model").map(str::to_string).filter(|_| role == "assistant");
+    messages.push(ChatMessage {
+      role: role.to_string(),
+      content,
+      thinking,
+      created_at: millis(m.get("timestamp")),
+      provider: model.as_deref().map(|m| guess_provider(m).to_string()),
+      model,
+      ..Default::default()
+    });
+  }
+  if messages.is_empty() {
+    return Err("no user or assistant messages".into());
+  }
+  let model = chat
+    .pointer("/models/0")
+    .and_then(Value::as_str)
+    .map(str::to_string)
+    .or_else(|| messages.iter().rev().find_map(|m| m.model.clone()))
+    .unwrap_or_default();
+  Ok((
+    Conversation {
+      id: format!("{}-{}", ImportSource::OpenWebui.id_prefix(), id),
+      title: str_at(entry, "/title").or_else(|| str_at(chat, "/title")).unwrap_or("Open WebUI").to_string(),
+      provider: guess_provider(&model).to_string(),
+      model,
+      created_at: millis(entry.get("created_at").or_else(|| chat.get("timestamp"))),
+      updated_at: millis(entry.get("updated_at")),
+      messages,
+      metadata: BTreeMap::from([("importedFrom".to_string(), "openwebui".to_string())]),
+    },
+    left_out,
+  ))
+}
+
+/// Open WebUI serves Ollama models (`llama3:8b`, always tagged) next to
+/// OpenAI-compatible ones; the tag is the only hint the export carries.
+fn guess_provider(model: &str) -> &'static str {
+  if model.contains(':') {
+    "ollama"
+  } else {
+    "openai"
+  }
+}
+
+/// Newer Open WebUI versions keep reasoning inside the answer as
+/// `<details type="reasoning">` blocks quoted with `> `; they become `thinking`.
+fn split_reasoning(content: &str) -> (String, String) {
+  static DETAILS: OnceLock<Regex> = OnceLock::new();
+  let re = DETAILS.get_or_init(|| {
+    Regex::new(r#"(?s)<details type="reasoning"[^>]*>\s*(?:<summary>.*?</summary>)?(.*?)</details>\s*"#).expect("reasoning pattern")
+  });
+  let mut thinking = String::new();
+  for caps in re.captures_iter(content) {
+    let text = caps[1].lines().map(|l| l.strip_prefix("> ").or_else(|| l.strip_prefix('>')).unwrap_or(l)).collect::<Vec<_>>().join("\n");
+    push_paragraph(&mut thinking, text.trim());
+  }
+  (re.replace_all(content, "").trim().to_string(), thinking)
+}
+
+fn push_paragraph(out: &mut String, text: &str) {
+  if text.is_empty() {
+    return;
+  }
+  if !out.is_empty() {
+    out.push_str("\n\n");
+  }
+  out.push_str(text);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// A ChatGPT export entry: `nodes` are `(id, parent, message)`, children are derived.
+  fn chatgpt_entry(current: Option<&str>, update_time: Value, nodes: &[(&str, Option<&str>, Value)]) -> Value {
+    let mut mapping = serde_json::Map::new();
+    for (id, parent, message) in nodes {
+      let children: Vec<&str> = nodes.iter().filter(|(_, p, _)| p == &Some(*id)).map(|(c, _, _)| *c).collect();
+      mapping.insert(id.to_string(), serde_json::json!({ "id": id, "parent": parent, "children": children, "message": message }));
+    }
+    serde_json::json!({
+      "title": "Trip planning",
+      "conversation_id": "abc",
+      "create_time": 1700000000.25,
+      "update_time": update_time,
+      "current_node": current,
+      "mapping": mapping,
+    })
+  }
+
+  fn text(role: &str, text: &str, at: f64) -> Value {
+    serde_json::json!({
+      "author": { "role": role },
+      "content": { "content_type": "text", "parts": [text] },
+      "create_time": at,
+      "metadata": { "model_slug": "gpt-4o" },
+    })
+  }
+
+  fn tree() -> Vec<(&'static str, Option<&'static str>, Value)> {
+    let hidden_system = serde_json::json!({
+      "author": { "role": "system" },
+      "content": { "content_type": "text", "parts": [""] },
+      "metadata": { "is_visually_hidden_from_conversation": true },
+    });
+    let thoughts = serde_json::json!({
+      "author": { "role": "assistant" },
+      "content": { "content_type": "thoughts", "thoughts": [{ "summary": "Weather", "content": "Check the season." }] },
+    });
+    let tool_call = serde_json::json!({
+      "author": { "role": "assistant" },
+      "recipient": "browser",
+      "content": { "content_type": "code", "text": "search(\"weather\")" },
+    });
+    vec![
+      ("root", None, Value::Null),
+      ("sys", Some("root"), hidden_system),
+      ("u1", Some("sys"), text("user", "Where should I go in May?", 1700000001.0)),
+      ("think", Some("u1"), thoughts),
+      ("tool", Some("think"), tool_call),
+      ("a1", Some("tool"), text("assistant", "Try Lisbon.", 1700000002.0)),
+      ("a2", Some("u1"), text("assistant", "Try Kyoto.", 1700000009.0)),
+    ]
+  }
+
+  #[test]
+  fn chatgpt_imports_the_current_branch() {
+    let (c, left_out) = chatgpt(&chatgpt_entry(Some("a1"), serde_json::json!(1700000003.0), &tree())).unwrap();
+    assert_eq!(c.id, "chatgpt-abc");
+    assert_eq!(c.title, "Trip planning");
+    assert_eq!(c.messages.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), ["Where should I go in May?", "Try Lisbon."]);
+    assert_eq!(c.messages[1].thinking, "Weather\n\nCheck the season.");
+    assert_eq!((c.messages[0].model.as_deref(), c.messages[1].model.as_deref()), (None, Some("gpt-4o")));
+    assert_eq!(c.messages[1].provider.as_deref(), Some("openai"));
+    assert_eq!((c.model.as_str(), c.created_at, c.updated_at), ("gpt-4o", 1_700_000_000_250, 1_700_000_003_000));
+    assert_eq!(c.messages[0].created_at, 1_700_000_001_000);
+    // the tool call; the hidden system prompt is not counted
+    assert_eq!(left_out, 1);
+    assert_eq!(c.metadata.get("importedFrom").map(String::as_str), Some("chatgpt"));
+  }
+
+  #[test]
+  fn chatgpt_without_current_node_takes_the_newest_leaf() {
+    let (c, _) = chatgpt(&chatgpt_entry(None, Value::Null, &tree())).unwrap();
+    assert_eq!(c.messages.last().map(|m| m.content.as_str()), Some("Try Kyoto."));
+    assert!(c.messages[1].thinking.is_empty());
+  }
+
+  #[test]
+  fn chatgpt_survives_a_parent_cycle() {
+    let nodes = [("x", Some("y"), text("user", "x", 1.0)), ("y", Some("x"), text("assistant", "y", 2.0))];
+    let (c, _) = chatgpt(&chatgpt_entry(Some("y"), Value::Null, &nodes)).unwrap();
+    assert_eq!(c.messages.len(), 2);
+    assert_eq!(chatgpt(&serde_json::json!({ "conversation_id": "e", "mapping": {} })).unwrap_err(), "no messages");
+  }
+
+  #[test]
+  fn open_webui_imports_the_current_branch() {
+    let entry = serde_json::json!({
+      "id": "w1",
+      "title": "Rust help",
+      "created_at": 1700000000,
+      "updated_at": 1700000100,
+      "chat": {
+        "models": ["llama3:8b"],
+        "history": {
+          "currentId": "m3",
+          "messages": {
+            "m0": { "id": "m0", "parentId": null, "role": "system", "content": "Be brief." },
+            "m1": { "id": "m1", "parentId": "m0", "role": "user", "content": "What is a borrow?", "timestamp": 1700000001 },
+            "m2": { "id": "m2", "parentId": "m1", "role": "assistant", "content": "Old answer", "model": "llama3:8b" },
+            "m3": { "id": "m3", "parentId": "m1", "role": "assistant", "model": "llama3:8b", "timestamp": 1700000002,
+                    "content": "<details type=\"reasoning\" done=\"true\">\n<summary>Thought for 2 seconds</summary>\n> Define it.\n> Keep it short.\n</details>\nA reference." }
+          }
+        }
+      }
+    });
+    let (c, left_out) = open_webui(&entry).unwrap();
+    assert_eq!(c.id, "openwebui-w1");
+    assert_eq!(c.messages.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), ["What is a borrow?", "A reference."]);
+    assert_eq!(c.messages[1].thinking, "Define it.\nKeep it short.");
+    assert_eq!((c.messages[1].model.as_deref(), c.messages[1].provider.as_deref()), (Some("llama3:8b"), Some("ollama")));
+    assert_eq!((c.model.as_str(), c.provider.as_str()), ("llama3:8b", "ollama"));
+    assert_eq!((c.created_at, c.updated_at, c.messages[0].created_at), (1_700_000_000_000, 1_700_000_100_000, 1_700_000_001_000));
+    assert_eq!(left_out, 1);
+  }
+
+  #[test]
+  fn open_webui_reads_flat_messages() {
+    let entry = serde_json::json!({ "id": "w2", "chat": { "messages": [
+      { "role": "user", "content": "hi" },
+      { "role": "assistant", "content": "hello", "model": "gpt-4o-mini" },
+      { "role": "assistant", "content": "  " }
+    ] } });
+    let (c, left_out) = open_webui(&entry).unwrap();
+    assert_eq!(c.messages.len(), 2);
+    assert_eq!((c.title.as_str(), c.model.as_str(), c.provider.as_str()), ("Open WebUI", "gpt-4o-mini", "openai"));
+    assert_eq!(left_out, 1);
+  }
+
+  #[test]
+  fn split_reasoning_moves_details_blocks() {
+    let content = "<details type=\"reasoning\">\n<summary>Thinking</summary>\n> first\n>second\n</details>\nAnswer\n<details type=\"reasoning\">\n> more\n</details>";
+    assert_eq!(split_reasoning(content), ("Answer".to_string(), "first\nsecond\n\nmore".to_string()));
+    assert_eq!(split_reasoning("plain <details>kept</details>"), ("plain <details>kept</details>".to_string(), String::new()));
+    assert_eq!(split_reasoning(""), (String::new(), String::new()));
+  }
+
+  #[test]
+  fn millis_reads_seconds_and_milliseconds() {
+    assert_eq!(millis(Some(&serde_json::json!(1700000000.5))), 1_700_000_000_500);
+    assert_eq!(millis(Some(&serde_json::json!(1700000000))), 1_700_000_000_000);
+    assert_eq!(millis(Some(&serde_json::json!(1700000000000_i64))), 1_700_000_000_000);
+    assert_eq!(millis(Some(&serde_json::json!(0))), 0);
+    assert_eq!(millis(Some(&Value::Null)), 0);
+    assert_eq!(millis(Some(&serde_json::json!("2024-01-01"))), 0);
+    assert_eq!(millis(None), 0);
+  }
+
+  #[test]
+  fn import_without_update_time_uses_the_newest_message() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conversations::init(&mut conn).unwrap();
+    let mut zero = chatgpt_entry(Some("a1"), serde_json::json!(0), &tree());
+    zero["conversation_id"] = "zero".into();
+    let mut absent = chatgpt_entry(Some("a2"), Value::Null, &tree());
+    absent["conversation_id"] = "absent".into();
+    let export = serde_json::json!([zero, absent]).to_string();
+
+    let report = import(&mut conn, &export, None).unwrap();
+    assert_eq!(report.source, ImportSource::Chatgpt);
+    assert_eq!(report.imported, ["chatgpt-zero", "chatgpt-absent"]);
+    let updated = |id: &str| conversations::get(&conn, id).unwrap().updated_at;
+    assert_eq!(updated("chatgpt-zero"), 1_700_000_002_000);
+    assert_eq!(updated("chatgpt-absent"), 1_700_000_009_000);
+
+    let again = import(&mut conn, &export, None).unwrap();
+    assert!(again.imported.is_empty());
+    assert_eq!(again.skipped.iter().map(|s| s.reason.as_str()).collect::<Vec<_>>(), ["already imported", "already imported"]);
+  }
+
+  #[test]
+  fn import_rejects_unknown_exports() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conversations::init(&mut conn).unwrap();
+    assert!(import(&mut conn, r#"[{"foo": 1}]"#, None).is_err());
+    assert!(import(&mut conn, "42", None).is_err());
+  }
+}
@@ -0,0 +1,327 @@
+//! Structured application log: one JSON object per line in `logs/app.log`, written
+//! by a single background thread and rotated by size.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::Write as _;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::redact::{redact, redact_fields};
+
+/// `app.log` is rotated once it would grow past this.
+const MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+/// Rotated files kept next to `app.log` (`app.1.log` is the newest).
+const KEEP_ROTATED: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Level {
+  Debug,
+  Info,
+  Warn,
+  Error,
+}
+
+impl Level {
+  /// Parses the level names the frontend logger uses.
+  pub fn parse(s: &str) -> Option<Level> {
+    match s.trim().to_ascii_uppercase().as_str() {
+      "DEBUG" => Some(Level::Debug),
+      "INFO" => Some(Level::Info),
+      "WARN" | "WARNING" => Some(Level::Warn),
+      "ERROR" => Some(Level::Error),
+      _ => None,
+    }
+  }
+}
+
+/// One line of `app.log`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+  /// UTC, `2024-05-01T12:00:00.000Z`; sorts lexicographically.
+  pub ts: String,
+  pub level: Level,
+  /// `chat`, `model-pull`, `model-check`, `frontend`, ...
+  pub component: String,
+  /// Stream or pull id the entry belongs to.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub id: Option<String>,
+  pub message: String,
+  #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+  pub fields: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Filter for [`query`] and [`subscribe`]; unset fields match everything.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LogQuery {
+  /// Minimum level.
+  pub level: Option<Level>,
+  pub component: Option<String>,
+  pub id: Option<String>,
+  /// Inclusive bounds in the format of [`LogEntry::ts`].
+  pub since: Option<String>,
+  pub until: Option<String>,
+  /// Newest matches to return from [`query`]; defaults to [`DEFAULT_QUERY_LIMIT`].
+  pub limit: Option<usize>,
+}
+
+pub const DEFAULT_QUERY_LIMIT: usize = 1000;
+
+impl LogQuery {
+  pub fn matches(&self, entry: &LogEntry) -> bool {
+    self.level.is_none_or(|level| entry.level >= level)
+      && self.component.as_ref().is_none_or(|c| *c == entry.component)
+      && self.id.as_ref().is_none_or(|id| entry.id.as_ref() == Some(id))
+      && self.since.as_ref().is_none_or(|since| entry.ts >= *since)
+      && self.until.as_ref().is_none_or(|until| entry.ts <= *until)
+  }
+}
+
+/// `logs/` next to the executable.
+pub fn log_dir() -> Option<PathBuf> {
+  Some(std::env::current_exe().ok()?.parent()?.join("logs"))
+}
+
+/// Queues an entry for the writer thread; never blocks on disk.
+pub fn log(level: Level, component: &str, id: Option<&str>, message: impl Into<String>, fields: serde_json::Value) {
+  let fields = match fields {
+    serde_json::Value::Object(map) => map,
+    serde_json::Value::Null => serde_json::Map::new(),
+    other => serde_json::Map::from_iter([("value".to_string(), other)]),
+  };
+  write(LogEntry {
+    ts: now(),
+    level,
+    component: component.to_string(),
+    id: id.map(|s| s.to_string()),
+    message: message.into(),
+    fields,
+  });
+}
+
+pub fn write(entry: LogEntry) {
+  static WRITER: OnceLock<Option<Sender<LogEntry>>> = OnceLock::new();
+  let writer = WRITER.get_or_init(|| {
+    let (tx, rx) = channel();
+    std::thread::Builder::new()
+      .name("log-writer".to_string())
+      .spawn(move || run_writer(rx))
+      .ok()
+      .map(|_| tx)
+  });
+  if let Some(tx) = writer {
+    let _ = tx.send(entry);
+  }
+}
+
+/// Keeps `app.log` open for the life of the app and appends each entry, redacted.
+fn run_writer(rx: Receiver<LogEntry>) {
+  let Some(dir) = log_dir() else { return };
+  let path = dir.join("app.log");
+  let mut file: Option<(File, u64)> = None;
+  for mut entry in rx {
+    // redact the values, not the serialized line: masking text that spans JSON
+    // syntax could leave a line that no longer parses
+    entry.message = redact(&entry.message);
+    redact_fields(&mut entry.fields);
+    let Ok(line) = serde_json::to_string(&entry) else { continue };
+    if let Some((_, size)) = &file {
+      if size + line.len() as u64 + 1 > MAX_FILE_BYTES {
+        file = None;
+        rotate(&dir);
+      }
+    }
+    if file.is_none() {
+      let _ = std::fs::create_dir_all(&dir);
+      file = OpenOptions::new().create(true).append(true).open(&path).ok().map(|f| {
+        let size = f.metadata().map(|m| m.len()).unwrap_or(0);
+        (f, size)
+      });
+    }
+    if let Some((f, size)) = &mut file {
+      if writeln!(f, "{}", line).is_ok() {
+        *size += line.len() as u64 + 1;
+      }
+    }
+    notify(&entry);
+  }
+}
+
+type Subscriber = Box<dyn Fn(&LogEntry) + Send>;
+
+fn subscribers() -> &'static Mutex<HashMap<String, Subscriber>> {
+  static SUBSCRIBERS: OnceLock<Mutex<HashMap<String, Subscriber>>> = OnceLock::new();
+  SUBSCRIBERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Calls `on_entry` on the writer thread with every entry written from now on,
+/// already redacted, until [`unsubscribe`] is called with `id`.
+pub fn subscribe(id: String, on_entry: impl Fn(&LogEntry) + Send + 'static) {
+  if let Ok(mut subs) = subscribers().lock() {
+    subs.insert(id, Box::new(on_entry));
+  }
+}
+
+pub fn unsubscribe(id: &str) -> bool {
+  subscribers().lock().map(|mut subs| subs.remove(id).is_some()).unwrap_or(false)
+}
+
+/// Hands a redacted entry to the subscribers.
+fn notify(entry: &LogEntry) {
+  let Ok(subs) = subscribers().lock() else { return };
+  for on_entry in subs.values() {
+    on_entry(entry);
+  }
+}
+
+/// `app.{KEEP_ROTATED}.log` ... `app.1.log`, `app.log`: every log file that exists, oldest first.
+fn log_files(dir: &std::path::Path) -> Vec<PathBuf> {
+  (1..=KEEP_ROTATED)
+    .rev()
+    .map(|n| dir.join(format!("app.{}.log", n)))
+    .chain(std::iter::once(dir.join("app.log")))
+    .filter(|p| p.is_file())
+    .collect()
+}
+
+/// The newest entries on disk matching `query`, oldest first. Lines that are not
+/// structured entries (written before logs were JSON) are skipped.
+pub fn query(query: &LogQuery) -> std::io::Result<Vec<LogEntry>> {
+  let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT);
+  let mut found = VecDeque::with_capacity(limit.min(DEFAULT_QUERY_LIMIT));
+  let Some(dir) = log_dir() else { return Ok(Vec::new()) };
+  for path in log_files(&dir) {
+    let bytes = match std::fs::read(&path) {
+      Ok(bytes) => bytes,
+      // rotated away between listing and reading
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+      Err(e) => return Err(e),
+    };
+    for line in String::from_utf8_lossy(&bytes).lines() {
+      let Ok(entry) = serde_json::from_str::<LogEntry>(line) else { continue };
+      if !query.matches(&entry) {
+        continue;
+      }
+      if found.len() == limit {
+        found.pop_front();
+      }
+      if limit > 0 {
+        found.push_back(entry);
+      }
+    }
+  }
+  Ok(found.into())
+}
+
+/// `app.log` -> `app.1.log` -> ... -> `app.{KEEP_ROTATED}.log`, dropping the oldest.
+fn rotate(dir: &std::path::Path) {
+  let rotated = |n: usize| dir.join(format!("app.{}.log", n));
+  let _ = std::fs::remove_file(rotated(KEEP_ROTATED));
+  for n in (1..KEEP_ROTATED).rev() {
+    let _ = std::fs::rename(rotated(n), rotated(n + 1));
+  }
+  let _ = std::fs::rename(dir.join("app.log"), rotated(1));
+}
+
+pub fn now() -> String {
+  let ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0);
+  format_ts(ms)
+}
+
+/// Unix milliseconds as `YYYY-MM-DDTHH:MM:SS.mmmZ`, the format of JavaScript's `toISOString`.
+pub fn format_ts(ms: i64) -> String {
+  let (days, rem) = (ms.div_euclid(86_400_000), ms.rem_euclid(86_400_000));
+  // civil_from_days (Howard Hinnant)
+  let z = days + 719_468;
+  let era = z.div_euclid(146_097);
+  let doe = z - era * 146_097;
+  let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+  format!(
+    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+    year,
+    month,
+    day,
+    rem / 3_600_000,
+    rem / 60_000 % 60,
+    rem / 1000 % 60,
+    rem % 1000
+  )
+}
+
+/// Reads a line written by the frontend logger: `[ts] [LEVEL] message {json}`.
+pub fn parse_frontend_line(line: &str) -> LogEntry {
+  let mut rest = line.trim();
+  let mut bracket = || -> Option<String> {
+    let inner = rest.strip_prefix('[')?;
+    let end = inner.find(']')?;
+    let value = inner[..end].to_string();
+    rest = inner[end + 1..].trim_start();
+    Some(value)
+  };
+  let ts = bracket().filter(|t| t.len() == 24 && t.ends_with('Z'));
+  let level = ts.as_ref().and(bracket()).and_then(|l| Level::parse(&l));
+  let (Some(ts), Some(level)) = (ts, level) else {
+    return LogEntry {
+      ts: now(),
+      level: Level::Info,
+      component: "frontend".to_string(),
+      id: None,
+      message: line.to_string(),
+      fields: serde_json::Map::new(),
+    };
+  };
+  // messages may contain spaces; the data is the first JSON object, array or string that
+  // runs to the end of the line
+  let data = rest
+    .match_indices([' '])
+    .map(|(i, _)| i)
+    .filter(|i| rest[i + 1..].starts_with(['{', '[', '"']))
+    .find_map(|i| serde_json::from_str::<serde_json::Value>(&rest[i + 1..]).ok().map(|v| (i, v)));
+  let (message, fields) = match data {
+    Some((i, serde_json::Value::Object(map))) => (rest[..i].to_string(), map),
+    Some((i, other)) => (rest[..i].to_string(), serde_json::Map::from_iter([("value".to_string(), other)])),
+    None => (rest.to_string(), serde_json::Map::new()),
+  };
+  LogEntry { ts, level, component: "frontend".to_string(), id: None, message, fields }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn redacted_entries_still_parse() {
+    let id = format!("test-{}", now());
+    log(Level::Info, "chat", Some(&id), "stream start", serde_json::json!({
+      "hasApiKey": true,
+      "apiKeyLength": 51,
+      "apiKey": "not-a-pattern-but-a-key",
+      "url": "https://example.com/v1?key=AIzaSyA1234567890abcdefghij",
+    }));
+    let query = LogQuery { id: Some(id), ..LogQuery::default() };
+    // the writer thread appends in the background
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    let entry = loop {
+      if let Some(entry) = self::query(&query).unwrap().pop() {
+        break entry;
+      }
+      assert!(std::time::Instant::now() < deadline, "entry never showed up in the log");
+      std::thread::sleep(std::time::Duration::from_millis(20));
+    };
+    assert_eq!(entry.fields["hasApiKey"], serde_json::json!(true));
+    assert_eq!(entry.fields["apiKeyLength"], serde_json::json!(51));
+    assert_eq!(entry.fields["apiKey"], serde_json::json!("[REDACTED]"));
+    assert!(!entry.fields["url"].as_str().unwrap().contains("AIza"));
+  }
+}
@@ -1,281 +1,258 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use anyhow::Result;
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 // no prelude import; use fully-qualified tauri paths to avoid unused import warnings
-use std::io::Write as _;
 use tauri::Manager;
 use tauri::Window;
 use tauri::Emitter;
-use std::sync::{OnceLock, atomic::{AtomicBool, Ordering}};
-use futures_util::StreamExt;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, OnceLock, atomic::{AtomicBool, AtomicU64, Ordering}};
+
+mod context;
+mod conversations;
+mod error;
+mod export;
+mod http;
+mod importer;
+mod logger;
+mod profiles;
+mod providers;
+mod redact;
+mod retry;
+mod search;
+mod secrets;
+mod settings;
+
+use conversations::{ChatMessage, Conversation, ConversationDb, ConversationPatch, ConversationSummary};
+use error::AppError;
+use http::HttpState;
+use logger::{Level, LogEntry, LogQuery};
+use providers::{ChatRequest, Delta};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ModelConfig {
   pub name: String,
   pub provider: String,
+  /// Empty means the server of the top-level config.
+  #[serde(default)]
   pub base_url: String,
+  #[serde(default)]
   pub api_key: Option<String>,
+  /// Azure OpenAI deployment name; defaults to the model name.
+  #[serde(default)]
+  pub deployment: Option<String>,
+  /// Azure OpenAI `api-version` query parameter.
+  #[serde(default)]
+  pub api_version: Option<String>,
+  /// Models (by `name`) to try in order when this one cannot answer.
+  #[serde(default)]
+  pub fallbacks: Option<Vec<String>>,
 }
 
+/// `settings.json`; fields missing from the file take the values of [`AppConfig::default`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", default)]
 pub struct AppConfig {
+  /// Schema version, see [`settings::CURRENT_VERSION`].
+  pub version: u32,
   pub provider: String,
   pub base_url: String,
   pub api_key: Option<String>,
   pub model: Option<String>,
-  #[serde(default)]
+  pub deployment: Option<String>,
+  pub api_version: Option<String>,
   pub ollama_path: Option<String>,
-  #[serde(default)]
   pub models: Option<Vec<ModelConfig>>,
-  #[serde(default)]
   pub streaming_enabled: Option<bool>,
-  #[serde(default)]
   pub default_think: Option<bool>,
-  #[serde(default)]
-  pub max_context_messages: Option<u32>,
-  #[serde(default)]
+  /// Context window to fit history into, in tokens; unset means the model's own.
+  pub context_tokens: Option<u32>,
   pub temperature: Option<f64>,
+  pub timeouts: http::Timeouts,
+  pub retry: retry::RetryPolicy,
+  /// Write chat messages to the log verbatim; otherwise only their length is logged.
+  pub log_prompts: bool,
+  /// UI language, `zh-CN` or `en`.
+  pub language: Option<String>,
+  pub mcp_servers: Vec<settings::McpServerConfig>,
+  /// What each MCP server reported about itself (name, version, tools), by server id.
+  pub mcp_server_infos: BTreeMap<String, serde_json::Value>,
+  pub mcp_max_retries: Option<u32>,
+  pub mcp_reflection_enabled: Option<bool>,
+  /// Saved connection settings; the top-level ones belong to `active_profile`.
+  pub profiles: Vec<profiles::Profile>,
+  pub active_profile: Option<String>,
 }
 
-#[tauri::command]
-async fn proxy_models(config: AppConfig) -> Result<String, String> {
-  let client = Client::new();
-  let result = if config.provider == "ollama" {
-    let url = format!("{}/api/tags", config.base_url.trim_end_matches('/'));
-    let resp = client.get(url).send().await.map_err(|e| e.to_string())?;
-    let v: serde_json::Value = resp.json().await.map_err(|e| e.to_string())?;
-    let list = v.get("models").and_then(|m| m.as_array()).map(|arr| {
-      arr.iter()
-        .filter_map(|m| m.get("name").and_then(|n| n.as_str()).map(|s| s.to_string()))
-        .collect::<Vec<String>>()
-    }).unwrap_or_default();
-    serde_json::to_string(&list).map_err(|e| e.to_string())?
-  } else {
-    let url = format!("{}/v1/models", config.base_url.trim_end_matches('/'));
-    let mut req = client.get(url);
-    if let Some(k) = config.api_key {
-      req = req.bearer_auth(k);
+impl Default for AppConfig {
+  fn default() -> Self {
+    let mut config = Self {
+      version: settings::CURRENT_VERSION,
+      provider: "ollama".to_string(),
+      base_url: "http://localhost:11434".to_string(),
+      api_key: None,
+      model: Some("gpt-oss:20b".to_string()),
+      deployment: None,
+      api_version: None,
+      ollama_path: None,
+      models: Some(vec![ModelConfig {
+        name: "qwen3:0.6b".to_string(),
+        provider: "ollama".to_string(),
+        base_url: "http://localhost:11434".to_string(),
+        api_key: None,
+        deployment: None,
+        api_version: None,
+        fallbacks: None,
+      }]),
+      streaming_enabled: Some(true),
+      default_think: Some(true),
+      context_tokens: None,
+      temperature: Some(0.6),
+      timeouts: http::Timeouts::default(),
+      retry: retry::RetryPolicy::default(),
+      log_prompts: false,
+      language: Some("zh-CN".to_string()),
+      mcp_servers: Vec::new(),
+      mcp_server_infos: BTreeMap::new(),
+      mcp_max_retries: Some(3),
+      mcp_reflection_enabled: Some(true),
+      profiles: Vec::new(),
+      active_profile: Some(profiles::DEFAULT_PROFILE.to_string()),
+    };
+    config.profiles.push(profiles::Profile::from_config(profiles::DEFAULT_PROFILE, &config));
+    config
+  }
+}
+
+impl AppConfig {
+  /// The entry of `models` with this name, if any.
+  fn model_entry(&self, name: &str) -> Option<&ModelConfig> {
+    self.models.iter().flatten().find(|m| m.name == name)
+  }
+
+  /// Replaces `secret:` references in the API keys with the stored keys.
+  fn resolve_secrets(&mut self) -> Result<(), AppError> {
+    let keys = std::iter::once(&mut self.api_key).chain(self.models.iter_mut().flatten().map(|m| &mut m.api_key));
+    for key in keys.flatten() {
+      if secrets::reference_id(key).is_some() {
+        *key = secrets::resolve(key)?;
+      }
     }
-    let resp = req.send().await.map_err(|e| e.to_string())?;
-    let v: serde_json::Value = resp.json().await.map_err(|e| e.to_string())?;
-    let list = v.get("data").and_then(|m| m.as_array()).map(|arr| {
-      arr.iter()
-        .filter_map(|m| m.get("id").and_then(|n| n.as_str()).map(|s| s.to_string()))
-        .collect::<Vec<String>>()
-    }).unwrap_or_default();
-    serde_json::to_string(&list).map_err(|e| e.to_string())?
-  };
-  Ok(result)
+    Ok(())
+  }
+
+  /// This config pointed at the server that hosts `name`. Names missing from
+  /// `models` are assumed to live on the current server.
+  fn for_model(&self, name: &str) -> AppConfig {
+    let Some(m) = self.model_entry(name) else { return self.clone() };
+    AppConfig {
+      provider: m.provider.clone(),
+      base_url: if m.base_url.is_empty() { self.base_url.clone() } else { m.base_url.clone() },
+      api_key: m.api_key.clone().filter(|k| !k.is_empty()).or_else(|| self.api_key.clone()),
+      deployment: m.deployment.clone(),
+      api_version: m.api_version.clone(),
+      ..self.clone()
+    }
+  }
+}
+
+#[tauri::command]
+async fn proxy_models(http: tauri::State<'_, HttpState>, mut config: AppConfig) -> Result<String, AppError> {
+  config.resolve_secrets()?;
+  redact::remember(&config);
+  let client = http.client(config.timeouts);
+  let provider = providers::get(&config.provider);
+  let list = config.retry
+    .run(|| provider.list_models(&client, &config), |_| {})
+    .await
+    .map_err(|e| AppError::provider(&e, &config.provider))?;
+  Ok(serde_json::to_string(&list)?)
 }
 
 #[tauri::command]
-async fn proxy_chat_stream(body: String) -> Result<String, String> {
+async fn proxy_chat_stream(body: String) -> Result<String, AppError> {
   // In this MVP, return the body handle string back; real-time streaming can be added with events
   Ok(body)
 }
 
 #[tauri::command]
-async fn proxy_chat(handle: String) -> Result<String, String> {
+async fn proxy_chat(
+  app: tauri::AppHandle,
+  http: tauri::State<'_, HttpState>,
+  db: tauri::State<'_, ConversationDb>,
+  handle: String,
+) -> Result<String, AppError> {
   #[derive(Deserialize)]
-  struct InBody { config: AppConfig, messages: Vec<Message>, model: String, #[serde(default)] think: bool }
-  let parsed: InBody = serde_json::from_str(&handle).map_err(|e| e.to_string())?;
-  chat_once(parsed.config, parsed.messages, parsed.model, parsed.think).await.map_err(|e| e.to_string())
+  #[serde(rename_all = "camelCase")]
+  struct InBody {
+    config: AppConfig,
+    #[serde(default)]
+    messages: Vec<Message>,
+    /// Send this stored conversation instead of `messages`, see `stored_history`.
+    #[serde(default)]
+    conversation_id: Option<String>,
+    #[serde(default)]
+    until: Option<i64>,
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    think: bool,
+    #[serde(default)]
+    profile: Option<String>,
+  }
+  let mut parsed: InBody = serde_json::from_str(&handle)?;
+  if let Some(name) = &parsed.profile {
+    parsed.config = parsed.config.with_profile(name)?;
+  }
+  if parsed.model.is_empty() {
+    parsed.model = parsed.config.model.clone().unwrap_or_default();
+  }
+  if let Some(id) = &parsed.conversation_id {
+    parsed.messages = stored_history(&app, &db, id, parsed.until).await?;
+  }
+  parsed.config.resolve_secrets()?;
+  redact::remember(&parsed.config);
+  let client = http.client(parsed.config.timeouts);
+  let (messages, context) = context::fit_model(&client, &parsed.config, &parsed.model, &parsed.messages, None).await;
+  let req = ChatRequest { messages, model: parsed.model, think: parsed.think, context: Some(context) };
+  let provider = providers::get(&parsed.config.provider);
+  parsed.config.retry
+    .run(|| provider.chat(&client, &parsed.config, &req), |_| {})
+    .await
+    // callers of this non-streaming path render reasoning from inline tags
+    .map(|reply| if reply.thinking.is_empty() {
+      reply.content
+    } else {
+      format!("<think>{}</think>{}", reply.thinking, reply.content)
+    })
+    .map_err(|e| AppError::provider(&e, &parsed.config.provider))
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Message { role: String, content: String }
 
-async fn chat_once(config: AppConfig, messages: Vec<Message>, model: String, think: bool) -> Result<String> {
-  eprintln!("[DEBUG] chat_once called with config: provider={}, baseUrl={}, apiKey={:?}", 
-    config.provider, config.base_url, config.api_key.as_ref().map(|k| format!("{}...", &k[..std::cmp::min(8, k.len())])));
-  let client = Client::new();
-  if config.provider == "ollama" {
-    // ensure model exists locally; if not, try to pull once
-    ensure_ollama_model(&client, &config, &model).await.ok();
-    let url = format!("{}/api/chat", config.base_url.trim_end_matches('/'));
-    let mut body = serde_json::json!({
-      "model": if model.is_empty() { serde_json::Value::Null } else { serde_json::Value::String(model.clone()) },
-      "messages": messages,
-      "stream": false
-    });
-    // Handle think mode based on Ollama version
-    // For newer versions (0.9+), use reasoning API
-    // For older versions (0.6.x), modify the last message content
-    if think {
-      // Try new API first (for 0.9+)
-      body["options"] = serde_json::json!({ "reasoning": { "effort": "medium" } });
-    } else {
-      // For disabling think in older versions, we might need to add /no_think to the last message
-      if let Some(messages_array) = body.get_mut("messages").and_then(|m| m.as_array_mut()) {
-        if let Some(last_message) = messages_array.last_mut() {
-          if let Some(content) = last_message.get_mut("content").and_then(|c| c.as_str()) {
-            // Add /no_think to disable thinking in older Ollama versions
-            let new_content = format!("{} /no_think", content);
-            last_message["content"] = serde_json::Value::String(new_content);
-          }
-        }
-      }
-    }
-    let resp = client.post(url).json(&body).send().await?;
-    let status = resp.status();
-    let text = resp.text().await?;
-    // Try parse JSON and get content
-    if let Ok(v) = serde_json::from_str::<serde_json::Value>(&text) {
-      if let Some(content) = v
-        .get("message")
-        .and_then(|m| m.get("content"))
-        .and_then(|c| c.as_str())
-      {
-        if !content.is_empty() {
-          return Ok(content.to_string());
-        }
-      }
-      if let Some(resp_str) = v.get("response").and_then(|c| c.as_str()) {
-        if !resp_str.is_empty() {
-          return Ok(resp_str.to_string());
-        }
-      }
-      if !status.is_success() {
-        let err = v.get("error").and_then(|e| e.as_str()).unwrap_or(text.as_str());
-        anyhow::bail!(err.to_string());
-      }
-    }
-    // Fallback: use /api/generate by flattening messages
-    let prompt = messages
-      .iter()
-      .map(|m| format!("{}: {}", m.role, m.content))
-      .collect::<Vec<_>>()
-      .join("\n");
-    let gen_url = format!("{}/api/generate", config.base_url.trim_end_matches('/'));
-    let mut gen_body = serde_json::json!({
-      "model": if model.is_empty() { serde_json::Value::Null } else { serde_json::Value::String(model.clone()) },
-      "prompt": prompt,
-      "stream": false
-    });
-    // Handle think mode for Ollama generate API
-    if think {
-      gen_body["options"] = serde_json::json!({ "reasoning": { "effort": "medium" } });
-    } else {
-      // For disabling think in older versions, add /no_think to prompt
-      if let Some(prompt_str) = gen_body.get_mut("prompt").and_then(|p| p.as_str()) {
-        let new_prompt = format!("{} /no_think", prompt_str);
-        gen_body["prompt"] = serde_json::Value::String(new_prompt);
-      }
-    }
-    let gen_resp = client.post(gen_url).json(&gen_body).send().await?;
-    let gen_status = gen_resp.status();
-    let gen_text = gen_resp.text().await?;
-    if let Ok(v2) = serde_json::from_str::<serde_json::Value>(&gen_text) {
-      if let Some(resp_str) = v2.get("response").and_then(|c| c.as_str()) {
-        return Ok(resp_str.to_string());
-      }
-      if !gen_status.is_success() {
-        let err = v2.get("error").and_then(|e| e.as_str()).unwrap_or(gen_text.as_str());
-        anyhow::bail!(err.to_string());
-      }
-    }
-    anyhow::bail!(format!("ollama empty response: status={} body={}", status, text))
-  } else {
-    let url = format!("{}/chat/completions", config.base_url.trim_end_matches('/'));
-    eprintln!("[DEBUG] OpenAI API URL: {}", url);
-    let body = serde_json::json!({
-      "model": model,
-      "messages": messages,
-      "stream": false,
-      "temperature": config.temperature.unwrap_or(0.6)
-    });
-    // For OpenAI-compatible APIs, thinking is typically handled differently
-    // Remove the reasoning parameter as it might not be supported
-    // if think { 
-    //   body["reasoning"] = serde_json::json!({ "effort": "medium" }); 
-    // }
-    eprintln!("[DEBUG] Request body: {}", serde_json::to_string_pretty(&body).unwrap_or_default());
-    let mut req = client.post(url)
-      .header("Content-Type", "application/json")
-      .header("User-Agent", "TautiOllama/1.0")
-      .json(&body);
-    if let Some(k) = &config.api_key {
-      if !k.is_empty() {
-        eprintln!("[DEBUG] Using API key: {}...", &k[..std::cmp::min(8, k.len())]);
-        req = req.bearer_auth(k);
-      } else {
-        eprintln!("[DEBUG] API key is empty");
-      }
-    } else {
-      eprintln!("[DEBUG] No API key provided");
-    }
-    let resp = req.send().await?;
-    let status = resp.status();
-    let text = resp.text().await?;
-    eprintln!("[DEBUG] Response status: {}", status);
-    eprintln!("[DEBUG] Response body: {}", text);
-    if let Ok(v) = serde_json::from_str::<serde_json::Value>(&text) {
-      if let Some(content) = v
-        .get("choices")
-        .and_then(|c| c.get(0))
-        .and_then(|c| c.get("message"))
-        .and_then(|m| m.get("content"))
-        .and_then(|c| c.as_str())
-      {
-        return Ok(content.to_string());
-      }
-      if !status.is_success() {
-        let err = v.get("error").and_then(|e| e.get("message")).and_then(|m| m.as_str()).unwrap_or(text.as_str());
-        anyhow::bail!(err.to_string());
-      }
-    }
-    anyhow::bail!(format!("openai empty response: status={} body={}", status, text))
-  }
-}
-
-async fn ensure_ollama_model(client: &Client, config: &AppConfig, model: &str) -> Result<()> {
-  if model.is_empty() { return Ok(()); }
-  // check tags
-  let tags_url = format!("{}/api/tags", config.base_url.trim_end_matches('/'));
-  if let Ok(resp) = client.get(tags_url).send().await {
-    if let Ok(v) = resp.json::<serde_json::Value>().await {
-      if v.get("models").and_then(|m| m.as_array()).map(|arr| {
-        arr.iter().any(|m| m.get("name").and_then(|n| n.as_str()) == Some(model))
-      }).unwrap_or(false) {
-        return Ok(());
-      }
-    }
-  }
-  // try to pull via CLI
-  #[cfg(target_os = "windows")]
-  {
-    use std::process::Command as P;
-    let exe = config.ollama_path.clone().filter(|p| !p.trim().is_empty()).unwrap_or_else(|| "ollama".to_string());
-    let _ = P::new("powershell")
-      .args([
-        "-NoProfile","-WindowStyle","Hidden","-Command",
-        &format!("Start-Process -Wait -WindowStyle Hidden -FilePath '{}' -ArgumentList 'pull \"{}\"'", exe.replace("'","''"), model.replace("'","''")),
-      ])
-      .status();
-  }
-  #[cfg(not(target_os = "windows"))]
-  {
-    use std::process::Command as P;
-    let exe = config.ollama_path.clone().filter(|p| !p.trim().is_empty()).unwrap_or_else(|| "ollama".to_string());
-    let _ = P::new(exe).arg("pull").arg(model).status();
-  }
-  Ok(())
+/// The messages of a stored conversation as sent to a model: its active branch, or
+/// the branch ending at message `until` (the prompt of a reply being regenerated).
+/// The whole branch; [`context::fit_model`] trims it per model.
+async fn stored_history(
+  app: &tauri::AppHandle,
+  db: &ConversationDb,
+  id: &str,
+  until: Option<i64>,
+) -> Result<Vec<Message>, AppError> {
+  let id = id.to_string();
+  let branch = db.with(conversations::dir(app)?, move |conn| conversations::branch(conn, &id, until)).await?;
+  Ok(branch.into_iter().map(|m| Message { role: m.role, content: m.content }).collect())
 }
 
 #[tauri::command]
-async fn ensure_ollama(config: AppConfig) -> Result<bool, String> {
-  let client = Client::new();
-  let base = config.base_url.trim_end_matches('/');
-  let probe = client
-    .get(format!("{}/api/tags", base))
-    .send()
-    .await
-    .map_err(|e| e.to_string());
-  if probe.is_ok() {
+async fn ensure_ollama(http: tauri::State<'_, HttpState>, config: AppConfig) -> Result<bool, AppError> {
+  redact::remember(&config);
+  let client = http.client(config.timeouts);
+  let ollama = providers::get("ollama");
+  if ollama.health(&client, &config).await.is_ok() {
     return Ok(true);
   }
 
@@ -285,9 +262,7 @@ async fn ensure_ollama(config: AppConfig) -> Result<bool, String> {
   if starting.load(Ordering::SeqCst) {
     let deadline = std::time::Instant::now() + std::time::Duration::from_secs(12);
     while std::time::Instant::now() < deadline {
-      if let Ok(resp) = client.get(format!("{}/api/tags", base)).send().await {
-        if resp.status().is_success() { return Ok(true); }
-      }
+      if ollama.health(&client, &config).await.is_ok() { return Ok(true); }
       tokio::time::sleep(std::time::Duration::from_millis(900)).await;
     }
     return Ok(false);
@@ -318,7 +293,7 @@ async fn ensure_ollama(config: AppConfig) -> Result<bool, String> {
       let _ = P::new("cmd")
         .args(["/C", "start", "", &exe, "serve"])
         .spawn()
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| AppError::invalid_config(format!("cannot start ollama: {}", e)))?;
     }
   }
   #[cfg(not(target_os = "windows"))]
@@ -333,7 +308,7 @@ async fn ensure_ollama(config: AppConfig) -> Result<bool, String> {
     let _ = P::new(exe)
       .arg("serve")
       .spawn()
-      .map_err(|e| e.to_string())?;
+      .map_err(|e| AppError::invalid_config(format!("cannot start ollama: {}", e)))?;
   }
 
   let deadline = std::time::Instant::now() + std::time::Duration::from_secs(12);
@@ -341,11 +316,9 @@ async fn ensure_ollama(config: AppConfig) -> Result<bool, String> {
     if std::time::Instant::now() > deadline {
       break;
     }
-    if let Ok(resp) = client.get(format!("{}/api/tags", base)).send().await {
-      if resp.status().is_success() {
-        starting.store(false, Ordering::SeqCst);
-        return Ok(true);
-      }
+    if ollama.health(&client, &config).await.is_ok() {
+      starting.store(false, Ordering::SeqCst);
+      return Ok(true);
     }
     tokio::time::sleep(std::time::Duration::from_millis(900)).await;
   }
@@ -374,9 +347,7 @@ async fn ensure_ollama(config: AppConfig) -> Result<bool, String> {
         .spawn();
       let deadline2 = std::time::Instant::now() + std::time::Duration::from_secs(8);
       while std::time::Instant::now() < deadline2 {
-        if let Ok(resp) = client.get(format!("{}/api/tags", base)).send().await {
-          if resp.status().is_success() { starting.store(false, Ordering::SeqCst); return Ok(true); }
-        }
+        if ollama.health(&client, &config).await.is_ok() { starting.store(false, Ordering::SeqCst); return Ok(true); }
         tokio::time::sleep(std::time::Duration::from_millis(900)).await;
       }
     }
@@ -386,192 +357,561 @@ async fn ensure_ollama(config: AppConfig) -> Result<bool, String> {
 }
 
 #[tauri::command]
-async fn get_log_path(_app: tauri::AppHandle) -> Result<String, String> {
-  // Prefer executable directory /logs/app.log
-  if let Ok(exe) = std::env::current_exe() {
-    if let Some(dir) = exe.parent() {
-      let p = dir.join("logs").join("app.log");
-      return Ok(p.to_string_lossy().into_owned());
-    }
+async fn get_log_path(_app: tauri::AppHandle) -> Result<String, AppError> {
+  // Prefer executable directory /logs/app.log, where the logger writes
+  if let Some(dir) = logger::log_dir() {
+    return Ok(dir.join("app.log").to_string_lossy().into_owned());
   }
   // Fallback to app data dir
-  let base = _app.path().app_local_data_dir().map_err(|e| e.to_string())?;
+  let base = _app.path().app_local_data_dir()?;
   let p = base.join("logs").join("app.log");
   Ok(p.to_string_lossy().into_owned())
 }
 
 #[tauri::command]
-async fn get_conversations_path(_app: tauri::AppHandle) -> Result<String, String> {
-  if let Ok(exe) = std::env::current_exe() {
-    if let Some(dir) = exe.parent() {
-      let p = dir.join("conversations.json");
-      return Ok(p.to_string_lossy().into_owned());
-    }
-  }
-  let base = _app.path().app_local_data_dir().map_err(|e| e.to_string())?;
-  let p = base.join("conversations.json");
-  Ok(p.to_string_lossy().into_owned())
+async fn get_conversations_path(app: tauri::AppHandle) -> Result<String, AppError> {
+  Ok(conversations::dir(&app)?.join("conversations.db").to_string_lossy().into_owned())
+}
+
+/// All conversations, most recently updated first, without their messages.
+#[tauri::command]
+async fn list_conversations(app: tauri::AppHandle, db: tauri::State<'_, ConversationDb>) -> Result<Vec<ConversationSummary>, AppError> {
+  db.with(conversations::dir(&app)?, |conn| conversations::list(conn)).await
 }
+
+#[tauri::command]
+async fn get_conversation(app: tauri::AppHandle, db: tauri::State<'_, ConversationDb>, id: String) -> Result<Conversation, AppError> {
+  db.with(conversations::dir(&app)?, move |conn| conversations::get(conn, &id)).await
+}
+
+#[tauri::command]
+async fn create_conversation(
+  app: tauri::AppHandle,
+  db: tauri::State<'_, ConversationDb>,
+  conversation: Conversation,
+) -> Result<Conversation, AppError> {
+  db.with(conversations::dir(&app)?, move |conn| conversations::create(conn, conversation)).await
+}
+
+/// Adds messages to the end of a conversation without rewriting the rest.
 #[tauri::command]
-async fn write_log_line(_app: tauri::AppHandle, line: String) -> Result<(), String> {
-  // Use exe dir logs
-  let base = std::env::current_exe().map_err(|e| e.to_string())?;
-  let dir = base.parent().ok_or("no parent")?.join("logs");
-  std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
-  let file = dir.join("app.log");
-  let mut f = std::fs::OpenOptions::new()
-    .create(true)
-    .append(true)
-    .open(file)
-    .map_err(|e| e.to_string())?;
-  writeln!(f, "{}", line).map_err(|e| e.to_string())?;
+async fn append_messages(
+  app: tauri::AppHandle,
+  db: tauri::State<'_, ConversationDb>,
+  id: String,
+  messages: Vec<ChatMessage>,
+) -> Result<(), AppError> {
+  db.with(conversations::dir(&app)?, move |conn| conversations::append_messages(conn, &id, messages)).await
+}
+
+#[tauri::command]
+async fn update_conversation(
+  app: tauri::AppHandle,
+  db: tauri::State<'_, ConversationDb>,
+  id: String,
+  patch: ConversationPatch,
+) -> Result<(), AppError> {
+  db.with(conversations::dir(&app)?, move |conn| conversations::update(conn, &id, patch)).await
+}
+
+#[tauri::command]
+async fn set_conversation_metadata(
+  app: tauri::AppHandle,
+  db: tauri::State<'_, ConversationDb>,
+  id: String,
+  key: String,
+  value: Option<String>,
+) -> Result<(), AppError> {
+  db.with(conversations::dir(&app)?, move |conn| conversations::set_metadata(conn, &id, &key, value.as_deref())).await
+}
+
+/// Messages matching `query` across all conversations, best first.
+#[tauri::command]
+async fn search_conversations(
+  app: tauri::AppHandle,
+  db: tauri::State<'_, ConversationDb>,
+  query: String,
+  limit: Option<usize>,
+) -> Result<Vec<search::SearchHit>, AppError> {
+  db.with(conversations::dir(&app)?, move |conn| search::search(conn, &query, limit)).await
+}
+
+/// Writes conversation `id` to `path` as Markdown, HTML or versioned JSON.
+#[tauri::command]
+async fn export_conversation(
+  app: tauri::AppHandle,
+  db: tauri::State<'_, ConversationDb>,
+  id: String,
+  format: export::ExportFormat,
+  path: String,
+) -> Result<(), AppError> {
+  let (cid, file) = (id.clone(), path.clone());
+  db.with(conversations::dir(&app)?, move |conn| {
+    export::export(&conversations::get(conn, &cid)?, format, std::path::Path::new(&file))
+  }).await?;
+  logger::log(Level::Info, "conversations", Some(&id), "conversation exported", serde_json::json!({ "format": format, "path": path }));
   Ok(())
 }
 
+/// Imports a ChatGPT `conversations.json` or an Open WebUI export; `source` is
+/// detected from the file when not given.
 #[tauri::command]
-async fn get_config_path(_app: tauri::AppHandle) -> Result<String, String> {
-  // 始终使用应用数据目录，这样更安全且符合权限配置
-  let base = _app.path().app_local_data_dir().map_err(|e| e.to_string())?;
-  
-  // 确保目录存在
-  if let Err(e) = std::fs::create_dir_all(&base) {
-    eprintln!("Failed to create config directory: {}", e);
+async fn import_conversations(
+  app: tauri::AppHandle,
+  db: tauri::State<'_, ConversationDb>,
+  path: String,
+  source: Option<importer::ImportSource>,
+) -> Result<importer::ImportReport, AppError> {
+  let report = db.with(conversations::dir(&app)?, move |conn| {
+    let text = std::fs::read_to_string(&path)?;
+    importer::import(conn, &text, source)
+  }).await?;
+  logger::log(
+    Level::Info,
+    "conversations",
+    None,
+    "conversations imported",
+    serde_json::json!({ "source": report.source, "imported": report.imported.len(), "skipped": report.skipped.len(), "skippedMessages": report.skipped_messages }),
+  );
+  Ok(report)
+}
+
+/// Starts a branch at message `message_id` with `message`, e.g. an edited prompt.
+#[tauri::command]
+async fn fork_conversation(
+  app: tauri::AppHandle,
+  db: tauri::State<'_, ConversationDb>,
+  id: String,
+  message_id: i64,
+  message: ChatMessage,
+) -> Result<Conversation, AppError> {
+  db.with(conversations::dir(&app)?, move |conn| conversations::fork(conn, &id, message_id, message)).await
+}
+
+/// Stores `reply` as an alternative to assistant message `message_id`.
+#[tauri::command]
+async fn regenerate_message(
+  app: tauri::AppHandle,
+  db: tauri::State<'_, ConversationDb>,
+  id: String,
+  message_id: i64,
+  reply: ChatMessage,
+) -> Result<Conversation, AppError> {
+  db.with(conversations::dir(&app)?, move |conn| conversations::regenerate(conn, &id, message_id, reply)).await
+}
+
+#[tauri::command]
+async fn switch_branch(
+  app: tauri::AppHandle,
+  db: tauri::State<'_, ConversationDb>,
+  id: String,
+  message_id: i64,
+) -> Result<Conversation, AppError> {
+  db.with(conversations::dir(&app)?, move |conn| conversations::switch_branch(conn, &id, message_id)).await
+}
+
+#[tauri::command]
+async fn delete_conversation(app: tauri::AppHandle, db: tauri::State<'_, ConversationDb>, id: String) -> Result<bool, AppError> {
+  db.with(conversations::dir(&app)?, move |conn| conversations::delete(conn, &id)).await
+}
+/// Logs a line from the frontend logger (`[ts] [LEVEL] message {json}`) as a structured entry.
+#[tauri::command]
+async fn write_log_line(_app: tauri::AppHandle, line: String) -> Result<(), AppError> {
+  logger::write(logger::parse_frontend_line(&line));
+  Ok(())
+}
+
+/// The newest log entries matching `query`, oldest first.
+#[tauri::command]
+async fn query_logs(query: LogQuery) -> Result<Vec<LogEntry>, AppError> {
+  Ok(tauri::async_runtime::spawn_blocking(move || logger::query(&query)).await.map_err(anyhow::Error::from)??)
+}
+
+/// Emits `log-entry:{id}` for every new entry matching `query` until `stop_tail_logs`.
+#[tauri::command]
+async fn tail_logs(window: Window, query: LogQuery) -> Result<String, AppError> {
+  static NEXT_TAIL: AtomicU64 = AtomicU64::new(0);
+  let id = format!("tail-{}", NEXT_TAIL.fetch_add(1, Ordering::Relaxed));
+  let event = format!("log-entry:{}", id);
+  logger::subscribe(id.clone(), move |entry| {
+    if query.matches(entry) {
+      let _ = window.emit(&event, entry);
+    }
+  });
+  Ok(id)
+}
+
+#[tauri::command]
+async fn stop_tail_logs(id: String) -> Result<bool, AppError> {
+  Ok(logger::unsubscribe(&id))
+}
+
+/// Stores an API key and returns the reference to put in the config in its place.
+#[tauri::command]
+async fn set_secret(id: String, value: String) -> Result<String, AppError> {
+  secrets::set(&id, &value)?;
+  redact::remember_key(&value);
+  Ok(secrets::reference(&id))
+}
+
+#[tauri::command]
+async fn get_secret(id: String) -> Result<Option<String>, AppError> {
+  secrets::get(&id)
+}
+
+#[tauri::command]
+async fn delete_secret(id: String) -> Result<bool, AppError> {
+  secrets::delete(&id)
+}
+
+#[tauri::command]
+async fn secret_store_status() -> Result<secrets::SecretStatus, AppError> {
+  Ok(secrets::status())
+}
+
+/// Opens (or on first use creates) the vault used when there is no OS keyring.
+#[tauri::command]
+async fn unlock_secrets(passphrase: String) -> Result<(), AppError> {
+  // key derivation is deliberately slow
+  tauri::async_runtime::spawn_blocking(move || secrets::unlock(&passphrase)).await.map_err(anyhow::Error::from)?
+}
+
+#[tauri::command]
+async fn lock_secrets() -> Result<(), AppError> {
+  secrets::lock()
+}
+
+#[tauri::command]
+async fn get_config_path(app: tauri::AppHandle) -> Result<String, AppError> {
+  Ok(settings::path(&app)?.to_string_lossy().into_owned())
+}
+
+/// The saved settings, upgraded to the current version; defaults when there are none.
+#[tauri::command]
+async fn load_settings(app: tauri::AppHandle) -> Result<AppConfig, AppError> {
+  settings::load(&settings::path(&app)?)
+}
+
+/// Validates and saves the settings, returning them as written.
+#[tauri::command]
+async fn save_settings(app: tauri::AppHandle, config: AppConfig) -> Result<AppConfig, AppError> {
+  let saved = settings::save(&settings::path(&app)?, config)?;
+  logger::log(Level::Info, "settings", None, "saved", serde_json::Value::Null);
+  Ok(saved)
+}
+
+#[tauri::command]
+async fn list_profiles(app: tauri::AppHandle) -> Result<profiles::ProfileList, AppError> {
+  let mut config = settings::load(&settings::path(&app)?)?;
+  config.sync_active_profile();
+  Ok(config.list_profiles())
+}
+
+/// Switches to profile `name` and saves; returns the settings now in effect.
+#[tauri::command]
+async fn activate_profile(app: tauri::AppHandle, name: String) -> Result<AppConfig, AppError> {
+  update_settings(&app, |config| config.activate_profile(&name))
+}
+
+/// Saves a copy of profile `from` as `name`; returns the updated settings.
+#[tauri::command]
+async fn duplicate_profile(app: tauri::AppHandle, from: String, name: String) -> Result<AppConfig, AppError> {
+  update_settings(&app, |config| config.duplicate_profile(&from, &name))
+}
+
+#[tauri::command]
+async fn delete_profile(app: tauri::AppHandle, name: String) -> Result<AppConfig, AppError> {
+  let mut orphaned = None;
+  let saved = update_settings(&app, |config| {
+    orphaned = config.delete_profile(&name)?;
+    Ok(())
+  })?;
+  // only once the profile is gone from disk; a locked vault leaves the key orphaned
+  if let Some(id) = orphaned {
+    if let Err(e) = secrets::delete(&id) {
+      logger::log(Level::Warn, "settings", None, "stored key of deleted profile kept", serde_json::json!({ "profile": name, "error": e.message }));
+    }
   }
-  
-  let p = base.join("settings.json");
-  Ok(p.to_string_lossy().into_owned())
+  Ok(saved)
+}
+
+/// Loads the saved settings, applies `change` and saves them.
+fn update_settings(
+  app: &tauri::AppHandle,
+  change: impl FnOnce(&mut AppConfig) -> Result<(), AppError>,
+) -> Result<AppConfig, AppError> {
+  let path = settings::path(app)?;
+  let mut config = settings::load(&path)?;
+  change(&mut config)?;
+  settings::save(&path, config)
 }
 
 #[tauri::command]
-async fn start_chat_stream(window: Window, body: String) -> Result<String, String> {
+async fn start_chat_stream(
+  app: tauri::AppHandle,
+  window: Window,
+  http: tauri::State<'_, HttpState>,
+  db: tauri::State<'_, ConversationDb>,
+  body: String,
+) -> Result<String, AppError> {
   #[derive(Deserialize)]
-  struct InBody { config: AppConfig, messages: Vec<Message>, model: String, #[serde(default)] think: bool }
-  let parsed: InBody = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+  #[serde(rename_all = "camelCase")]
+  struct InBody {
+    config: AppConfig,
+    #[serde(default)]
+    messages: Vec<Message>,
+    /// Send this stored conversation instead of `messages`, see `stored_history`.
+    #[serde(default)]
+    conversation_id: Option<String>,
+    #[serde(default)]
+    until: Option<i64>,
+    /// Empty means the model of the config (or of `profile`).
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    think: bool,
+    /// Overrides the `fallbacks` of the model's entry in `config.models`.
+    #[serde(default)]
+    fallbacks: Option<Vec<String>>,
+    /// Answer with the connection settings of this profile instead of the active one.
+    #[serde(default)]
+    profile: Option<String>,
+  }
+  let mut parsed: InBody = serde_json::from_str(&body)?;
+  if let Some(name) = &parsed.profile {
+    parsed.config = parsed.config.with_profile(name)?;
+  }
+  if parsed.model.is_empty() {
+    parsed.model = parsed.config.model.clone().unwrap_or_default();
+  }
+  if let Some(id) = &parsed.conversation_id {
+    parsed.messages = stored_history(&app, &db, id, parsed.until).await?;
+  }
+  parsed.config.resolve_secrets()?;
+  redact::remember(&parsed.config);
   // simple unique id without external deps
   let millis = std::time::SystemTime::now()
     .duration_since(std::time::UNIX_EPOCH)
     .unwrap_or_else(|_| std::time::Duration::from_millis(0))
     .as_millis();
-  let stream_id = format!("stream-{}", millis);
+  // millis alone collide when two chats start within the same millisecond
+  static NEXT_SEQ: AtomicU64 = AtomicU64::new(0);
+  let stream_id = format!("stream-{}-{}", millis, NEXT_SEQ.fetch_add(1, Ordering::Relaxed));
   let sid = stream_id.clone();
 
   // log start
-  if let Ok(_path) = get_log_path(window.app_handle().clone()).await {
-    let _ = write_log_line(window.app_handle().clone(), format!(
-      "[chat-start] id={} model={} think={} input={}",
-      stream_id,
-      parsed.model,
-      parsed.think,
-      parsed
-        .messages
-        .iter()
-        .rev()
-        .find(|m| m.role == "user")
-        .map(|m| m.content.as_str())
-        .unwrap_or("")
-    ))
-    .await;
-  }
+  logger::log(Level::Info, "chat", Some(&stream_id), "start", serde_json::json!({
+    "provider": parsed.config.provider,
+    "model": parsed.model,
+    "think": parsed.think,
+    "messages": parsed.messages.len(),
+    "input": parsed
+      .messages
+      .iter()
+      .rev()
+      .find(|m| m.role == "user")
+      .map(|m| redact::prompt(&m.content, &parsed.config))
+      .unwrap_or_default(),
+  }));
 
-  // spawn task
+  // spawn task; keep the registry locked until the handle is stored so a fast
+  // task cannot deregister itself before it was registered
   let win = window.clone();
-  tauri::async_runtime::spawn(async move {
-    match chat_once(parsed.config, parsed.messages.clone(), parsed.model.clone(), parsed.think).await {
-      Ok(content) => {
-        // emit chunks by characters batches of 8 for smoother UI
-        let mut buf = String::new();
-        for (i, ch) in content.chars().enumerate() {
-          buf.push(ch);
-          if buf.len() >= 8 || i == content.chars().count().saturating_sub(1) {
-            let _ = win.emit(&format!("chat-chunk:{}", sid), buf.clone());
-            buf.clear();
-          }
-        }
-        let _ = win.emit(&format!("chat-end:{}", sid), "");
-        let _ = write_log_line(win.app_handle().clone(), format!(
-          "[chat-end] id={} output_len={}",
-          sid,
-          content.len()
-        ))
-        .await;
+  let client = http.client(parsed.config.timeouts);
+  let mut streams = chat_streams().lock()?;
+  let handle = tauri::async_runtime::spawn(async move {
+    // the requested model first, then its fallbacks, each on its own server
+    let fallbacks = parsed.fallbacks
+      .or_else(|| parsed.config.model_entry(&parsed.model).and_then(|m| m.fallbacks.clone()))
+      .unwrap_or_default();
+    let mut chain = vec![(parsed.config.clone(), parsed.model.clone())];
+    for name in fallbacks {
+      if !name.is_empty() && !chain.iter().any(|(_, model)| *model == name) {
+        chain.push((parsed.config.for_model(&name), name));
+      }
+    }
+
+    let mut req = ChatRequest { messages: Vec::new(), model: String::new(), think: parsed.think, context: None };
+    let mut answered = 0;
+    let mut result = Err(anyhow::anyhow!("no model to try"));
+    for (i, (config, model)) in chain.iter().enumerate() {
+      // a fallback may have a smaller window than the model it stands in for
+      let (messages, context) = context::fit_model(&client, config, model, &parsed.messages, Some(&sid)).await;
+      req.messages = messages;
+      req.context = Some(context);
+      req.model = model.clone();
+      let (outcome, streamed) = stream_with_retry(&win, &sid, &client, config, &req).await;
+      result = outcome;
+      answered = i;
+      let Err(err) = &result else { break };
+      let Some((next_config, next_model)) = chain.get(i + 1) else { break };
+      if streamed || !retry::should_fall_back(err) {
+        break;
+      }
+      logger::log(Level::Warn, "chat", Some(&sid), "fallback", serde_json::json!({
+        "from": format!("{}/{}", config.provider, model),
+        "to": format!("{}/{}", next_config.provider, next_model),
+        "error": err.to_string(),
+      }));
+    }
+    let (config, model) = &chain[answered];
+    // a missing entry means stop_chat_stream already reported the cancellation
+    if chat_streams().lock().map(|mut m| m.remove(&sid).is_none()).unwrap_or(false) {
+      return;
+    }
+    match result {
+      Ok(reply) => {
+        let _ = win.emit(&format!("chat-end:{}", sid), ChatEnd { reply: &reply, model, provider: &config.provider });
+        logger::log(Level::Info, "chat", Some(&sid), "end", serde_json::json!({
+          "provider": config.provider,
+          "model": model,
+          "outputLen": reply.content.len(),
+          "thinkingLen": reply.thinking.len(),
+          "evalCount": reply.stats.as_ref().and_then(|s| s.eval_count),
+        }));
       }
       Err(err) => {
-        let _ = win.emit(&format!("chat-error:{}", sid), err.to_string());
-        let _ = write_log_line(win.app_handle().clone(), format!(
-          "[chat-error] id={} err={}",
-          sid,
-          err.to_string()
-        ))
-        .await;
+        let error = AppError::provider(&err, &config.provider);
+        logger::log(Level::Error, "chat", Some(&sid), "error", serde_json::json!({
+          "provider": config.provider,
+          "model": model,
+          "code": error.code,
+          "error": error.message,
+        }));
+        let _ = win.emit(&format!("chat-error:{}", sid), error);
       }
     }
   });
+  streams.insert(stream_id.clone(), handle);
+  drop(streams);
 
   Ok(stream_id)
 }
 
+/// Payload of `chat-end:{id}`: the reply plus the model that actually produced it,
+/// which differs from the requested one when a fallback answered.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChatEnd<'a> {
+  #[serde(flatten)]
+  reply: &'a providers::ChatReply,
+  model: &'a str,
+  provider: &'a str,
+}
+
+/// One model's turn at a chat stream: retries transient failures until output has
+/// started. Returns the outcome and whether any of it reached the window.
+async fn stream_with_retry(
+  win: &Window,
+  sid: &str,
+  client: &http::HttpClient,
+  config: &AppConfig,
+  req: &ChatRequest,
+) -> (Result<providers::ChatReply>, bool) {
+  let chunk_event = format!("chat-chunk:{}", sid);
+  let thinking_event = format!("chat-thinking:{}", sid);
+  let provider = providers::get(&config.provider);
+  let policy = config.retry;
+  let mut attempt = 1;
+  loop {
+    let mut streamed = false;
+    let result = {
+      let mut on_delta = |delta: Delta<'_>| {
+        streamed = true;
+        let _ = match delta {
+          Delta::Content(text) => win.emit(&chunk_event, text),
+          Delta::Thinking(text) => win.emit(&thinking_event, text),
+        };
+      };
+      provider.chat_stream(client, config, req, &mut on_delta).await
+    };
+    let Err(err) = &result else { return (result, streamed) };
+    let Some(delay) = policy.next_stream_delay(attempt, streamed, err) else { return (result, streamed) };
+    attempt += 1;
+    let _ = win.emit(&format!("chat-retry:{}", sid), policy.notice(attempt, delay, err));
+    logger::log(Level::Warn, "chat", Some(sid), "retry", serde_json::json!({
+      "attempt": attempt,
+      "maxAttempts": policy.max_attempts,
+      "delayMs": delay.as_millis() as u64,
+      "error": err.to_string(),
+    }));
+    tokio::time::sleep(delay).await;
+  }
+}
+
+/// Running chat streams keyed by stream id, so `stop_chat_stream` can abort them.
+fn chat_streams() -> &'static Mutex<HashMap<String, tauri::async_runtime::JoinHandle<()>>> {
+  static STREAMS: OnceLock<Mutex<HashMap<String, tauri::async_runtime::JoinHandle<()>>>> = OnceLock::new();
+  STREAMS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Aborts a running chat stream. Dropping the task closes the HTTP connection,
+/// which also makes Ollama stop generating. Returns false if the stream already finished.
+#[tauri::command]
+async fn stop_chat_stream(window: Window, id: String) -> Result<bool, AppError> {
+  let handle = chat_streams().lock()?.remove(&id);
+  let Some(handle) = handle else { return Ok(false) };
+  handle.abort();
+  let _ = window.emit(&format!("chat-cancelled:{}", id), "");
+  logger::log(Level::Info, "chat", Some(&id), "cancelled", serde_json::Value::Null);
+  Ok(true)
+}
+
 #[tauri::command]
-async fn check_model_exists(window: Window, config: AppConfig, model: String) -> Result<bool, String> {
+async fn check_model_exists(
+  http: tauri::State<'_, HttpState>,
+  mut config: AppConfig,
+  model: String,
+) -> Result<bool, AppError> {
+  config.resolve_secrets()?;
+  redact::remember(&config);
   if model.is_empty() { 
-    let _ = write_log_line(window.app_handle().clone(), format!(
-      "[model-check] model=<empty> exists=true"
-    )).await;
+    logger::log(Level::Info, "model-check", None, "empty model name, nothing to check", serde_json::Value::Null);
     return Ok(true); 
   }
   
-  let _ = write_log_line(window.app_handle().clone(), format!(
-    "[model-check] checking model existence model={} baseUrl={}",
-    model, config.base_url
-  )).await;
+  logger::log(Level::Info, "model-check", None, "checking", serde_json::json!({
+    "model": model,
+    "baseUrl": config.base_url,
+  }));
   
-  let client = Client::new();
-  let url = format!("{}/api/tags", config.base_url.trim_end_matches('/'));
-  let resp = client.get(url).send().await.map_err(|e| {
-    let _err_msg = format!("Failed to connect to Ollama: {}", e);
-    // Note: async logging in error handler - will be logged if reached
-    e.to_string()
-  })?;
-  
-  let v: serde_json::Value = resp.json().await.map_err(|e| {
-    let err_msg = format!("Failed to parse Ollama response: {}", e);
-    // Note: async logging in error handler - will be logged if reached
-    err_msg
-  })?;
-  
-  let available_models: Vec<String> = v.get("models")
-    .and_then(|m| m.as_array())
-    .map(|arr| {
-      arr.iter()
-        .filter_map(|m| m.get("name").and_then(|n| n.as_str()).map(|s| s.to_string()))
-        .collect()
-    })
-    .unwrap_or_default();
-    
+  let client = http.client(config.timeouts);
+  let provider = providers::get(&config.provider);
+  let available_models = config.retry
+    .run(|| provider.list_models(&client, &config), |_| {})
+    .await
+    .map_err(|e| AppError::provider(&e, &config.provider))?;
+
   let exists = available_models.iter().any(|m| m == &model);
   
-  let _ = write_log_line(window.app_handle().clone(), format!(
-    "[model-check] model={} exists={} available_models={:?}",
-    model, exists, available_models
-  )).await;
+  logger::log(Level::Info, "model-check", None, "checked", serde_json::json!({
+    "model": model,
+    "exists": exists,
+    "available": available_models,
+  }));
   
   Ok(exists)
 }
 
 #[tauri::command]
-async fn start_pull_model(window: Window, base_url: String, name: String) -> Result<String, String> {
-  let _ = write_log_line(window.app_handle().clone(), format!(
-    "[model-pull] starting download model={} baseUrl={}",
-    name, base_url
-  )).await;
+async fn start_pull_model(
+  window: Window,
+  http: tauri::State<'_, HttpState>,
+  base_url: String,
+  name: String,
+) -> Result<String, AppError> {
+  logger::log(Level::Info, "model-pull", None, "starting download", serde_json::json!({
+    "model": name,
+    "baseUrl": base_url,
+  }));
   
   let url = format!("{}/api/pull", base_url.trim_end_matches('/'));
   let body = serde_json::json!({ "name": name, "stream": true });
-  let client = Client::new();
+  let client = http.current();
   
-  let res = client.post(url).json(&body).send().await.map_err(|e| {
-    let err_msg = format!("Failed to start model download: {}", e);
-    // Note: async logging in error handler - will be logged if reached  
-    err_msg
+  let res = client.send(client.post(url).json(&body)).await.map_err(|e| {
+    let err = AppError::provider(&e, "ollama");
+    AppError { message: format!("Failed to start model download: {}", err.message), ..err }
   })?;
   
   let id = format!("pull-{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis());
@@ -580,13 +920,10 @@ async fn start_pull_model(window: Window, base_url: String, name: String) -> Res
   let win = window.clone();
   let model_name = name.clone();
   
-  let _ = write_log_line(window.app_handle().clone(), format!(
-    "[model-pull] stream established model={} pullId={}",
-    name, sid
-  )).await;
+  logger::log(Level::Info, "model-pull", Some(&sid), "stream established", serde_json::json!({ "model": name }));
   
   tauri::async_runtime::spawn(async move {
-    let mut stream = res.bytes_stream();
+    let mut stream = client.stream(res);
     let mut last_percent = -1.0;
     
     while let Some(item) = stream.next().await {
@@ -606,10 +943,13 @@ async fn start_pull_model(window: Window, base_url: String, name: String) -> Res
               
               // Log progress every 10% or on status changes
               if (percent - last_percent).abs() >= 10.0 || last_percent < 0.0 || !status.is_empty() {
-                let _ = write_log_line(win.app_handle().clone(), format!(
-                  "[model-pull] progress model={} status='{}' percent={:.1}% completed={:.0}MB total={:.0}MB pullId={}",
-                  model_name, status, percent, completed / 1_000_000.0, total / 1_000_000.0, sid
-                )).await;
+                logger::log(Level::Info, "model-pull", Some(&sid), "progress", serde_json::json!({
+                  "model": model_name,
+                  "status": status,
+                  "percent": (percent * 10.0).round() / 10.0,
+                  "completedMb": (completed / 1_000_000.0).round(),
+                  "totalMb": (total / 1_000_000.0).round(),
+                }));
                 last_percent = percent;
               }
               
@@ -622,29 +962,26 @@ async fn start_pull_model(window: Window, base_url: String, name: String) -> Res
               let _ = win.emit(&format!("model-pull-progress:{}", sid), payload);
             } else {
               // Log raw status messages
-              let _ = write_log_line(win.app_handle().clone(), format!(
-                "[model-pull] status model={} message='{}' pullId={}",
-                model_name, line, sid
-              )).await;
+              logger::log(Level::Info, "model-pull", Some(&sid), "status", serde_json::json!({
+                "model": model_name,
+                "line": line,
+              }));
               let _ = win.emit(&format!("model-pull-progress:{}", sid), line.clone());
             }
           }
         }
         Err(err) => {
-          let _ = write_log_line(win.app_handle().clone(), format!(
-            "[model-pull] stream error model={} error={} pullId={}",
-            model_name, err.to_string(), sid
-          )).await;
-          let _ = win.emit(&format!("model-pull-error:{}", sid), err.to_string());
+          logger::log(Level::Error, "model-pull", Some(&sid), "stream error", serde_json::json!({
+            "model": model_name,
+            "error": err.to_string(),
+          }));
+          let _ = win.emit(&format!("model-pull-error:{}", sid), AppError::provider(&err, "ollama"));
           return;
         }
       }
     }
     
-    let _ = write_log_line(win.app_handle().clone(), format!(
-      "[model-pull] download completed successfully model={} pullId={}",
-      model_name, sid
-    )).await;
+    logger::log(Level::Info, "model-pull", Some(&sid), "download completed", serde_json::json!({ "model": model_name }));
     let _ = win.emit(&format!("model-pull-end:{}", sid), "");
   });
   Ok(id)
@@ -657,6 +994,13 @@ async fn main() {
     .plugin(tauri_plugin_process::init())
     .plugin(tauri_plugin_shell::init())
     .plugin(tauri_plugin_fs::init())
+    .plugin(tauri_plugin_dialog::init())
+    .manage(HttpState::default())
+    .manage(ConversationDb::default())
+    .setup(|app| {
+      secrets::init(&app.path().app_local_data_dir()?);
+      Ok(())
+    })
     .invoke_handler(tauri::generate_handler![
       proxy_models,
       proxy_chat_stream,
@@ -664,9 +1008,38 @@ async fn main() {
       ensure_ollama,
       get_log_path,
       write_log_line,
+      query_logs,
+      tail_logs,
+      stop_tail_logs,
+      set_secret,
+      get_secret,
+      delete_secret,
+      secret_store_status,
+      unlock_secrets,
+      lock_secrets,
       get_config_path,
+      load_settings,
+      save_settings,
+      list_profiles,
+      activate_profile,
+      duplicate_profile,
+      delete_profile,
       get_conversations_path,
+      list_conversations,
+      get_conversation,
+      create_conversation,
+      append_messages,
+      update_conversation,
+      set_conversation_metadata,
+      delete_conversation,
+      search_conversations,
+      export_conversation,
+      import_conversations,
+      fork_conversation,
+      regenerate_message,
+      switch_branch,
       start_chat_stream,
+      stop_chat_stream,
       check_model_exists,
       start_pull_model
     ])
@@ -0,0 +1,136 @@
+//! Named connection profiles ("home Ollama", "OpenAI", ...). The top-level
+//! provider/base_url/key/model/temperature of [`AppConfig`] are the active
+//! profile's; switching copies another profile's set over them.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::secrets;
+use crate::AppConfig;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+  pub name: String,
+  pub provider: String,
+  pub base_url: String,
+  #[serde(default)]
+  pub api_key: Option<String>,
+  #[serde(default)]
+  pub model: Option<String>,
+  #[serde(default)]
+  pub temperature: Option<f64>,
+  #[serde(default)]
+  pub deployment: Option<String>,
+  #[serde(default)]
+  pub api_version: Option<String>,
+}
+
+/// Payload of `list_profiles`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileList {
+  pub active: Option<String>,
+  pub profiles: Vec<Profile>,
+}
+
+/// Name given to the profile made from settings that predate profiles.
+pub const DEFAULT_PROFILE: &str = "Default";
+
+impl Profile {
+  /// The connection settings `config` currently uses, under `name`.
+  pub fn from_config(name: &str, config: &AppConfig) -> Self {
+    Profile {
+      name: name.to_string(),
+      provider: config.provider.clone(),
+      base_url: config.base_url.clone(),
+      api_key: config.api_key.clone(),
+      model: config.model.clone(),
+      temperature: config.temperature,
+      deployment: config.deployment.clone(),
+      api_version: config.api_version.clone(),
+    }
+  }
+
+  fn apply_to(&self, config: &mut AppConfig) {
+    config.provider = self.provider.clone();
+    config.base_url = self.base_url.clone();
+    config.api_key = self.api_key.clone();
+    config.model = self.model.clone();
+    config.temperature = self.temperature;
+    config.deployment = self.deployment.clone();
+    config.api_version = self.api_version.clone();
+  }
+}
+
+impl AppConfig {
+  pub fn profile(&self, name: &str) -> Result<&Profile, AppError> {
+    self
+      .profiles
+      .iter()
+      .find(|p| p.name == name)
+      .ok_or_else(|| AppError::invalid_config(format!("no profile named '{}'", name)))
+  }
+
+  /// Copies the top-level connection settings back into the active profile, so
+  /// edits made while it is active are kept when switching away.
+  pub fn sync_active_profile(&mut self) {
+    let Some(active) = self.active_profile.clone() else { return };
+    let current = Profile::from_config(&active, self);
+    if let Some(p) = self.profiles.iter_mut().find(|p| p.name == active) {
+      *p = current;
+    }
+  }
+
+  /// This config with the connection settings of profile `name`, for one request.
+  pub fn with_profile(&self, name: &str) -> Result<AppConfig, AppError> {
+    let mut config = self.clone();
+    self.profile(name)?.apply_to(&mut config);
+    config.active_profile = Some(name.to_string());
+    Ok(config)
+  }
+
+  pub fn list_profiles(&self) -> ProfileList {
+    ProfileList { active: self.active_profile.clone(), profiles: self.profiles.clone() }
+  }
+
+  pub fn activate_profile(&mut self, name: &str) -> Result<(), AppError> {
+    self.sync_active_profile();
+    *self = self.with_profile(name)?;
+    Ok(())
+  }
+
+  /// Adds a copy of profile `from` named `to`. The copy shares `from`'s stored key
+  /// until a different one is saved for it.
+  pub fn duplicate_profile(&mut self, from: &str, to: &str) -> Result<(), AppError> {
+    let to = to.trim();
+    if to.is_empty() {
+      return Err(AppError::invalid_config("a profile needs a name"));
+    }
+    if self.profiles.iter().any(|p| p.name == to) {
+      return Err(AppError::invalid_config(format!("a profile named '{}' already exists", to)));
+    }
+    self.sync_active_profile();
+    let copy = Profile { name: to.to_string(), ..self.profile(from)?.clone() };
+    self.profiles.push(copy);
+    Ok(())
+  }
+
+  /// Removes profile `name` and returns the id of its stored key when nothing else
+  /// refers to it; the caller deletes that key once the settings are saved.
+  /// Deleting the active profile leaves its settings in place, no longer tied to a profile.
+  pub fn delete_profile(&mut self, name: &str) -> Result<Option<String>, AppError> {
+    let removed = self.profile(name)?.clone();
+    self.profiles.retain(|p| p.name != name);
+    if self.active_profile.as_deref() == Some(name) {
+      self.active_profile = None;
+    }
+    let Some(key) = removed.api_key.as_deref() else { return Ok(None) };
+    let Some(id) = secrets::reference_id(key) else { return Ok(None) };
+    let still_used = std::iter::once(&self.api_key)
+      .chain(self.profiles.iter().map(|p| &p.api_key))
+      .chain(self.models.iter().flatten().map(|m| &m.api_key))
+      .any(|k| k.as_deref() == Some(key));
+    Ok((!still_used).then(|| id.to_string()))
+  }
+}
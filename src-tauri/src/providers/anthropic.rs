@@ -0,0 +1,282 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::RequestBuilder;
+
+use super::{base_url, lookup, ChatProvider, ChatReply, ChatRequest, ChatStats, LineBuffer, OnDelta, ReplyAccumulator};
+use crate::http::{HttpClient, HttpStatus};
+use crate::AppConfig;
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// `/v1/messages` requires `max_tokens`; this matches what the other backends allow by default.
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+/// Anthropic Messages API (`/v1/messages`) and compatible endpoints.
+pub struct AnthropicProvider;
+
+#[async_trait]
+impl ChatProvider for AnthropicProvider {
+  async fn list_models(&self, client: &HttpClient, config: &AppConfig) -> Result<Vec<String>> {
+    let resp = client.send(authorize(client.get(api_url(config, "models")), config)).await?;
+    let status = resp.status();
+    let v = client.json(resp).await?;
+    if !status.is_success() {
+      let msg = error_message(&v).unwrap_or_else(|| format!("anthropic models failed: status={}", status));
+      return Err(HttpStatus::new(status, msg).into());
+    }
+    let list = v.get("data").and_then(|m| m.as_array()).map(|arr| {
+      arr.iter()
+        .filter_map(|m| m.get("id").and_then(|n| n.as_str()).map(|s| s.to_string()))
+        .collect::<Vec<String>>()
+    }).unwrap_or_default();
+    Ok(list)
+  }
+
+  async fn chat(&self, client: &HttpClient, config: &AppConfig, req: &ChatRequest) -> Result<ChatReply> {
+    let body = request_body(config, req, false);
+    let resp = client.send(authorize(client.post(api_url(config, "messages")), config).json(&body)).await?;
+    let status = resp.status();
+    let text = client.text(resp).await?;
+    parse_message(status, &text)
+  }
+
+  async fn chat_stream(
+    &self,
+    client: &HttpClient,
+    config: &AppConfig,
+    req: &ChatRequest,
+    on_delta: &mut OnDelta<'_>,
+  ) -> Result<ChatReply> {
+    let body = request_body(config, req, true);
+    let http = authorize(client.post(api_url(config, "messages")), config)
+      .header("Accept", "text/event-stream")
+      .json(&body);
+    let resp = client.send(http).await?;
+    let status = resp.status();
+    if !status.is_success() {
+      let text = client.text(resp).await?;
+      let v = serde_json::from_str::<serde_json::Value>(&text).unwrap_or(serde_json::Value::Null);
+      let msg = error_message(&v).unwrap_or_else(|| format!("anthropic error: status={} body={}", status, text));
+      return Err(HttpStatus::new(status, msg).into());
+    }
+
+    let mut stream = client.stream(resp);
+    let mut lines = LineBuffer::default();
+    let mut reply = ReplyAccumulator::new(on_delta);
+    let mut stats = ChatStats::default();
+    'events: while let Some(item) = stream.next().await {
+      lines.push(&item?);
+      while let Some(line) = lines.next_line() {
+        // every event repeats its name in the data's `type`, so `event:` lines can be skipped
+        let Some(data) = line.strip_prefix("data:") else { continue };
+        let Ok(v) = serde_json::from_str::<serde_json::Value>(data.trim()) else { continue };
+        if read_event(&v, &mut reply, &mut stats)? {
+          break 'events;
+        }
+      }
+    }
+    if reply.is_empty() {
+      anyhow::bail!(format!("anthropic empty response: status={}", status))
+    }
+    Ok(reply.finish(Some(stats)))
+  }
+
+  async fn health(&self, client: &HttpClient, config: &AppConfig) -> Result<()> {
+    let resp = client.send(authorize(client.get(api_url(config, "models")), config)).await?;
+    if !resp.status().is_success() {
+      anyhow::bail!(format!("anthropic not ready: status={}", resp.status()));
+    }
+    Ok(())
+  }
+
+  async fn context_length(&self, _client: &HttpClient, _config: &AppConfig, model: &str) -> Option<u32> {
+    lookup(&[("claude-2", 100_000), ("claude-instant", 100_000), ("claude", 200_000)], model)
+  }
+}
+
+/// `base_url` may be given with or without the `/v1` suffix.
+fn api_url(config: &AppConfig, path: &str) -> String {
+  let base = base_url(config);
+  if base.ends_with("/v1") {
+    format!("{}/{}", base, path)
+  } else {
+    format!("{}/v1/{}", base, path)
+  }
+}
+
+fn authorize(req: RequestBuilder, config: &AppConfig) -> RequestBuilder {
+  let req = req.header("anthropic-version", ANTHROPIC_VERSION);
+  match config.api_key.as_deref() {
+    Some(k) if !k.is_empty() => req.header("x-api-key", k),
+    _ => req,
+  }
+}
+
+/// System messages become the top-level `system` prompt; the Messages API only
+/// accepts `user` and `assistant` turns in `messages`.
+fn request_body(config: &AppConfig, req: &ChatRequest, stream: bool) -> serde_json::Value {
+  let system = req.messages
+    .iter()
+    .filter(|m| m.role == "system")
+    .map(|m| m.content.as_str())
+    .collect::<Vec<_>>()
+    .join("\n\n");
+  let messages = req.messages
+    .iter()
+    .filter(|m| m.role != "system")
+    .map(|m| serde_json::json!({ "role": m.role, "content": m.content }))
+    .collect::<Vec<_>>();
+  let mut body = serde_json::json!({
+    "model": req.model,
+    "messages": messages,
+    "max_tokens": DEFAULT_MAX_TOKENS,
+    "stream": stream,
+    // Anthropic accepts 0..=1, the other backends up to 2
+    "temperature": config.temperature.unwrap_or(0.6).clamp(0.0, 1.0)
+  });
+  if !system.is_empty() {
+    body["system"] = serde_json::Value::String(system);
+  }
+  body
+}
+
+/// Reply of a non-streaming `/v1/messages` call, or the error it reports.
+fn parse_message(status: reqwest::StatusCode, text: &str) -> Result<ChatReply> {
+  let v = serde_json::from_str::<serde_json::Value>(text).unwrap_or(serde_json::Value::Null);
+  if !status.is_success() {
+    let msg = error_message(&v).unwrap_or_else(|| format!("anthropic error: status={} body={}", status, text));
+    return Err(HttpStatus::new(status, msg).into());
+  }
+  let blocks = v.get("content").and_then(|c| c.as_array()).cloned().unwrap_or_default();
+  let block_text = |kind: &str, field: &str| {
+    blocks.iter()
+      .filter(|b| b.get("type").and_then(|t| t.as_str()) == Some(kind))
+      .filter_map(|b| b.get(field).and_then(|t| t.as_str()))
+      .collect::<String>()
+  };
+  let content = block_text("text", "text");
+  let thinking = block_text("thinking", "thinking");
+  if content.is_empty() && thinking.is_empty() {
+    anyhow::bail!(format!("anthropic empty response: status={} body={}", status, text))
+  }
+  let usage = v.get("usage");
+  let stats = ChatStats {
+    prompt_eval_count: usage.and_then(|u| u.get("input_tokens")).and_then(|n| n.as_u64()),
+    eval_count: usage.and_then(|u| u.get("output_tokens")).and_then(|n| n.as_u64()),
+    ..Default::default()
+  };
+  Ok(ChatReply::from_parts(&thinking, &content, Some(stats)))
+}
+
+/// Applies one server-sent event to `reply` and `stats`; true once the message is complete.
+fn read_event(v: &serde_json::Value, reply: &mut ReplyAccumulator<'_, '_>, stats: &mut ChatStats) -> Result<bool> {
+  match v.get("type").and_then(|t| t.as_str()).unwrap_or("") {
+    "message_start" => {
+      stats.prompt_eval_count = v
+        .get("message")
+        .and_then(|m| m.get("usage"))
+        .and_then(|u| u.get("input_tokens"))
+        .and_then(|n| n.as_u64());
+    }
+    "content_block_delta" => {
+      let delta = v.get("delta");
+      match delta.and_then(|d| d.get("type")).and_then(|t| t.as_str()) {
+        Some("text_delta") => {
+          if let Some(text) = delta.and_then(|d| d.get("text")).and_then(|t| t.as_str()) {
+            if !text.is_empty() {
+              reply.content(text);
+            }
+          }
+        }
+        Some("thinking_delta") => {
+          if let Some(text) = delta.and_then(|d| d.get("thinking")).and_then(|t| t.as_str()) {
+            reply.thinking(text);
+          }
+        }
+        _ => {}
+      }
+    }
+    "message_delta" => {
+      if let Some(n) = v.get("usage").and_then(|u| u.get("output_tokens")).and_then(|n| n.as_u64()) {
+        stats.eval_count = Some(n);
+      }
+    }
+    "message_stop" => return Ok(true),
+    "error" => {
+      anyhow::bail!(error_message(v).unwrap_or_else(|| v.to_string()));
+    }
+    _ => {}
+  }
+  Ok(false)
+}
+
+fn error_message(v: &serde_json::Value) -> Option<String> {
+  v.get("error")
+    .and_then(|e| e.get("message"))
+    .and_then(|m| m.as_str())
+    .map(|m| m.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::providers::Delta;
+  use reqwest::StatusCode;
+
+  #[test]
+  fn parse_message_splits_text_and_thinking() {
+    let body = r#"{"content":[{"type":"thinking","thinking":"plan"},{"type":"text","text":"Hello"},{"type":"text","text":" there"}],
+      "usage":{"input_tokens":12,"output_tokens":7}}"#;
+    let reply = parse_message(StatusCode::OK, body).unwrap();
+    assert_eq!((reply.content.as_str(), reply.thinking.as_str()), ("Hello there", "plan"));
+    let stats = reply.stats.unwrap();
+    assert_eq!((stats.prompt_eval_count, stats.eval_count), (Some(12), Some(7)));
+  }
+
+  #[test]
+  fn parse_message_reports_the_error_body() {
+    let body = r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#;
+    let err = parse_message(StatusCode::UNAUTHORIZED, body).unwrap_err();
+    let http = err.downcast_ref::<HttpStatus>().unwrap();
+    assert_eq!((http.status, http.message.as_str()), (StatusCode::UNAUTHORIZED, "invalid x-api-key"));
+  }
+
+  #[test]
+  fn parse_message_rejects_an_empty_reply() {
+    let err = parse_message(StatusCode::OK, r#"{"content":[]}"#).unwrap_err();
+    assert!(err.downcast_ref::<HttpStatus>().is_none());
+    assert!(err.to_string().contains("empty response"));
+  }
+
+  #[test]
+  fn read_event_follows_a_stream() {
+    let events = [
+      r#"{"type":"message_start","message":{"usage":{"input_tokens":25}}}"#,
+      r#"{"type":"content_block_delta","delta":{"type":"thinking_delta","thinking":"hmm"}}"#,
+      r#"{"type":"content_block_delta","delta":{"type":"signature_delta","signature":"abc"}}"#,
+      r#"{"type":"content_block_delta","delta":{"type":"text_delta","text":"Hi"}}"#,
+      r#"{"type":"ping"}"#,
+      r#"{"type":"message_delta","usage":{"output_tokens":3}}"#,
+      r#"{"type":"message_stop"}"#,
+    ];
+    let mut on_delta = |_: Delta<'_>| {};
+    let mut reply = ReplyAccumulator::new(&mut on_delta);
+    let mut stats = ChatStats::default();
+    let done = events
+      .iter()
+      .map(|e| read_event(&serde_json::from_str(e).unwrap(), &mut reply, &mut stats).unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(done, [false, false, false, false, false, false, true]);
+    assert_eq!((stats.prompt_eval_count, stats.eval_count), (Some(25), Some(3)));
+    let reply = reply.finish(Some(stats));
+    assert_eq!((reply.content.as_str(), reply.thinking.as_str()), ("Hi", "hmm"));
+  }
+
+  #[test]
+  fn read_event_fails_on_an_error_event() {
+    let mut on_delta = |_: Delta<'_>| {};
+    let mut reply = ReplyAccumulator::new(&mut on_delta);
+    let event = serde_json::json!({ "type": "error", "error": { "type": "overloaded_error", "message": "Overloaded" } });
+    let err = read_event(&event, &mut reply, &mut ChatStats::default()).unwrap_err();
+    assert_eq!(err.to_string(), "Overloaded");
+  }
+}
@@ -0,0 +1,334 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::RequestBuilder;
+
+use super::{base_url, lookup, ChatProvider, ChatReply, ChatRequest, ChatStats, Delta, LineBuffer, OnDelta, ReplyAccumulator};
+use crate::http::{HttpClient, HttpStatus};
+use crate::AppConfig;
+
+/// Google Gemini `generateContent` / `streamGenerateContent` API.
+pub struct GeminiProvider;
+
+#[async_trait]
+impl ChatProvider for GeminiProvider {
+  async fn list_models(&self, client: &HttpClient, config: &AppConfig) -> Result<Vec<String>> {
+    let mut list = Vec::new();
+    let mut page_token: Option<String> = None;
+    loop {
+      let mut http = authorize(client.get(api_url(config, "models")), config).query(&[("pageSize", "1000")]);
+      if let Some(token) = &page_token {
+        http = http.query(&[("pageToken", token)]);
+      }
+      let resp = client.send(http).await?;
+      let status = resp.status();
+      let v = client.json(resp).await?;
+      if !status.is_success() {
+        let msg = error_message(&v).unwrap_or_else(|| format!("gemini models failed: status={}", status));
+        return Err(HttpStatus::new(status, msg).into());
+      }
+      if let Some(models) = v.get("models").and_then(|m| m.as_array()) {
+        list.extend(models.iter()
+          .filter(|m| {
+            m.get("supportedGenerationMethods")
+              .and_then(|s| s.as_array())
+              .map(|methods| methods.iter().any(|x| x.as_str() == Some("generateContent")))
+              .unwrap_or(true)
+          })
+          .filter_map(|m| m.get("name").and_then(|n| n.as_str()))
+          .map(|n| n.trim_start_matches("models/").to_string()));
+      }
+      page_token = v.get("nextPageToken").and_then(|t| t.as_str()).filter(|t| !t.is_empty()).map(|t| t.to_string());
+      if page_token.is_none() {
+        break;
+      }
+    }
+    Ok(list)
+  }
+
+  async fn chat(&self, client: &HttpClient, config: &AppConfig, req: &ChatRequest) -> Result<ChatReply> {
+    let url = api_url(config, &format!("models/{}:generateContent", model_id(&req.model)));
+    let resp = client.send(authorize(client.post(url), config).json(&request_body(config, req))).await?;
+    let status = resp.status();
+    let text = client.text(resp).await?;
+    let v = serde_json::from_str::<serde_json::Value>(&text).unwrap_or(serde_json::Value::Null);
+    if !status.is_success() {
+      let msg = error_message(&v).unwrap_or_else(|| format!("gemini error: status={} body={}", status, text));
+      return Err(HttpStatus::new(status, msg).into());
+    }
+    let mut ignore = |_: Delta<'_>| {};
+    let mut reply = ReplyAccumulator::new(&mut ignore);
+    read_parts(&v, &mut reply);
+    if let Some(reason) = block_reason(&v) {
+      anyhow::bail!(reason);
+    }
+    if reply.is_empty() {
+      anyhow::bail!(format!("gemini empty response: status={} body={}", status, text))
+    }
+    Ok(reply.finish(Some(usage(&v))))
+  }
+
+  async fn chat_stream(
+    &self,
+    client: &HttpClient,
+    config: &AppConfig,
+    req: &ChatRequest,
+    on_delta: &mut OnDelta<'_>,
+  ) -> Result<ChatReply> {
+    let url = api_url(config, &format!("models/{}:streamGenerateContent", model_id(&req.model)));
+    let http = authorize(client.post(url), config)
+      .query(&[("alt", "sse")])
+      .json(&request_body(config, req));
+    let resp = client.send(http).await?;
+    let status = resp.status();
+    if !status.is_success() {
+      let text = client.text(resp).await?;
+      let v = serde_json::from_str::<serde_json::Value>(&text).unwrap_or(serde_json::Value::Null);
+      let msg = error_message(&v).unwrap_or_else(|| format!("gemini error: status={} body={}", status, text));
+      return Err(HttpStatus::new(status, msg).into());
+    }
+
+    let mut stream = client.stream(resp);
+    let mut lines = LineBuffer::default();
+    let mut reply = ReplyAccumulator::new(on_delta);
+    let mut stats = None;
+    let mut blocked = None;
+    while let Some(item) = stream.next().await {
+      lines.push(&item?);
+      while let Some(line) = lines.next_line() {
+        let Some(data) = line.strip_prefix("data:") else { continue };
+        let Ok(v) = serde_json::from_str::<serde_json::Value>(data.trim()) else { continue };
+        if let Some(err) = error_message(&v) {
+          anyhow::bail!(err);
+        }
+        read_parts(&v, &mut reply);
+        if v.get("usageMetadata").is_some() {
+          stats = Some(usage(&v));
+        }
+        if let Some(reason) = block_reason(&v) {
+          blocked = Some(reason);
+        }
+      }
+    }
+    if let Some(reason) = blocked {
+      anyhow::bail!(reason);
+    }
+    if reply.is_empty() {
+      anyhow::bail!(format!("gemini empty response: status={}", status))
+    }
+    Ok(reply.finish(stats))
+  }
+
+  async fn health(&self, client: &HttpClient, config: &AppConfig) -> Result<()> {
+    let resp = client.send(authorize(client.get(api_url(config, "models")), config).query(&[("pageSize", "1")])).await?;
+    if !resp.status().is_success() {
+      anyhow::bail!(format!("gemini not ready: status={}", resp.status()));
+    }
+    Ok(())
+  }
+
+  async fn context_length(&self, _client: &HttpClient, _config: &AppConfig, model: &str) -> Option<u32> {
+    lookup(&[("gemini-1.5-pro", 2_097_152), ("gemini-1.0", 32_760), ("gemini-pro", 32_760), ("gemini", 1_048_576)], model)
+  }
+}
+
+/// `base_url` may be the bare host or already carry the API version.
+fn api_url(config: &AppConfig, path: &str) -> String {
+  let base = base_url(config);
+  if base.ends_with("/v1beta") || base.ends_with("/v1") {
+    format!("{}/{}", base, path)
+  } else {
+    format!("{}/v1beta/{}", base, path)
+  }
+}
+
+/// Model ids are accepted with or without the `models/` resource prefix.
+fn model_id(model: &str) -> &str {
+  model.trim_start_matches("models/")
+}
+
+fn authorize(req: RequestBuilder, config: &AppConfig) -> RequestBuilder {
+  match config.api_key.as_deref() {
+    Some(k) if !k.is_empty() => req.header("x-goog-api-key", k),
+    _ => req,
+  }
+}
+
+/// Maps our `{ role, content }` list onto `contents[].parts[]`; Gemini calls the
+/// assistant `model` and takes system prompts as a separate `systemInstruction`.
+fn request_body(config: &AppConfig, req: &ChatRequest) -> serde_json::Value {
+  let system = req.messages
+    .iter()
+    .filter(|m| m.role == "system")
+    .map(|m| serde_json::json!({ "text": m.content }))
+    .collect::<Vec<_>>();
+  let contents = req.messages
+    .iter()
+    .filter(|m| m.role != "system")
+    .map(|m| serde_json::json!({
+      "role": if m.role == "assistant" { "model" } else { "user" },
+      "parts": [{ "text": m.content }]
+    }))
+    .collect::<Vec<_>>();
+  let mut generation = serde_json::json!({ "temperature": config.temperature.unwrap_or(0.6) });
+  if req.think {
+    generation["thinkingConfig"] = serde_json::json!({ "includeThoughts": true });
+  } else if can_skip_thinking(&req.model) {
+    generation["thinkingConfig"] = serde_json::json!({ "thinkingBudget": 0 });
+  }
+  let mut body = serde_json::json!({ "contents": contents, "generationConfig": generation });
+  if !system.is_empty() {
+    body["systemInstruction"] = serde_json::json!({ "parts": system });
+  }
+  body
+}
+
+/// 2.5 Flash and Flash-Lite think unless given a zero budget. 2.5 Pro rejects one,
+/// and older models reject `thinkingConfig` altogether.
+fn can_skip_thinking(model: &str) -> bool {
+  model_id(model).to_ascii_lowercase().starts_with("gemini-2.5-flash")
+}
+
+/// Feeds the text parts of the first candidate to `reply`; parts flagged `thought`
+/// are reasoning summaries.
+fn read_parts(v: &serde_json::Value, reply: &mut ReplyAccumulator<'_, '_>) {
+  let Some(parts) = v
+    .get("candidates")
+    .and_then(|c| c.get(0))
+    .and_then(|c| c.get("content"))
+    .and_then(|c| c.get("parts"))
+    .and_then(|p| p.as_array())
+  else { return };
+  for part in parts {
+    let Some(text) = part.get("text").and_then(|t| t.as_str()) else { continue };
+    if text.is_empty() { continue; }
+    if part.get("thought").and_then(|t| t.as_bool()).unwrap_or(false) {
+      reply.thinking(text);
+    } else {
+      reply.content(text);
+    }
+  }
+}
+
+/// Why Gemini refused or cut off the answer, if it did.
+fn block_reason(v: &serde_json::Value) -> Option<String> {
+  if let Some(reason) = v
+    .get("promptFeedback")
+    .and_then(|f| f.get("blockReason"))
+    .and_then(|r| r.as_str())
+  {
+    return Some(format!("gemini blocked the prompt: {}", reason));
+  }
+  let candidate = v.get("candidates").and_then(|c| c.get(0))?;
+  let finish = candidate.get("finishReason").and_then(|r| r.as_str())?;
+  if !matches!(finish, "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII") {
+    return None;
+  }
+  let categories = candidate
+    .get("safetyRatings")
+    .and_then(|r| r.as_array())
+    .map(|ratings| {
+      ratings.iter()
+        .filter(|r| r.get("blocked").and_then(|b| b.as_bool()).unwrap_or(false))
+        .filter_map(|r| r.get("category").and_then(|c| c.as_str()))
+        .collect::<Vec<_>>()
+        .join(", ")
+    })
+    .unwrap_or_default();
+  if categories.is_empty() {
+    Some(format!("gemini stopped the answer: {}", finish))
+  } else {
+    Some(format!("gemini stopped the answer: {} ({})", finish, categories))
+  }
+}
+
+fn usage(v: &serde_json::Value) -> ChatStats {
+  let usage = v.get("usageMetadata");
+  let count = |key: &str| usage.and_then(|u| u.get(key)).and_then(|n| n.as_u64());
+  ChatStats {
+    prompt_eval_count: count("promptTokenCount"),
+    eval_count: match (count("candidatesTokenCount"), count("thoughtsTokenCount")) {
+      (None, None) => None,
+      (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+    },
+    ..Default::default()
+  }
+}
+
+fn error_message(v: &serde_json::Value) -> Option<String> {
+  v.get("error")
+    .and_then(|e| e.get("message"))
+    .and_then(|m| m.as_str())
+    .map(|m| m.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Message;
+
+  fn request(model: &str, think: bool) -> ChatRequest {
+    let messages = vec![Message { role: "user".into(), content: "hi".into() }];
+    ChatRequest { messages, model: model.into(), think, context: None }
+  }
+
+  #[test]
+  fn thinking_off_sets_a_zero_budget_where_allowed() {
+    let config = AppConfig::default();
+    let thinking = |model: &str, think: bool| request_body(&config, &request(model, think))["generationConfig"].get("thinkingConfig").cloned();
+    assert_eq!(thinking("gemini-2.5-flash", true), Some(serde_json::json!({ "includeThoughts": true })));
+    assert_eq!(thinking("gemini-2.5-flash", false), Some(serde_json::json!({ "thinkingBudget": 0 })));
+    assert_eq!(thinking("models/gemini-2.5-flash-lite", false), Some(serde_json::json!({ "thinkingBudget": 0 })));
+    assert_eq!(thinking("gemini-2.5-pro", false), None);
+    assert_eq!(thinking("gemini-2.0-flash", false), None);
+  }
+
+  #[test]
+  fn read_parts_separates_thought_summaries() {
+    let v = serde_json::json!({
+      "candidates": [{ "content": { "parts": [
+        { "text": "Weighing the options", "thought": true },
+        { "text": "Take the " },
+        { "text": "" },
+        { "text": "train." }
+      ] } }],
+      "usageMetadata": { "promptTokenCount": 9, "candidatesTokenCount": 4, "thoughtsTokenCount": 30 }
+    });
+    let mut on_delta = |_: Delta<'_>| {};
+    let mut reply = ReplyAccumulator::new(&mut on_delta);
+    read_parts(&v, &mut reply);
+    let reply = reply.finish(Some(usage(&v)));
+    assert_eq!((reply.content.as_str(), reply.thinking.as_str()), ("Take the train.", "Weighing the options"));
+    let stats = reply.stats.unwrap();
+    assert_eq!((stats.prompt_eval_count, stats.eval_count), (Some(9), Some(34)));
+  }
+
+  #[test]
+  fn usage_without_metadata_is_empty() {
+    let stats = usage(&serde_json::json!({ "candidates": [] }));
+    assert_eq!((stats.prompt_eval_count, stats.eval_count), (None, None));
+  }
+
+  #[test]
+  fn block_reason_names_the_cause() {
+    let prompt = serde_json::json!({ "promptFeedback": { "blockReason": "SAFETY" } });
+    assert_eq!(block_reason(&prompt).as_deref(), Some("gemini blocked the prompt: SAFETY"));
+    let answer = serde_json::json!({ "candidates": [{
+      "finishReason": "SAFETY",
+      "safetyRatings": [
+        { "category": "HARM_CATEGORY_HARASSMENT", "probability": "LOW" },
+        { "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "probability": "HIGH", "blocked": true }
+      ]
+    }] });
+    assert_eq!(block_reason(&answer).as_deref(), Some("gemini stopped the answer: SAFETY (HARM_CATEGORY_DANGEROUS_CONTENT)"));
+    let recited = serde_json::json!({ "candidates": [{ "finishReason": "RECITATION" }] });
+    assert_eq!(block_reason(&recited).as_deref(), Some("gemini stopped the answer: RECITATION"));
+  }
+
+  #[test]
+  fn block_reason_ignores_normal_stops() {
+    for finish in ["STOP", "MAX_TOKENS"] {
+      assert_eq!(block_reason(&serde_json::json!({ "candidates": [{ "finishReason": finish }] })), None);
+    }
+    assert_eq!(block_reason(&serde_json::json!({})), None);
+  }
+}
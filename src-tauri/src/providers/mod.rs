@@ -0,0 +1,330 @@
+//! Chat backends. Each provider knows how to list models, chat (blocking or
+//! streamed) and probe its server; commands look them up by `AppConfig.provider`.
+
+mod anthropic;
+mod gemini;
+mod ollama;
+mod openai;
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::http::HttpClient;
+use crate::{AppConfig, Message};
+
+/// One chat turn as sent to a provider.
+#[derive(Debug, Clone)]
+pub struct ChatRequest {
+  pub messages: Vec<Message>,
+  pub model: String,
+  pub think: bool,
+  /// Context window `messages` were fitted to, in tokens; servers that size the
+  /// window per request (Ollama) are asked for this much.
+  pub context: Option<u32>,
+}
+
+/// Token and timing counters, when the provider reports them.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatStats {
+  pub prompt_eval_count: Option<u64>,
+  pub eval_count: Option<u64>,
+  pub total_duration: Option<u64>,
+  pub eval_duration: Option<u64>,
+}
+
+/// The complete answer of a chat call, with any reasoning kept apart from the answer.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatReply {
+  pub content: String,
+  pub thinking: String,
+  pub stats: Option<ChatStats>,
+}
+
+impl ChatReply {
+  /// A reply that arrived in one piece; `thinking` is reasoning the provider reported
+  /// in its own field, and `content` may still carry inline `<think>` sections.
+  pub(crate) fn from_parts(thinking: &str, content: &str, stats: Option<ChatStats>) -> Self {
+    let mut ignore = |_: Delta<'_>| {};
+    let mut reply = ReplyAccumulator::new(&mut ignore);
+    reply.thinking(thinking);
+    reply.content(content);
+    reply.finish(stats)
+  }
+
+  /// Hands a reply that arrived in one piece to a streaming caller.
+  pub(crate) fn replay(&self, on_delta: &mut OnDelta<'_>) {
+    if !self.thinking.is_empty() {
+      on_delta(Delta::Thinking(&self.thinking));
+    }
+    if !self.content.is_empty() {
+      on_delta(Delta::Content(&self.content));
+    }
+  }
+}
+
+/// One fragment of a streamed reply.
+#[derive(Debug, Clone, Copy)]
+pub enum Delta<'a> {
+  /// Reasoning: Ollama `message.thinking`, DeepSeek `reasoning_content`, `<think>` sections, ...
+  Thinking(&'a str),
+  Content(&'a str),
+}
+
+/// Receives each fragment of a streamed reply.
+pub type OnDelta<'a> = dyn FnMut(Delta<'_>) + Send + 'a;
+
+#[async_trait]
+pub trait ChatProvider: Send + Sync {
+  /// Model ids offered by the server.
+  async fn list_models(&self, client: &HttpClient, config: &AppConfig) -> Result<Vec<String>>;
+
+  /// Sends the request and waits for the whole reply.
+  async fn chat(&self, client: &HttpClient, config: &AppConfig, req: &ChatRequest) -> Result<ChatReply>;
+
+  /// Sends the request and calls `on_delta` for every fragment as it arrives.
+  async fn chat_stream(
+    &self,
+    client: &HttpClient,
+    config: &AppConfig,
+    req: &ChatRequest,
+    on_delta: &mut OnDelta<'_>,
+  ) -> Result<ChatReply>;
+
+  /// Succeeds when the server is reachable and answering.
+  async fn health(&self, client: &HttpClient, config: &AppConfig) -> Result<()>;
+
+  /// Tokens `model` can take in (prompt plus reply), when the server or a table of
+  /// known models says.
+  async fn context_length(&self, client: &HttpClient, config: &AppConfig, model: &str) -> Option<u32>;
+}
+
+/// The context length of the first entry in `table` whose prefix `model` starts
+/// with; more specific prefixes go first.
+fn lookup(table: &[(&str, u32)], model: &str) -> Option<u32> {
+  let model = model.to_ascii_lowercase();
+  // "models/gemini-1.5-pro", "openai/gpt-4o": compare the last path segment
+  let name = model.rsplit('/').next().unwrap_or(&model);
+  table.iter().find(|(prefix, _)| name.starts_with(prefix)).map(|(_, tokens)| *tokens)
+}
+
+fn registry() -> &'static HashMap<&'static str, &'static dyn ChatProvider> {
+  static REGISTRY: OnceLock<HashMap<&'static str, &'static dyn ChatProvider>> = OnceLock::new();
+  REGISTRY.get_or_init(|| {
+    let mut providers: HashMap<&'static str, &'static dyn ChatProvider> = HashMap::new();
+    providers.insert("ollama", &ollama::OllamaProvider);
+    providers.insert("openai", &openai::OpenAiProvider { azure: false });
+    providers.insert("azure", &openai::OpenAiProvider { azure: true });
+    providers.insert("anthropic", &anthropic::AnthropicProvider);
+    providers.insert("gemini", &gemini::GeminiProvider);
+    providers
+  })
+}
+
+/// Looks up a provider by name. Unknown names get the OpenAI-compatible provider,
+/// which is how every non-Ollama server has always been treated.
+pub fn get(name: &str) -> &'static dyn ChatProvider {
+  let providers = registry();
+  providers
+    .get(name)
+    .or_else(|| providers.get("openai"))
+    .copied()
+    .expect("openai provider is always registered")
+}
+
+fn base_url(config: &AppConfig) -> &str {
+  config.base_url.trim_end_matches('/')
+}
+
+/// Splits a streamed body into lines, keeping partial lines across chunks.
+#[derive(Default)]
+struct LineBuffer {
+  buf: Vec<u8>,
+}
+
+impl LineBuffer {
+  fn push(&mut self, bytes: &[u8]) {
+    self.buf.extend_from_slice(bytes);
+  }
+
+  /// Next complete line, trimmed; `None` until a newline has arrived.
+  fn next_line(&mut self) -> Option<String> {
+    let pos = self.buf.iter().position(|b| *b == b'\n')?;
+    let line = self.buf.drain(..=pos).collect::<Vec<u8>>();
+    Some(String::from_utf8_lossy(&line).trim().to_string())
+  }
+}
+
+/// Builds a reply from provider fragments. Content is scanned for inline
+/// `<think>...</think>` sections, which are moved to the thinking channel.
+pub(crate) struct ReplyAccumulator<'s, 'f> {
+  on_delta: &'s mut OnDelta<'f>,
+  tags: ThinkTags,
+  content: String,
+  thinking: String,
+}
+
+impl<'s, 'f> ReplyAccumulator<'s, 'f> {
+  pub fn new(on_delta: &'s mut OnDelta<'f>) -> Self {
+    Self { on_delta, tags: ThinkTags::default(), content: String::new(), thinking: String::new() }
+  }
+
+  pub fn content(&mut self, text: &str) {
+    let Self { on_delta, tags, content, thinking } = self;
+    tags.feed(text, &mut |in_think, part| {
+      if in_think {
+        thinking.push_str(part);
+        on_delta(Delta::Thinking(part));
+      } else {
+        content.push_str(part);
+        on_delta(Delta::Content(part));
+      }
+    });
+  }
+
+  pub fn thinking(&mut self, text: &str) {
+    if text.is_empty() { return; }
+    self.thinking.push_str(text);
+    (self.on_delta)(Delta::Thinking(text));
+  }
+
+  /// True while neither answer nor reasoning has arrived.
+  pub fn is_empty(&self) -> bool {
+    self.content.is_empty() && self.thinking.is_empty() && self.tags.pending.is_empty()
+  }
+
+  pub fn finish(mut self, stats: Option<ChatStats>) -> ChatReply {
+    let Self { on_delta, tags, content, thinking } = &mut self;
+    tags.flush(&mut |in_think, part| {
+      if in_think {
+        thinking.push_str(part);
+        on_delta(Delta::Thinking(part));
+      } else {
+        content.push_str(part);
+        on_delta(Delta::Content(part));
+      }
+    });
+    ChatReply { content: std::mem::take(content), thinking: std::mem::take(thinking), stats }
+  }
+}
+
+/// `(content, thinking)` of a finished text with inline `<think>` sections, e.g.
+/// an answer as the frontend displays it.
+pub fn split_think(text: &str) -> (String, String) {
+  let (mut content, mut thinking) = (String::new(), String::new());
+  let mut tags = ThinkTags::default();
+  let mut out = |in_think: bool, part: &str| if in_think { thinking.push_str(part) } else { content.push_str(part) };
+  tags.feed(text, &mut out);
+  tags.flush(&mut out);
+  (content, thinking)
+}
+
+/// Splits text into think / non-think runs. A tag can be cut across chunks, so a
+/// trailing partial tag is held back until the next chunk decides what it is.
+#[derive(Default)]
+struct ThinkTags {
+  in_think: bool,
+  pending: String,
+}
+
+impl ThinkTags {
+  const OPEN: &'static str = "<think>";
+  const CLOSE: &'static str = "</think>";
+
+  fn feed(&mut self, text: &str, out: &mut dyn FnMut(bool, &str)) {
+    self.pending.push_str(text);
+    loop {
+      let tag = if self.in_think { Self::CLOSE } else { Self::OPEN };
+      if let Some(pos) = self.pending.find(tag) {
+        if pos > 0 {
+          out(self.in_think, &self.pending[..pos]);
+        }
+        self.pending.drain(..pos + tag.len());
+        self.in_think = !self.in_think;
+        continue;
+      }
+      let keep = (1..tag.len()).rev().find(|n| self.pending.ends_with(&tag[..*n])).unwrap_or(0);
+      let ready = self.pending.len() - keep;
+      if ready > 0 {
+        out(self.in_think, &self.pending[..ready]);
+        self.pending.drain(..ready);
+      }
+      return;
+    }
+  }
+
+  fn flush(&mut self, out: &mut dyn FnMut(bool, &str)) {
+    if !self.pending.is_empty() {
+      out(self.in_think, &self.pending);
+      self.pending.clear();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Feeds `chunks` through one `ThinkTags` and collects `(content, thinking)`.
+  fn feed_chunks(chunks: &[&str]) -> (String, String) {
+    let (mut content, mut thinking) = (String::new(), String::new());
+    let mut tags = ThinkTags::default();
+    let mut out = |in_think: bool, part: &str| if in_think { thinking.push_str(part) } else { content.push_str(part) };
+    for chunk in chunks {
+      tags.feed(chunk, &mut out);
+    }
+    tags.flush(&mut out);
+    (content, thinking)
+  }
+
+  #[test]
+  fn split_think_separates_reasoning() {
+    assert_eq!(split_think("<think>plan</think>answer"), ("answer".to_string(), "plan".to_string()));
+    assert_eq!(split_think("no reasoning"), ("no reasoning".to_string(), String::new()));
+    assert_eq!(split_think("a<think>b</think>c<think>d</think>e"), ("ace".to_string(), "bd".to_string()));
+  }
+
+  #[test]
+  fn split_think_keeps_unclosed_reasoning() {
+    assert_eq!(split_think("<think>still thinking"), (String::new(), "still thinking".to_string()));
+  }
+
+  #[test]
+  fn think_tags_cut_across_chunks() {
+    assert_eq!(feed_chunks(&["<thi", "nk>x</th", "ink>y"]), ("y".to_string(), "x".to_string()));
+    assert_eq!(feed_chunks(&["<", "think", ">", "x", "<", "/think>"]), (String::new(), "x".to_string()));
+  }
+
+  #[test]
+  fn think_tags_release_text_that_is_not_a_tag() {
+    assert_eq!(feed_chunks(&["a <th", "ree> b"]), ("a <three> b".to_string(), String::new()));
+    assert_eq!(feed_chunks(&["ends with <thi"]), ("ends with <thi".to_string(), String::new()));
+  }
+
+  #[test]
+  fn line_buffer_waits_for_newline() {
+    let mut lines = LineBuffer::default();
+    lines.push(b"data: a\ndata: ");
+    assert_eq!(lines.next_line().as_deref(), Some("data: a"));
+    assert_eq!(lines.next_line(), None);
+    lines.push(b"b\r\n\n");
+    assert_eq!(lines.next_line().as_deref(), Some("data: b"));
+    assert_eq!(lines.next_line().as_deref(), Some(""));
+    assert_eq!(lines.next_line(), None);
+  }
+
+  #[test]
+  fn line_buffer_joins_split_utf8() {
+    let bytes = "{\"content\":\"你好\"}\n".as_bytes();
+    let mut lines = LineBuffer::default();
+    lines.push(&bytes[..14]);
+    assert_eq!(lines.next_line(), None);
+    lines.push(&bytes[14..]);
+    assert_eq!(lines.next_line().as_deref(), Some("{\"content\":\"你好\"}"));
+  }
+}
@@ -0,0 +1,452 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{base_url, ChatProvider, ChatReply, ChatRequest, ChatStats, LineBuffer, OnDelta, ReplyAccumulator};
+use crate::http::{HttpClient, HttpStatus};
+use crate::AppConfig;
+
+/// Local Ollama server: `/api/tags`, `/api/chat` (NDJSON streaming) and `/api/generate`.
+pub struct OllamaProvider;
+
+#[async_trait]
+impl ChatProvider for OllamaProvider {
+  async fn list_models(&self, client: &HttpClient, config: &AppConfig) -> Result<Vec<String>> {
+    let url = format!("{}/api/tags", base_url(config));
+    let v = client.json(client.send(client.get(url)).await?).await?;
+    let list = v.get("models").and_then(|m| m.as_array()).map(|arr| {
+      arr.iter()
+        .filter_map(|m| m.get("name").and_then(|n| n.as_str()).map(|s| s.to_string()))
+        .collect::<Vec<String>>()
+    }).unwrap_or_default();
+    Ok(list)
+  }
+
+  async fn chat(&self, client: &HttpClient, config: &AppConfig, req: &ChatRequest) -> Result<ChatReply> {
+    // ensure model exists locally; if not, try to pull once
+    ensure_ollama_model(client, config, &req.model).await.ok();
+    let url = format!("{}/api/chat", base_url(config));
+    let mut body = serde_json::json!({
+      "model": model_value(&req.model),
+      "messages": req.messages,
+      "stream": false
+    });
+    let support = think_support(client, config, &req.model).await;
+    apply_think(&mut body, req.think, support);
+    apply_context(&mut body, req.context);
+    let resp = client.send(client.post(url).json(&body)).await?;
+    let status = resp.status();
+    let text = client.text(resp).await?;
+    // Try parse JSON and get content
+    if let Ok(v) = serde_json::from_str::<serde_json::Value>(&text) {
+      let thinking = v.get("message").and_then(|m| m.get("thinking")).and_then(|t| t.as_str()).unwrap_or("");
+      if let Some(content) = v
+        .get("message")
+        .and_then(|m| m.get("content"))
+        .and_then(|c| c.as_str())
+      {
+        if !content.is_empty() || !thinking.is_empty() {
+          return Ok(ChatReply::from_parts(thinking, content, Some(parse_stats(&v))));
+        }
+      }
+      if let Some(resp_str) = v.get("response").and_then(|c| c.as_str()) {
+        if !resp_str.is_empty() {
+          return Ok(ChatReply::from_parts("", resp_str, Some(parse_stats(&v))));
+        }
+      }
+      if !status.is_success() {
+        let err = v.get("error").and_then(|e| e.as_str()).unwrap_or(text.as_str());
+        return Err(HttpStatus::new(status, err.to_string()).into());
+      }
+    }
+    // Fallback: use /api/generate by flattening messages
+    let prompt = req.messages
+      .iter()
+      .map(|m| format!("{}: {}", m.role, m.content))
+      .collect::<Vec<_>>()
+      .join("\n");
+    let gen_url = format!("{}/api/generate", base_url(config));
+    let mut gen_body = serde_json::json!({
+      "model": model_value(&req.model),
+      "prompt": prompt,
+      "stream": false
+    });
+    apply_think(&mut gen_body, req.think, support);
+    apply_context(&mut gen_body, req.context);
+    let gen_resp = client.send(client.post(gen_url).json(&gen_body)).await?;
+    let gen_status = gen_resp.status();
+    let gen_text = client.text(gen_resp).await?;
+    if let Ok(v2) = serde_json::from_str::<serde_json::Value>(&gen_text) {
+      if let Some(resp_str) = v2.get("response").and_then(|c| c.as_str()) {
+        let thinking = v2.get("thinking").and_then(|t| t.as_str()).unwrap_or("");
+        return Ok(ChatReply::from_parts(thinking, resp_str, Some(parse_stats(&v2))));
+      }
+      if !gen_status.is_success() {
+        let err = v2.get("error").and_then(|e| e.as_str()).unwrap_or(gen_text.as_str());
+        return Err(HttpStatus::new(gen_status, err.to_string()).into());
+      }
+    }
+    anyhow::bail!(format!("ollama empty response: status={} body={}", status, text))
+  }
+
+  async fn chat_stream(
+    &self,
+    client: &HttpClient,
+    config: &AppConfig,
+    req: &ChatRequest,
+    on_delta: &mut OnDelta<'_>,
+  ) -> Result<ChatReply> {
+    // ensure model exists locally; if not, try to pull once
+    ensure_ollama_model(client, config, &req.model).await.ok();
+    let url = format!("{}/api/chat", base_url(config));
+    let mut body = serde_json::json!({
+      "model": model_value(&req.model),
+      "messages": req.messages,
+      "stream": true
+    });
+    apply_think(&mut body, req.think, think_support(client, config, &req.model).await);
+    apply_context(&mut body, req.context);
+    let resp = client.send(client.post(url).json(&body)).await?;
+    let status = resp.status();
+    if !status.is_success() {
+      let text = client.text(resp).await?;
+      if let Some(err) = serde_json::from_str::<serde_json::Value>(&text)
+        .ok()
+        .and_then(|v| v.get("error").and_then(|e| e.as_str()).map(|e| e.to_string()))
+      {
+        return Err(HttpStatus::new(status, err).into());
+      }
+      // Servers without /api/chat: fall back to the non-streaming path (uses /api/generate)
+      let reply = self.chat(client, config, req).await?;
+      reply.replay(on_delta);
+      return Ok(reply);
+    }
+
+    let mut stream = client.stream(resp);
+    let mut lines = LineBuffer::default();
+    let mut reply = ReplyAccumulator::new(on_delta);
+    let mut stats = None;
+    while let Some(item) = stream.next().await {
+      lines.push(&item?);
+      while let Some(line) = lines.next_line() {
+        if line.is_empty() { continue; }
+        let Ok(v) = serde_json::from_str::<serde_json::Value>(&line) else { continue };
+        if let Some(done) = read_line(&v, &mut reply)? {
+          stats = Some(done);
+        }
+      }
+    }
+    if reply.is_empty() {
+      anyhow::bail!(format!("ollama empty response: status={}", status))
+    }
+    Ok(reply.finish(stats))
+  }
+
+  async fn health(&self, client: &HttpClient, config: &AppConfig) -> Result<()> {
+    let resp = client.send(client.get(format!("{}/api/tags", base_url(config)))).await?;
+    if !resp.status().is_success() {
+      anyhow::bail!(format!("ollama not ready: status={}", resp.status()));
+    }
+    Ok(())
+  }
+
+  async fn context_length(&self, client: &HttpClient, config: &AppConfig, model: &str) -> Option<u32> {
+    let base = base_url(config).to_string();
+    let cached = servers().lock().ok().and_then(|cache| cache.get(&base).and_then(|info| info.context_lengths.get(model).copied()));
+    if cached.is_some() {
+      return cached;
+    }
+    show(client, &base, model).await?.context_length
+  }
+}
+
+fn model_value(model: &str) -> serde_json::Value {
+  if model.is_empty() { serde_json::Value::Null } else { serde_json::Value::String(model.to_string()) }
+}
+
+/// Counters from a final (`done: true`) chat or generate record.
+fn parse_stats(v: &serde_json::Value) -> ChatStats {
+  ChatStats {
+    prompt_eval_count: v.get("prompt_eval_count").and_then(|n| n.as_u64()),
+    eval_count: v.get("eval_count").and_then(|n| n.as_u64()),
+    total_duration: v.get("total_duration").and_then(|n| n.as_u64()),
+    eval_duration: v.get("eval_duration").and_then(|n| n.as_u64()),
+  }
+}
+
+/// Applies one NDJSON record of a streamed chat to `reply`; the counters once it is the last.
+fn read_line(v: &serde_json::Value, reply: &mut ReplyAccumulator<'_, '_>) -> Result<Option<ChatStats>> {
+  if let Some(err) = v.get("error").and_then(|e| e.as_str()) {
+    anyhow::bail!(err.to_string());
+  }
+  if let Some(message) = v.get("message") {
+    if let Some(thinking) = message.get("thinking").and_then(|t| t.as_str()) {
+      reply.thinking(thinking);
+    }
+    if let Some(delta) = message.get("content").and_then(|c| c.as_str()) {
+      if !delta.is_empty() {
+        reply.content(delta);
+      }
+    }
+  }
+  if v.get("done").and_then(|d| d.as_bool()).unwrap_or(false) {
+    return Ok(Some(parse_stats(v)));
+  }
+  Ok(None)
+}
+
+/// How a model on a given server can be told to think or not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ThinkSupport {
+  /// Ollama 0.9+ with a model that lists the `thinking` capability: top-level `think` field.
+  Native,
+  /// Older servers running Qwen3, which honours a `/no_think` soft switch in the prompt.
+  NoThinkSuffix,
+  /// Nothing to send; the model thinks (or not) on its own.
+  Unsupported,
+}
+
+/// What one server reported: its version, and the capabilities and context length
+/// of each model asked about.
+#[derive(Default)]
+struct ServerInfo {
+  version: Option<(u64, u64, u64)>,
+  capabilities: HashMap<String, Vec<String>>,
+  context_lengths: HashMap<String, u32>,
+}
+
+/// The parts of `/api/show` we use.
+struct ModelInfo {
+  capabilities: Vec<String>,
+  context_length: Option<u32>,
+}
+
+/// Asks the server about `model` and caches the answer; `None` when it could not say.
+async fn show(client: &HttpClient, base: &str, model: &str) -> Option<ModelInfo> {
+  if model.is_empty() {
+    return None;
+  }
+  let resp = client.send(client.post(format!("{}/api/show", base)).json(&serde_json::json!({ "model": model }))).await.ok()?;
+  if !resp.status().is_success() {
+    return None;
+  }
+  let v = client.json(resp).await.ok()?;
+  // servers predating capability reporting omit the field; treat that as "none"
+  let capabilities = v.get("capabilities").and_then(|c| c.as_array()).map(|arr| {
+    arr.iter().filter_map(|c| c.as_str().map(|s| s.to_string())).collect::<Vec<String>>()
+  }).unwrap_or_default();
+  // a num_ctx set in the Modelfile is the window its author chose; otherwise the
+  // length the model was trained for, e.g. model_info["llama.context_length"].
+  // Chats send it back as options.num_ctx so the server runs with that window.
+  let num_ctx = v.get("parameters").and_then(|p| p.as_str()).and_then(|params| {
+    params.lines().find_map(|line| line.trim().strip_prefix("num_ctx").and_then(|n| n.trim().parse::<u32>().ok()))
+  });
+  let trained = v.get("model_info").and_then(|m| m.as_object()).and_then(|info| {
+    info.iter().find(|(k, _)| k.ends_with(".context_length")).and_then(|(_, n)| n.as_u64()).map(|n| n as u32)
+  });
+  let info = ModelInfo { capabilities, context_length: num_ctx.or(trained) };
+  if let Ok(mut cache) = servers().lock() {
+    let server = cache.entry(base.to_string()).or_default();
+    server.capabilities.insert(model.to_string(), info.capabilities.clone());
+    if let Some(n) = info.context_length {
+      server.context_lengths.insert(model.to_string(), n);
+    }
+  }
+  Some(info)
+}
+
+/// Probe results per base URL; queried once and reused by every later chat.
+fn servers() -> &'static Mutex<HashMap<String, ServerInfo>> {
+  static SERVERS: OnceLock<Mutex<HashMap<String, ServerInfo>>> = OnceLock::new();
+  SERVERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Detects think support from `/api/version` and `/api/show`. Failed probes are not
+/// cached, so a server that was down or a model that was still being pulled is retried.
+async fn think_support(client: &HttpClient, config: &AppConfig, model: &str) -> ThinkSupport {
+  let base = base_url(config).to_string();
+  let (mut version, mut capabilities) = match servers().lock() {
+    Ok(cache) => cache.get(&base).map(|info| (info.version, info.capabilities.get(model).cloned())).unwrap_or_default(),
+    Err(_) => (None, None),
+  };
+
+  if version.is_none() {
+    if let Ok(resp) = client.send(client.get(format!("{}/api/version", base))).await {
+      if let Ok(v) = client.json(resp).await {
+        version = v.get("version").and_then(|s| s.as_str()).and_then(parse_version);
+      }
+    }
+    if let (Some(found), Ok(mut cache)) = (version, servers().lock()) {
+      cache.entry(base.clone()).or_default().version = Some(found);
+    }
+  }
+  if capabilities.is_none() {
+    capabilities = show(client, &base, model).await.map(|info| info.capabilities);
+  }
+
+  let native_api = version.map(|v| v >= (0, 9, 0)).unwrap_or(false);
+  let thinking = capabilities.map(|c| c.iter().any(|x| x == "thinking")).unwrap_or(false);
+  if native_api && thinking {
+    ThinkSupport::Native
+  } else if !native_api && model.to_ascii_lowercase().contains("qwen3") {
+    ThinkSupport::NoThinkSuffix
+  } else {
+    ThinkSupport::Unsupported
+  }
+}
+
+/// "0.9.0", "0.11.4-rc1" -> (major, minor, patch)
+fn parse_version(s: &str) -> Option<(u64, u64, u64)> {
+  let mut parts = s.trim().trim_start_matches('v').split(['.', '-', '+']).map(|p| p.parse::<u64>().ok());
+  let major = parts.next()??;
+  let minor = parts.next().flatten().unwrap_or(0);
+  let patch = parts.next().flatten().unwrap_or(0);
+  Some((major, minor, patch))
+}
+
+/// Applies the think switch to a `/api/chat` (`messages`) or `/api/generate` (`prompt`) body.
+fn apply_think(body: &mut serde_json::Value, think: bool, support: ThinkSupport) {
+  match support {
+    ThinkSupport::Native => {
+      body["think"] = serde_json::Value::Bool(think);
+    }
+    ThinkSupport::NoThinkSuffix if !think => {
+      if let Some(prompt) = body.get("prompt").and_then(|p| p.as_str()) {
+        body["prompt"] = serde_json::Value::String(format!("{} /no_think", prompt));
+      } else if let Some(last_user) = body
+        .get_mut("messages")
+        .and_then(|m| m.as_array_mut())
+        .and_then(|arr| arr.iter_mut().rev().find(|m| m.get("role").and_then(|r| r.as_str()) == Some("user")))
+      {
+        if let Some(content) = last_user.get("content").and_then(|c| c.as_str()) {
+          last_user["content"] = serde_json::Value::String(format!("{} /no_think", content));
+        }
+      }
+    }
+    ThinkSupport::NoThinkSuffix | ThinkSupport::Unsupported => {}
+  }
+}
+
+/// Sets `options.num_ctx`, keeping any other options of the body. Without it the
+/// server runs with its own default window (a few thousand tokens) and silently
+/// drops the start of a longer prompt.
+fn apply_context(body: &mut serde_json::Value, context: Option<u32>) {
+  let Some(n) = context else { return };
+  if !body["options"].is_object() {
+    body["options"] = serde_json::json!({});
+  }
+  body["options"]["num_ctx"] = n.into();
+}
+
+async fn ensure_ollama_model(client: &HttpClient, config: &AppConfig, model: &str) -> Result<()> {
+  if model.is_empty() { return Ok(()); }
+  // check tags
+  let tags_url = format!("{}/api/tags", base_url(config));
+  if let Ok(resp) = client.send(client.get(tags_url)).await {
+    if let Ok(v) = client.json(resp).await {
+      if v.get("models").and_then(|m| m.as_array()).map(|arr| {
+        arr.iter().any(|m| m.get("name").and_then(|n| n.as_str()) == Some(model))
+      }).unwrap_or(false) {
+        return Ok(());
+      }
+    }
+  }
+  // try to pull via CLI
+  #[cfg(target_os = "windows")]
+  {
+    use std::process::Command as P;
+    let exe = config.ollama_path.clone().filter(|p| !p.trim().is_empty()).unwrap_or_else(|| "ollama".to_string());
+    let _ = P::new("powershell")
+      .args([
+        "-NoProfile","-WindowStyle","Hidden","-Command",
+        &format!("Start-Process -Wait -WindowStyle Hidden -FilePath '{}' -ArgumentList 'pull \"{}\"'", exe.replace("'","''"), model.replace("'","''")),
+      ])
+      .status();
+  }
+  #[cfg(not(target_os = "windows"))]
+  {
+    use std::process::Command as P;
+    let exe = config.ollama_path.clone().filter(|p| !p.trim().is_empty()).unwrap_or_else(|| "ollama".to_string());
+    let _ = P::new(exe).arg("pull").arg(model).status();
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::providers::Delta;
+
+  #[test]
+  fn read_line_collects_a_stream() {
+    let lines = [
+      r#"{"model":"qwen3","message":{"role":"assistant","content":"","thinking":"Let me see"},"done":false}"#,
+      r#"{"model":"qwen3","message":{"role":"assistant","content":"Hel"},"done":false}"#,
+      r#"{"model":"qwen3","message":{"role":"assistant","content":"lo"},"done":false}"#,
+      r#"{"model":"qwen3","message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":26,"eval_count":5,"total_duration":900,"eval_duration":400}"#,
+    ];
+    let mut deltas = Vec::new();
+    let mut on_delta = |d: Delta<'_>| deltas.push(matches!(d, Delta::Thinking(_)));
+    let mut reply = ReplyAccumulator::new(&mut on_delta);
+    let mut stats = None;
+    for line in lines {
+      if let Some(done) = read_line(&serde_json::from_str(line).unwrap(), &mut reply).unwrap() {
+        stats = Some(done);
+      }
+    }
+    let reply = reply.finish(stats);
+    assert_eq!((reply.content.as_str(), reply.thinking.as_str()), ("Hello", "Let me see"));
+    let stats = reply.stats.unwrap();
+    assert_eq!((stats.prompt_eval_count, stats.eval_count, stats.total_duration, stats.eval_duration), (Some(26), Some(5), Some(900), Some(400)));
+    assert_eq!(deltas, [true, false, false]);
+  }
+
+  #[test]
+  fn read_line_moves_inline_think_tags() {
+    let mut on_delta = |_: Delta<'_>| {};
+    let mut reply = ReplyAccumulator::new(&mut on_delta);
+    for content in ["<think>plan", "</think>answer"] {
+      let line = serde_json::json!({ "message": { "role": "assistant", "content": content }, "done": false });
+      assert!(read_line(&line, &mut reply).unwrap().is_none());
+    }
+    let reply = reply.finish(None);
+    assert_eq!((reply.content.as_str(), reply.thinking.as_str()), ("answer", "plan"));
+  }
+
+  #[test]
+  fn read_line_fails_on_an_error_record() {
+    let mut on_delta = |_: Delta<'_>| {};
+    let mut reply = ReplyAccumulator::new(&mut on_delta);
+    let line = serde_json::json!({ "error": "model 'llama9' not found, try pulling it first" });
+    let err = read_line(&line, &mut reply).unwrap_err();
+    assert_eq!(err.to_string(), "model 'llama9' not found, try pulling it first");
+  }
+
+  #[test]
+  fn parse_version_reads_release_and_prerelease() {
+    assert_eq!(parse_version("0.9.0"), Some((0, 9, 0)));
+    assert_eq!(parse_version("0.11.4-rc1"), Some((0, 11, 4)));
+    assert_eq!(parse_version(" v1.2 "), Some((1, 2, 0)));
+    assert_eq!(parse_version("0.12.3+build.7"), Some((0, 12, 3)));
+  }
+
+  #[test]
+  fn parse_version_rejects_garbage() {
+    assert_eq!(parse_version(""), None);
+    assert_eq!(parse_version("unknown"), None);
+  }
+
+  #[test]
+  fn apply_context_keeps_other_options() {
+    let mut body = serde_json::json!({ "model": "qwen3", "options": { "temperature": 0.6 } });
+    apply_context(&mut body, Some(32_768));
+    assert_eq!(body["options"], serde_json::json!({ "temperature": 0.6, "num_ctx": 32_768 }));
+    let mut bare = serde_json::json!({ "model": "qwen3" });
+    apply_context(&mut bare, None);
+    assert!(bare.get("options").is_none());
+    apply_context(&mut bare, Some(8192));
+    assert_eq!(bare["options"], serde_json::json!({ "num_ctx": 8192 }));
+  }
+}
@@ -0,0 +1,277 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::RequestBuilder;
+
+use super::{base_url, lookup, ChatProvider, ChatReply, ChatRequest, LineBuffer, OnDelta, ReplyAccumulator};
+use crate::http::{HttpClient, HttpStatus};
+use crate::logger::{self, Level};
+use crate::redact::redact;
+use crate::AppConfig;
+
+/// Any server speaking the OpenAI `/chat/completions` + `/models` API, or Azure
+/// OpenAI, which serves the same payloads under per-deployment paths.
+pub struct OpenAiProvider {
+  pub azure: bool,
+}
+
+/// Context windows of OpenAI models by name prefix. Other OpenAI-compatible
+/// servers (vLLM, LM Studio, ...) serve unknown names and get no limit from here.
+const CONTEXT_LENGTHS: &[(&str, u32)] = &[
+  ("gpt-5", 400_000),
+  ("gpt-4.1", 1_047_576),
+  ("gpt-4o", 128_000),
+  ("gpt-4-turbo", 128_000),
+  ("gpt-4-1106", 128_000),
+  ("gpt-4-0125", 128_000),
+  ("gpt-4-32k", 32_768),
+  ("gpt-4", 8_192),
+  ("gpt-3.5-turbo-instruct", 4_096),
+  ("gpt-3.5-turbo", 16_385),
+  ("o1-mini", 128_000),
+  ("o1", 200_000),
+  ("o3", 200_000),
+  ("o4", 200_000),
+  ("gpt-oss", 131_072),
+  ("deepseek", 65_536),
+];
+
+/// Used when a model config does not set `apiVersion`.
+const AZURE_DEFAULT_API_VERSION: &str = "2024-10-21";
+/// Newer data-plane versions dropped `GET /openai/deployments`; this is the last one with it.
+const AZURE_LIST_API_VERSION: &str = "2022-12-01";
+
+#[async_trait]
+impl ChatProvider for OpenAiProvider {
+  async fn list_models(&self, client: &HttpClient, config: &AppConfig) -> Result<Vec<String>> {
+    let resp = client.send(self.authorize(client.get(self.models_url(config)), config)).await?;
+    let v = client.json(resp).await?;
+    let list = v.get("data").and_then(|m| m.as_array()).map(|arr| {
+      arr.iter()
+        .filter_map(|m| m.get("id").and_then(|n| n.as_str()).map(|s| s.to_string()))
+        .collect::<Vec<String>>()
+    }).unwrap_or_default();
+    Ok(list)
+  }
+
+  async fn chat(&self, client: &HttpClient, config: &AppConfig, req: &ChatRequest) -> Result<ChatReply> {
+    let url = self.chat_url(config, &req.model);
+    let body = serde_json::json!({
+      "model": req.model,
+      "messages": req.messages,
+      "stream": false,
+      "temperature": config.temperature.unwrap_or(0.6)
+    });
+    let log_url = redact(&url);
+    let http = client.post(url)
+      .header("Content-Type", "application/json")
+      .header("User-Agent", "TautiOllama/1.0")
+      .json(&body);
+    let resp = client.send(self.authorize(http, config)).await?;
+    let status = resp.status();
+    let text = client.text(resp).await?;
+    logger::log(Level::Debug, "chat", None, "openai response", serde_json::json!({ "url": log_url, "status": status.as_u16() }));
+    parse_completion(status, &text)
+  }
+
+  async fn chat_stream(
+    &self,
+    client: &HttpClient,
+    config: &AppConfig,
+    req: &ChatRequest,
+    on_delta: &mut OnDelta<'_>,
+  ) -> Result<ChatReply> {
+    let url = self.chat_url(config, &req.model);
+    let body = serde_json::json!({
+      "model": req.model,
+      "messages": req.messages,
+      "stream": true,
+      "temperature": config.temperature.unwrap_or(0.6)
+    });
+    let http = client.post(url)
+      .header("Accept", "text/event-stream")
+      .header("User-Agent", "TautiOllama/1.0")
+      .json(&body);
+    let resp = client.send(self.authorize(http, config)).await?;
+    let status = resp.status();
+    let is_sse = resp
+      .headers()
+      .get(reqwest::header::CONTENT_TYPE)
+      .and_then(|v| v.to_str().ok())
+      .map(|v| v.starts_with("text/event-stream"))
+      .unwrap_or(false);
+    if !status.is_success() || !is_sse {
+      // Errors and servers that ignore `stream: true` answer with a plain JSON body
+      let text = client.text(resp).await?;
+      let reply = parse_completion(status, &text)?;
+      reply.replay(on_delta);
+      return Ok(reply);
+    }
+
+    let mut stream = client.stream(resp);
+    let mut lines = LineBuffer::default();
+    let mut reply = ReplyAccumulator::new(on_delta);
+    'events: while let Some(item) = stream.next().await {
+      lines.push(&item?);
+      while let Some(line) = lines.next_line() {
+        // SSE comments (": keep-alive") and `event:`/`id:` fields carry nothing we need
+        let Some(data) = line.strip_prefix("data:") else { continue };
+        let data = data.trim();
+        if data == "[DONE]" {
+          break 'events;
+        }
+        let Ok(v) = serde_json::from_str::<serde_json::Value>(data) else { continue };
+        if let Some(err) = v.get("error") {
+          let msg = err.get("message").and_then(|m| m.as_str()).map(|s| s.to_string()).unwrap_or_else(|| err.to_string());
+          anyhow::bail!(msg);
+        }
+        let Some(delta) = v.get("choices").and_then(|c| c.get(0)).and_then(|c| c.get("delta")) else { continue };
+        reply.thinking(reasoning(delta));
+        if let Some(text) = delta.get("content").and_then(|c| c.as_str()) {
+          if !text.is_empty() {
+            reply.content(text);
+          }
+        }
+      }
+    }
+    if reply.is_empty() {
+      anyhow::bail!(format!("openai empty response: status={}", status))
+    }
+    Ok(reply.finish(None))
+  }
+
+  async fn health(&self, client: &HttpClient, config: &AppConfig) -> Result<()> {
+    let resp = client.send(self.authorize(client.get(self.models_url(config)), config)).await?;
+    if !resp.status().is_success() {
+      anyhow::bail!(format!("openai not ready: status={}", resp.status()));
+    }
+    Ok(())
+  }
+
+  async fn context_length(&self, _client: &HttpClient, _config: &AppConfig, model: &str) -> Option<u32> {
+    lookup(CONTEXT_LENGTHS, model)
+  }
+}
+
+impl OpenAiProvider {
+  fn chat_url(&self, config: &AppConfig, model: &str) -> String {
+    if self.azure {
+      format!(
+        "{}/openai/deployments/{}/chat/completions?api-version={}",
+        base_url(config),
+        azure_deployment(config, model),
+        config.api_version.as_deref().filter(|v| !v.is_empty()).unwrap_or(AZURE_DEFAULT_API_VERSION)
+      )
+    } else {
+      format!("{}/chat/completions", base_url(config))
+    }
+  }
+
+  /// Azure lists deployments rather than models, since chats are addressed by deployment.
+  /// Otherwise `base_url` is used as-is for `/chat/completions`, so it may or may not
+  /// already end in `/v1`.
+  fn models_url(&self, config: &AppConfig) -> String {
+    let base = base_url(config);
+    if self.azure {
+      format!("{}/openai/deployments?api-version={}", base, AZURE_LIST_API_VERSION)
+    } else if base.ends_with("/v1") {
+      format!("{}/models", base)
+    } else {
+      format!("{}/v1/models", base)
+    }
+  }
+
+  fn authorize(&self, req: RequestBuilder, config: &AppConfig) -> RequestBuilder {
+    match config.api_key.as_deref() {
+      Some(k) if !k.is_empty() && self.azure => req.header("api-key", k),
+      Some(k) if !k.is_empty() => req.bearer_auth(k),
+      _ => req,
+    }
+  }
+}
+
+fn azure_deployment<'a>(config: &'a AppConfig, model: &'a str) -> &'a str {
+  config.deployment.as_deref().filter(|d| !d.is_empty()).unwrap_or(model)
+}
+
+/// Reads a non-streamed `/chat/completions` body, or the error it carries.
+fn parse_completion(status: reqwest::StatusCode, text: &str) -> Result<ChatReply> {
+  if let Ok(v) = serde_json::from_str::<serde_json::Value>(text) {
+    if let Some(message) = v.get("choices").and_then(|c| c.get(0)).and_then(|c| c.get("message")) {
+      let content = message.get("content").and_then(|c| c.as_str()).unwrap_or("");
+      let thinking = reasoning(message);
+      if !content.is_empty() || !thinking.is_empty() {
+        return Ok(ChatReply::from_parts(thinking, content, None));
+      }
+    }
+    if !status.is_success() {
+      let err = v.get("error").and_then(|e| e.get("message")).and_then(|m| m.as_str()).unwrap_or(text);
+      return Err(HttpStatus::new(status, err.to_string()).into());
+    }
+  }
+  anyhow::bail!(format!("openai empty response: status={} body={}", status, text))
+}
+
+/// DeepSeek and vLLM send reasoning as `reasoning_content`, OpenRouter as `reasoning`.
+fn reasoning(message: &serde_json::Value) -> &str {
+  message
+    .get("reasoning_content")
+    .or_else(|| message.get("reasoning"))
+    .and_then(|r| r.as_str())
+    .unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use reqwest::StatusCode;
+
+  #[test]
+  fn parse_completion_reads_content_and_reasoning() {
+    let body = r#"{"choices":[{"message":{"role":"assistant","content":"4","reasoning_content":"2+2"}}]}"#;
+    let reply = parse_completion(StatusCode::OK, body).unwrap();
+    assert_eq!((reply.content.as_str(), reply.thinking.as_str()), ("4", "2+2"));
+  }
+
+  #[test]
+  fn parse_completion_accepts_reasoning_only() {
+    let body = r#"{"choices":[{"message":{"role":"assistant","content":null,"reasoning":"ran out of tokens while thinking"}}]}"#;
+    let reply = parse_completion(StatusCode::OK, body).unwrap();
+    assert_eq!((reply.content.as_str(), reply.thinking.as_str()), ("", "ran out of tokens while thinking"));
+  }
+
+  #[test]
+  fn parse_completion_reports_the_error_body() {
+    let body = r#"{"error":{"message":"Rate limit reached for gpt-4o","type":"requests"}}"#;
+    let err = parse_completion(StatusCode::TOO_MANY_REQUESTS, body).unwrap_err();
+    let http = err.downcast_ref::<HttpStatus>().unwrap();
+    assert_eq!((http.status, http.message.as_str()), (StatusCode::TOO_MANY_REQUESTS, "Rate limit reached for gpt-4o"));
+  }
+
+  #[test]
+  fn parse_completion_rejects_an_empty_body() {
+    for body in ["", r#"{"choices":[{"message":{"content":""}}]}"#] {
+      let err = parse_completion(StatusCode::OK, body).unwrap_err();
+      assert!(err.downcast_ref::<HttpStatus>().is_none());
+      assert!(err.to_string().starts_with("openai empty response"), "{}", err);
+    }
+  }
+
+  #[test]
+  fn context_lengths_prefer_the_longest_prefix() {
+    assert_eq!(lookup(CONTEXT_LENGTHS, "gpt-4o"), Some(128_000));
+    assert_eq!(lookup(CONTEXT_LENGTHS, "gpt-4o-mini-2024-07-18"), Some(128_000));
+    assert_eq!(lookup(CONTEXT_LENGTHS, "gpt-4.1-nano"), Some(1_047_576));
+    assert_eq!(lookup(CONTEXT_LENGTHS, "gpt-4-0613"), Some(8_192));
+    assert_eq!(lookup(CONTEXT_LENGTHS, "gpt-4-32k"), Some(32_768));
+    assert_eq!(lookup(CONTEXT_LENGTHS, "o1-mini"), Some(128_000));
+    assert_eq!(lookup(CONTEXT_LENGTHS, "o1-preview"), Some(200_000));
+    assert_eq!(lookup(CONTEXT_LENGTHS, "gpt-3.5-turbo-instruct"), Some(4_096));
+  }
+
+  #[test]
+  fn context_lengths_ignore_case_and_vendor_prefix() {
+    assert_eq!(lookup(CONTEXT_LENGTHS, "openai/GPT-4o"), Some(128_000));
+    assert_eq!(lookup(CONTEXT_LENGTHS, "llama3.1:8b"), None);
+  }
+}
@@ -0,0 +1,176 @@
+//! Masks secrets before text reaches stderr or the log file.
+
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock};
+
+use regex::Regex;
+
+use crate::AppConfig;
+
+const MASK: &str = "[REDACTED]";
+
+/// API keys seen in configs so far; they are masked wherever they appear later.
+fn secrets() -> &'static Mutex<HashSet<String>> {
+  static SECRETS: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+  SECRETS.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+/// Patterns for credentials whose values we have not been told about: auth
+/// headers, `apiKey`-style fields, `?key=` query parameters and well-known key shapes.
+fn patterns() -> &'static [(Regex, &'static str)] {
+  static PATTERNS: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+  PATTERNS.get_or_init(|| {
+    [
+      (r#"(?i)(authorization"?\s*[:=]\s*"?(?:bearer\s+|basic\s+)?)[^\s",}]+"#, "${1}[REDACTED]"),
+      // `\b` keeps `hasApiKey` and `apiKeyLength` (what the UI logs instead of the key) readable
+      (r#"(?i)(\b(?:x-goog-api-key|x-api-key|api-key|api_key|apikey)"?\s*[:=]\s*"?)[^\s",&}]+"#, "${1}[REDACTED]"),
+      (r#"(?i)([?&]key=)[^&\s"]+"#, "${1}[REDACTED]"),
+      (r"\bsk-[A-Za-z0-9_\-]{8,}", MASK),
+      (r"\bAIza[0-9A-Za-z_\-]{20,}", MASK),
+    ]
+    .into_iter()
+    .map(|(re, with)| (Regex::new(re).expect("redaction pattern"), with))
+    .collect()
+  })
+}
+
+/// Remembers the API keys of `config` and of every entry in `config.models`.
+pub fn remember(config: &AppConfig) {
+  let keys = std::iter::once(&config.api_key)
+    .chain(config.models.iter().flatten().map(|m| &m.api_key))
+    .flatten()
+    // very short values would mask ordinary words
+    .filter(|k| k.len() >= 8);
+  if let Ok(mut known) = secrets().lock() {
+    known.extend(keys.cloned());
+  }
+}
+
+/// Remembers a single key, e.g. one just stored in the secret store.
+pub fn remember_key(key: &str) {
+  if key.len() >= 8 {
+    if let Ok(mut known) = secrets().lock() {
+      known.insert(key.to_string());
+    }
+  }
+}
+
+/// `text` with every known key and anything that looks like a credential masked.
+pub fn redact(text: &str) -> String {
+  let mut out = text.to_string();
+  if let Ok(known) = secrets().lock() {
+    for secret in known.iter() {
+      if out.contains(secret.as_str()) {
+        out = out.replace(secret.as_str(), MASK);
+      }
+    }
+  }
+  for (re, with) in patterns() {
+    if re.is_match(&out) {
+      out = re.replace_all(&out, *with).into_owned();
+    }
+  }
+  out
+}
+
+/// Field names whose values are credentials whatever they look like.
+fn is_secret_field(name: &str) -> bool {
+  let name = name.to_ascii_lowercase().replace(['-', '_'], "");
+  matches!(name.as_str(), "apikey" | "xapikey" | "xgoogapikey" | "authorization" | "passphrase" | "password")
+}
+
+/// Masks credentials inside every string of `fields`, and the whole string value of
+/// fields named like a credential. Keys, numbers and booleans are left alone, so
+/// the result is still the same shape of JSON.
+pub fn redact_fields(fields: &mut serde_json::Map<String, serde_json::Value>) {
+  for (key, value) in fields.iter_mut() {
+    if is_secret_field(key) && value.is_string() {
+      *value = serde_json::Value::String(MASK.to_string());
+    } else {
+      redact_value(value);
+    }
+  }
+}
+
+fn redact_value(value: &mut serde_json::Value) {
+  use serde_json::Value;
+  match value {
+    Value::String(s) => *s = redact(s),
+    Value::Array(items) => items.iter_mut().for_each(redact_value),
+    Value::Object(map) => redact_fields(map),
+    _ => {}
+  }
+}
+
+/// A chat message as it may appear in logs: verbatim only when the user opted in.
+pub fn prompt(text: &str, config: &AppConfig) -> String {
+  if config.log_prompts {
+    text.to_string()
+  } else {
+    format!("<{} chars>", text.chars().count())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn masks_auth_headers() {
+    assert_eq!(redact("Authorization: Bearer abc.def-123"), "Authorization: Bearer [REDACTED]");
+    assert_eq!(redact(r#"{"authorization":"Basic dXNlcjpwYXNz"}"#), r#"{"authorization":"Basic [REDACTED]"}"#);
+    assert_eq!(redact("x-goog-api-key: goog-value-1"), "x-goog-api-key: [REDACTED]");
+    assert_eq!(redact("api-key=azure-value-1&x=1"), "api-key=[REDACTED]&x=1");
+    assert_eq!(redact(r#"{"x-api-key":"anthropic-value"}"#), r#"{"x-api-key":"[REDACTED]"}"#);
+  }
+
+  #[test]
+  fn masks_key_shapes_and_query_keys() {
+    assert_eq!(redact("key sk-proj-AbC123_xyz-789 failed"), "key [REDACTED] failed");
+    assert_eq!(
+      redact("GET https://generativelanguage.googleapis.com/v1beta/models?key=AIzaSyA1b2C3d4E5f6G7h8I9j0&alt=sse"),
+      "GET https://generativelanguage.googleapis.com/v1beta/models?key=[REDACTED]&alt=sse"
+    );
+    assert_eq!(redact("found AIzaSyA1b2C3d4E5f6G7h8I9j0K in text"), "found [REDACTED] in text");
+    // too short to be a key
+    assert_eq!(redact("task-sk-1"), "task-sk-1");
+  }
+
+  #[test]
+  fn masks_remembered_keys() {
+    remember_key("plain-looking-secret-value");
+    remember_key("short");
+    assert_eq!(redact("sent plain-looking-secret-value to the server"), "sent [REDACTED] to the server");
+    assert_eq!(redact("a short word"), "a short word");
+  }
+
+  #[test]
+  fn leaves_key_facts_alone() {
+    let line = r#"{"hasApiKey":true,"apiKeyLength":51,"provider":"openai"}"#;
+    assert_eq!(redact(line), line);
+    assert_eq!(redact("hasApiKey=false apiKeyLength=0"), "hasApiKey=false apiKeyLength=0");
+    assert_eq!(redact("monkey=banana"), "monkey=banana");
+  }
+
+  #[test]
+  fn redact_fields_keeps_the_shape() {
+    let mut fields = serde_json::json!({
+      "hasApiKey": true,
+      "apiKeyLength": 51,
+      "apiKey": "no-recognisable-shape",
+      "config": { "api_key": "nested-value", "baseUrl": "https://x.test/v1?key=AIzaSyA1b2C3d4E5f6G7h8I9j0" },
+      "headers": ["Authorization: Bearer t0ken"],
+      "password": null,
+    });
+    let serde_json::Value::Object(map) = &mut fields else { unreachable!() };
+    redact_fields(map);
+    assert_eq!(fields, serde_json::json!({
+      "hasApiKey": true,
+      "apiKeyLength": 51,
+      "apiKey": "[REDACTED]",
+      "config": { "api_key": "[REDACTED]", "baseUrl": "https://x.test/v1?key=[REDACTED]" },
+      "headers": ["Authorization: Bearer [REDACTED]"],
+      "password": null,
+    }));
+  }
+}
@@ -0,0 +1,219 @@
+//! Retrying provider calls that failed for reasons likely to go away on their own,
+//! and recognising failures that call for a different model instead.
+
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::http::{HttpStatus, Timeout, TimeoutPhase};
+
+/// Longest `Retry-After` worth sitting through; past this the error is reported instead.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// `AppConfig.retry`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RetryPolicy {
+  /// Total attempts including the first; 1 disables retrying.
+  pub max_attempts: u32,
+  /// Wait before the second attempt; doubled for each one after.
+  pub base_delay_ms: u64,
+  pub max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    Self { max_attempts: 3, base_delay_ms: 500, max_delay_ms: 8000 }
+  }
+}
+
+/// Payload of `chat-retry:{id}`, sent before waiting for attempt `attempt`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryNotice {
+  pub attempt: u32,
+  pub max_attempts: u32,
+  pub delay_ms: u64,
+  pub error: String,
+}
+
+impl RetryPolicy {
+  /// Attempt `attempt` (1-based) failed with `err`: how long to wait before the
+  /// next one, or `None` when the error is not transient or attempts are used up.
+  pub fn next_delay(&self, attempt: u32, err: &anyhow::Error) -> Option<Duration> {
+    if attempt >= self.max_attempts {
+      return None;
+    }
+    match transient(err)? {
+      Some(wait) if wait > MAX_RETRY_AFTER => None,
+      Some(wait) => Some(wait),
+      None => Some(self.backoff(attempt)),
+    }
+  }
+
+  /// [`RetryPolicy::next_delay`] for a streamed call, which is never retried once
+  /// part of the answer is on screen: a second attempt would repeat it.
+  pub fn next_stream_delay(&self, attempt: u32, streamed: bool, err: &anyhow::Error) -> Option<Duration> {
+    if streamed {
+      return None;
+    }
+    self.next_delay(attempt, err)
+  }
+
+  pub fn notice(&self, attempt: u32, delay: Duration, err: &anyhow::Error) -> RetryNotice {
+    RetryNotice {
+      attempt,
+      max_attempts: self.max_attempts,
+      delay_ms: delay.as_millis() as u64,
+      error: err.to_string(),
+    }
+  }
+
+  /// Runs `call` until it succeeds or fails for good. Only for calls that produce
+  /// nothing until they complete; streamed calls check [`RetryPolicy::next_stream_delay`]
+  /// themselves so they never retry once output has been shown.
+  pub async fn run<T, F, Fut>(&self, mut call: F, mut on_retry: impl FnMut(&RetryNotice)) -> Result<T>
+  where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+  {
+    let mut attempt = 1;
+    loop {
+      let err = match call().await {
+        Ok(value) => return Ok(value),
+        Err(err) => err,
+      };
+      let Some(delay) = self.next_delay(attempt, &err) else { return Err(err) };
+      attempt += 1;
+      on_retry(&self.notice(attempt, delay, &err));
+      tokio::time::sleep(delay).await;
+    }
+  }
+
+  /// Exponential backoff with "equal jitter": half the step is fixed, half random,
+  /// so clients that failed together do not retry together.
+  fn backoff(&self, attempt: u32) -> Duration {
+    let step = self
+      .base_delay_ms
+      .saturating_mul(1u64 << (attempt - 1).min(20))
+      .min(self.max_delay_ms);
+    let half = step / 2;
+    Duration::from_millis(half + RandomState::new().hash_one(attempt) % (step - half + 1))
+  }
+}
+
+/// `Some(retry_after)` when `err` is worth retrying: a rate-limit or gateway status,
+/// a connect timeout, or a connection dropped by the peer.
+fn transient(err: &anyhow::Error) -> Option<Option<Duration>> {
+  if let Some(status) = err.downcast_ref::<HttpStatus>() {
+    return status.is_transient().then_some(status.retry_after);
+  }
+  if let Some(timeout) = err.downcast_ref::<Timeout>() {
+    // a slow first byte or a stalled body is the model being slow; asking again won't help
+    return (timeout.phase == TimeoutPhase::Connect).then_some(None);
+  }
+  let dropped = err.chain().any(|cause| {
+    cause.downcast_ref::<std::io::Error>().is_some_and(|io| {
+      matches!(
+        io.kind(),
+        std::io::ErrorKind::ConnectionReset | std::io::ErrorKind::ConnectionAborted | std::io::ErrorKind::BrokenPipe
+      )
+    })
+  });
+  dropped.then_some(None)
+}
+
+/// Failures another model may not share: the server is unreachable, the model is
+/// missing, or quota / rate limits are used up (after any retries).
+pub fn should_fall_back(err: &anyhow::Error) -> bool {
+  if let Some(status) = err.downcast_ref::<HttpStatus>() {
+    return status.is_transient()
+      || matches!(status.status.as_u16(), 402 | 404)
+      || status.message.to_ascii_lowercase().contains("quota");
+  }
+  if let Some(timeout) = err.downcast_ref::<Timeout>() {
+    return timeout.phase == TimeoutPhase::Connect;
+  }
+  err.chain().any(|cause| cause.downcast_ref::<reqwest::Error>().is_some_and(|e| e.is_connect()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use reqwest::StatusCode;
+
+  fn status(code: u16) -> anyhow::Error {
+    HttpStatus::new(StatusCode::from_u16(code).unwrap(), "failed").into()
+  }
+
+  fn policy() -> RetryPolicy {
+    RetryPolicy { max_attempts: 20, ..Default::default() }
+  }
+
+  #[test]
+  fn backoff_uses_equal_jitter_under_the_cap() {
+    let policy = policy();
+    for attempt in 1..15 {
+      let step = (500u64 << (attempt - 1)).min(8000);
+      for _ in 0..20 {
+        let delay = policy.next_delay(attempt, &status(503)).unwrap().as_millis() as u64;
+        assert!((step / 2..=step).contains(&delay), "attempt {}: {} not in {}..={}", attempt, delay, step / 2, step);
+      }
+    }
+  }
+
+  #[test]
+  fn retry_after_overrides_backoff() {
+    let policy = policy();
+    let wait = |secs: u64| -> anyhow::Error {
+      HttpStatus { retry_after: Some(Duration::from_secs(secs)), ..HttpStatus::new(StatusCode::TOO_MANY_REQUESTS, "slow down") }.into()
+    };
+    assert_eq!(policy.next_delay(1, &wait(20)), Some(Duration::from_secs(20)));
+    assert_eq!(policy.next_delay(1, &wait(0)), Some(Duration::ZERO));
+    // too long to wait for; reported so the caller can fall back instead
+    assert_eq!(policy.next_delay(1, &wait(120)), None);
+  }
+
+  #[test]
+  fn client_errors_other_than_429_are_final() {
+    let policy = policy();
+    for code in [400, 401, 403, 404, 422, 500] {
+      assert_eq!(policy.next_delay(1, &status(code)), None, "{}", code);
+    }
+    for code in [429, 502, 503, 504] {
+      assert!(policy.next_delay(1, &status(code)).is_some(), "{}", code);
+    }
+  }
+
+  #[test]
+  fn attempts_run_out() {
+    let policy = RetryPolicy { max_attempts: 3, ..Default::default() };
+    assert!(policy.next_delay(2, &status(503)).is_some());
+    assert_eq!(policy.next_delay(3, &status(503)), None);
+    let disabled = RetryPolicy { max_attempts: 1, ..Default::default() };
+    assert_eq!(disabled.next_delay(1, &status(503)), None);
+  }
+
+  #[test]
+  fn streamed_output_is_never_retried() {
+    let policy = policy();
+    assert!(policy.next_stream_delay(1, false, &status(503)).is_some());
+    assert_eq!(policy.next_stream_delay(1, true, &status(503)), None);
+    let reset: anyhow::Error = std::io::Error::from(std::io::ErrorKind::ConnectionReset).into();
+    assert!(policy.next_stream_delay(1, false, &reset).is_some());
+    assert_eq!(policy.next_stream_delay(1, true, &reset), None);
+  }
+
+  #[test]
+  fn only_connect_timeouts_are_retried() {
+    let policy = policy();
+    let timeout = |phase| -> anyhow::Error { Timeout { phase, after: Duration::from_secs(10) }.into() };
+    assert!(policy.next_delay(1, &timeout(TimeoutPhase::Connect)).is_some());
+    assert_eq!(policy.next_delay(1, &timeout(TimeoutPhase::FirstByte)), None);
+    assert_eq!(policy.next_delay(1, &timeout(TimeoutPhase::Idle)), None);
+  }
+}
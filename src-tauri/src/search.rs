@@ -0,0 +1,316 @@
+//! Full-text search over conversation messages. SQLite's tokenizers split text on
+//! spaces, which leaves Chinese and Japanese as one token per sentence, so text is
+//! tokenized here instead: words for alphabetic scripts, and single characters plus
+//! overlapping pairs for CJK. The FTS5 table `message_fts` holds those tokens.
+
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+
+use crate::error::AppError;
+
+/// Characters of context kept around the first match in a snippet.
+const SNIPPET_CHARS: usize = 160;
+const DEFAULT_LIMIT: usize = 50;
+
+/// One matching message.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+  pub conversation_id: String,
+  pub conversation_title: String,
+  /// Insertion order of the message in its conversation; its index there as long
+  /// as the conversation never branched.
+  pub message_index: i64,
+  /// Pass to `switch_branch` to show a message that is not on the active branch.
+  pub message_id: i64,
+  pub role: String,
+  /// HTML-escaped excerpt with matches wrapped in `<mark>`.
+  pub snippet: String,
+  /// Higher is better.
+  pub score: f64,
+  pub created_at: i64,
+}
+
+pub(crate) fn is_cjk(c: char) -> bool {
+  matches!(c as u32,
+    0x3040..=0x30FF      // Hiragana, Katakana
+    | 0x3400..=0x4DBF    // CJK Extension A
+    | 0x4E00..=0x9FFF    // CJK Unified Ideographs
+    | 0xAC00..=0xD7AF    // Hangul syllables
+    | 0xF900..=0xFAFF    // CJK Compatibility Ideographs
+    | 0x20000..=0x2FA1F) // Extensions B-F, compatibility supplement
+}
+
+/// Runs of word characters, each either entirely CJK or entirely not.
+fn runs(text: &str) -> Vec<(bool, String)> {
+  let mut out: Vec<(bool, String)> = Vec::new();
+  for c in text.chars() {
+    if !c.is_alphanumeric() {
+      if out.last().is_some_and(|(_, run)| !run.is_empty()) {
+        out.push((false, String::new()));
+      }
+      continue;
+    }
+    let cjk = is_cjk(c);
+    match out.last_mut() {
+      Some((kind, run)) if *kind == cjk || run.is_empty() => {
+        *kind = cjk;
+        run.extend(c.to_lowercase());
+      }
+      _ => out.push((cjk, c.to_lowercase().collect())),
+    }
+  }
+  out.retain(|(_, run)| !run.is_empty());
+  out
+}
+
+/// Index form of `text`: space-separated words, CJK unigrams and CJK bigrams.
+pub fn index_tokens(text: &str) -> String {
+  let mut out = Vec::new();
+  for (cjk, run) in runs(text) {
+    if !cjk {
+      out.push(run);
+      continue;
+    }
+    let chars: Vec<char> = run.chars().collect();
+    out.extend(chars.iter().map(|c| c.to_string()));
+    out.extend(chars.windows(2).map(|w| w.iter().collect::<String>()));
+  }
+  out.join(" ")
+}
+
+/// Terms a query has to match: words, and for CJK the pairs of the run (or the
+/// single character when that is all there is).
+fn query_terms(query: &str) -> Vec<String> {
+  let mut terms = Vec::new();
+  for (cjk, run) in runs(query) {
+    let chars: Vec<char> = run.chars().collect();
+    if cjk && chars.len() > 1 {
+      terms.extend(chars.windows(2).map(|w| w.iter().collect::<String>()));
+    } else {
+      terms.push(run);
+    }
+  }
+  terms.dedup();
+  terms
+}
+
+/// FTS5 MATCH expression requiring every term; the last word also matches as a
+/// prefix so results appear while typing. Terms are quoted strings, so operators
+/// and column filters in them are plain text.
+fn match_expression(terms: &[String]) -> String {
+  let last = terms.len().saturating_sub(1);
+  terms
+    .iter()
+    .enumerate()
+    .map(|(i, t)| {
+      let prefix = i == last && !t.chars().any(is_cjk);
+      format!("\"{}\"{}", t.replace('"', "\"\""), if prefix { "*" } else { "" })
+    })
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// Makes `search_tokens(text)` available to SQL; the triggers that keep
+/// `message_fts` current call it, so it is registered before anything else runs.
+pub fn register(conn: &Connection) -> Result<(), AppError> {
+  conn.create_scalar_function(
+    "search_tokens",
+    1,
+    FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+    |ctx| Ok(index_tokens(&ctx.get::<String>(0)?)),
+  )?;
+  Ok(())
+}
+
+/// Schema step that adds the index, keeps it in step with `messages` and fills it.
+pub const SCHEMA: &str = "
+  CREATE VIRTUAL TABLE message_fts USING fts5(tokens);
+  CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages BEGIN
+    INSERT INTO message_fts (rowid, tokens) VALUES (new.id, search_tokens(new.content));
+  END;
+  CREATE TRIGGER messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+    UPDATE message_fts SET tokens = search_tokens(new.content) WHERE rowid = new.id;
+  END;
+  CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages BEGIN
+    DELETE FROM message_fts WHERE rowid = old.id;
+  END;
+  INSERT INTO message_fts (rowid, tokens) SELECT id, search_tokens(content) FROM messages;
+";
+
+/// Messages matching every term of `query`, best first.
+pub fn search(conn: &Connection, query: &str, limit: Option<usize>) -> Result<Vec<SearchHit>, AppError> {
+  let terms = query_terms(query);
+  if terms.is_empty() {
+    return Ok(Vec::new());
+  }
+  let mut stmt = conn.prepare(
+    "SELECT m.conversation_id, c.title, m.position, m.role, m.content, bm25(message_fts), m.created_at, m.id
+     FROM message_fts
+     JOIN messages m ON m.id = message_fts.rowid
+     JOIN conversations c ON c.id = m.conversation_id
+     WHERE message_fts MATCH ?1
+     ORDER BY bm25(message_fts), m.created_at DESC
+     LIMIT ?2",
+  )?;
+  let limit = limit.unwrap_or(DEFAULT_LIMIT) as i64;
+  let rows = stmt.query_map(params![match_expression(&terms), limit], |row| {
+    let content: String = row.get(4)?;
+    let bm25: f64 = row.get(5)?;
+    Ok(SearchHit {
+      conversation_id: row.get(0)?,
+      conversation_title: row.get(1)?,
+      message_index: row.get(2)?,
+      role: row.get(3)?,
+      snippet: snippet(&content, &terms),
+      score: -bm25,
+      created_at: row.get(6)?,
+      message_id: row.get(7)?,
+    })
+  })?;
+  Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// An excerpt of `text` around the first term found, with every term occurrence marked.
+fn snippet(text: &str, terms: &[String]) -> String {
+  let chars: Vec<char> = text.chars().collect();
+  let lower: Vec<char> = chars.iter().map(|c| c.to_lowercase().next().unwrap_or(*c)).collect();
+  let term_chars: Vec<Vec<char>> = terms.iter().map(|t| t.chars().collect()).collect();
+  let match_len = |i: usize| term_chars.iter().filter(|t| lower[i..].starts_with(t)).map(|t| t.len()).max();
+  // marked[i] is true when character i is part of a match
+  let mut marked = vec![false; chars.len()];
+  let mut first = None;
+  for i in 0..chars.len() {
+    if let Some(n) = match_len(i) {
+      first.get_or_insert(i);
+      marked[i..i + n].iter_mut().for_each(|m| *m = true);
+    }
+  }
+  let start = first.map_or(0, |f| f.saturating_sub(SNIPPET_CHARS / 4));
+  let end = (start + SNIPPET_CHARS).min(chars.len());
+  let mut out = String::new();
+  if start > 0 {
+    out.push('…');
+  }
+  let mut open = false;
+  for i in start..end {
+    if marked[i] != open {
+      out.push_str(if open { "</mark>" } else { "<mark>" });
+      open = marked[i];
+    }
+    match chars[i] {
+      '<' => out.push_str("&lt;"),
+      '>' => out.push_str("&gt;"),
+      '&' => out.push_str("&amp;"),
+      '"' => out.push_str("&quot;"),
+      '\n' | '\r' | '\t' => out.push(' '),
+      c => out.push(c),
+    }
+  }
+  if open {
+    out.push_str("</mark>");
+  }
+  if end < chars.len() {
+    out.push('…');
+  }
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn terms(list: &[&str]) -> Vec<String> {
+    list.iter().map(|t| t.to_string()).collect()
+  }
+
+  #[test]
+  fn index_tokens_split_mixed_scripts() {
+    assert_eq!(index_tokens("Hello, 世界！Rust编程"), "hello 世 界 世界 rust 编 程 编程");
+    assert_eq!(index_tokens("한국어 v2"), "한 국 어 한국 국어 v2");
+  }
+
+  #[test]
+  fn index_tokens_drop_punctuation() {
+    assert_eq!(index_tokens("don't -- stop!!! (ok)"), "don t stop ok");
+    assert_eq!(index_tokens(""), "");
+    assert_eq!(index_tokens("?! … ——"), "");
+  }
+
+  #[test]
+  fn query_terms_pair_cjk_characters() {
+    assert_eq!(query_terms("世界和平 Rust"), terms(&["世界", "界和", "和平", "rust"]));
+    assert_eq!(query_terms("世"), terms(&["世"]));
+    assert_eq!(query_terms("rust rust"), terms(&["rust"]));
+    assert!(query_terms("").is_empty());
+    assert!(query_terms("  ,;  ").is_empty());
+  }
+
+  #[test]
+  fn query_terms_strip_fts_syntax() {
+    assert_eq!(
+      query_terms("title:foo AND (bar* OR -baz) NEAR \"qux\""),
+      terms(&["title", "foo", "and", "bar", "or", "baz", "near", "qux"])
+    );
+  }
+
+  #[test]
+  fn match_expression_quotes_every_term() {
+    assert_eq!(match_expression(&terms(&["hello", "wor"])), "\"hello\" \"wor\"*");
+    // a CJK pair is already a whole term
+    assert_eq!(match_expression(&terms(&["rust", "编程"])), "\"rust\" \"编程\"");
+    assert_eq!(match_expression(&terms(&["or", "near"])), "\"or\" \"near\"*");
+    assert_eq!(match_expression(&terms(&["say\"hi"])), "\"say\"\"hi\"*");
+    assert_eq!(match_expression(&[]), "");
+  }
+
+  #[test]
+  fn search_accepts_fts_operators_as_text() {
+    let conn = Connection::open_in_memory().unwrap();
+    register(&conn).unwrap();
+    conn.execute_batch("
+      CREATE TABLE conversations (id TEXT PRIMARY KEY, title TEXT NOT NULL);
+      CREATE TABLE messages (
+        id INTEGER PRIMARY KEY, conversation_id TEXT NOT NULL, position INTEGER NOT NULL,
+        role TEXT NOT NULL, content TEXT NOT NULL, created_at INTEGER NOT NULL
+      );
+    ").unwrap();
+    conn.execute_batch(SCHEMA).unwrap();
+    conn.execute_batch("
+      INSERT INTO conversations VALUES ('c1', 'Rust');
+      INSERT INTO messages VALUES (1, 'c1', 0, 'user', '我在学习Rust编程', 1);
+      INSERT INTO messages VALUES (2, 'c1', 1, 'assistant', 'Use cargo NEAR the workspace root', 2);
+    ").unwrap();
+    let ids = |query: &str| search(&conn, query, None).unwrap().iter().map(|h| h.message_id).collect::<Vec<_>>();
+    assert_eq!(ids("编程"), [1]);
+    assert_eq!(ids("rus"), [1]);
+    assert_eq!(ids("near workspace"), [2]);
+    assert_eq!(ids("\"cargo* (root"), [2]);
+    assert!(ids("content:cargo -").is_empty());
+    assert!(ids("***").is_empty());
+  }
+
+  #[test]
+  fn snippet_marks_multibyte_matches() {
+    assert_eq!(snippet("我在学习Rust编程", &terms(&["编程"])), "我在学习Rust<mark>编程</mark>");
+    assert_eq!(snippet("a < b & Ünïcode", &terms(&["ünïcode"])), "a &lt; b &amp; <mark>Ünïcode</mark>");
+  }
+
+  #[test]
+  fn snippet_cuts_on_character_boundaries() {
+    let text = format!("{}目标{}", "前".repeat(100), "后".repeat(300));
+    let out = snippet(&text, &terms(&["目标"]));
+    let before = SNIPPET_CHARS / 4;
+    assert!(out.starts_with(&format!("…{}<mark>目标</mark>", "前".repeat(before))), "{}", out);
+    assert!(out.ends_with("后…"));
+    let shown = out.replace("<mark>", "").replace("</mark>", "");
+    assert_eq!(shown.chars().count(), SNIPPET_CHARS + 2);
+  }
+
+  #[test]
+  fn snippet_without_a_match_shows_the_start() {
+    assert_eq!(snippet("short text", &terms(&["missing"])), "short text");
+    assert_eq!(snippet("", &terms(&["x"])), "");
+  }
+}
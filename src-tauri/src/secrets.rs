@@ -0,0 +1,417 @@
+//! API keys kept out of `settings.json`. The config holds `secret:<id>` and the
+//! value lives in the OS keyring or, where there is none, in `secrets.vault`: a
+//! file of ChaCha20-Poly1305 ciphertexts under a key derived from a passphrase.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, ErrorCode};
+
+/// Prefix of an API key field that names a stored secret instead of holding one.
+pub const REF_PREFIX: &str = "secret:";
+/// Keyring service name; the secret id is the account.
+#[cfg(any(windows, target_os = "macos"))]
+const SERVICE: &str = "yao";
+const VAULT_VERSION: u32 = 1;
+/// Sealed under the vault key to tell a wrong passphrase from a damaged entry.
+const CHECK_PLAINTEXT: &[u8] = b"yao-vault";
+
+/// The secret id in `value`, when it is a reference.
+pub fn reference_id(value: &str) -> Option<&str> {
+  value.strip_prefix(REF_PREFIX).filter(|id| !id.is_empty())
+}
+
+pub fn reference(id: &str) -> String {
+  format!("{}{}", REF_PREFIX, id)
+}
+
+/// Payload of `secret_store_status`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretStatus {
+  /// New secrets go to the OS keyring.
+  pub keyring: bool,
+  pub vault_exists: bool,
+  pub vault_unlocked: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+  version: u32,
+  kdf: KdfParams,
+  /// Hex.
+  salt: String,
+  check: Sealed,
+  entries: BTreeMap<String, Sealed>,
+}
+
+/// Argon2id cost, stored so it can be raised later without breaking old vaults.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KdfParams {
+  memory_kib: u32,
+  iterations: u32,
+  parallelism: u32,
+}
+
+impl Default for KdfParams {
+  fn default() -> Self {
+    let p = argon2::Params::default();
+    Self { memory_kib: p.m_cost(), iterations: p.t_cost(), parallelism: p.p_cost() }
+  }
+}
+
+/// One ciphertext; the entry id is bound in as associated data.
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+  nonce: String,
+  data: String,
+}
+
+struct Vault {
+  cipher: ChaCha20Poly1305,
+  file: VaultFile,
+}
+
+fn vault_path() -> &'static OnceLock<PathBuf> {
+  static PATH: OnceLock<PathBuf> = OnceLock::new();
+  &PATH
+}
+
+/// The unlocked vault, held for the rest of the session.
+fn vault() -> &'static Mutex<Option<Vault>> {
+  static VAULT: OnceLock<Mutex<Option<Vault>>> = OnceLock::new();
+  VAULT.get_or_init(|| Mutex::new(None))
+}
+
+/// Sets where the vault lives; called once at startup.
+pub fn init(dir: &Path) {
+  let _ = vault_path().set(dir.join("secrets.vault"));
+}
+
+fn path() -> Result<&'static PathBuf, AppError> {
+  vault_path().get().ok_or_else(|| AppError::internal("secret store is not initialised"))
+}
+
+pub fn status() -> SecretStatus {
+  SecretStatus {
+    keyring: keyring_available(),
+    vault_exists: vault_exists(),
+    vault_unlocked: vault().lock().is_ok_and(|v| v.is_some()),
+  }
+}
+
+/// Opens the vault with `passphrase`, creating it on first use.
+pub fn unlock(passphrase: &str) -> Result<(), AppError> {
+  if passphrase.is_empty() {
+    return Err(AppError::invalid_config("the passphrase must not be empty"));
+  }
+  let path = path()?;
+  let opened = match std::fs::read(path) {
+    Ok(bytes) => Vault::open(serde_json::from_slice(&bytes)?, passphrase)?,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+      let vault = Vault::create(passphrase)?;
+      vault.save(path)?;
+      vault
+    }
+    Err(e) => return Err(e.into()),
+  };
+  *vault().lock()? = Some(opened);
+  Ok(())
+}
+
+/// Forgets the vault key; keyring secrets stay available.
+pub fn lock() -> Result<(), AppError> {
+  *vault().lock()? = None;
+  Ok(())
+}
+
+/// Stores `value` under `id`, in the keyring when it works, else in the vault.
+pub fn set(id: &str, value: &str) -> Result<(), AppError> {
+  validate_id(id)?;
+  if keyring_set(id, value) {
+    // a copy left in the vault would shadow nothing but would outlive a delete
+    if let Some(v) = vault().lock()?.as_mut() {
+      if v.file.entries.remove(id).is_some() {
+        v.save(path()?)?;
+      }
+    }
+    return Ok(());
+  }
+  let mut guard = vault().lock()?;
+  let v = guard.as_mut().ok_or_else(locked)?;
+  let sealed = v.seal(id, value.as_bytes())?;
+  v.file.entries.insert(id.to_string(), sealed);
+  v.save(path()?)
+}
+
+/// The value stored under `id`, or `None` when there is none.
+pub fn get(id: &str) -> Result<Option<String>, AppError> {
+  if let Some(value) = keyring_get(id) {
+    return Ok(Some(value));
+  }
+  let guard = vault().lock()?;
+  match guard.as_ref() {
+    Some(v) => v.file.entries.get(id).map(|sealed| v.unseal(id, sealed)).transpose(),
+    None if vault_exists() => Err(locked()),
+    None => Ok(None),
+  }
+}
+
+/// Removes `id` from both stores; false when neither had it.
+pub fn delete(id: &str) -> Result<bool, AppError> {
+  let mut removed = keyring_delete(id);
+  let mut guard = vault().lock()?;
+  if let Some(v) = guard.as_mut() {
+    if v.file.entries.remove(id).is_some() {
+      v.save(path()?)?;
+      removed = true;
+    }
+  } else if !removed && vault_exists() {
+    return Err(locked());
+  }
+  Ok(removed)
+}
+
+/// The value of a `secret:` reference; other values are returned unchanged.
+pub fn resolve(value: &str) -> Result<String, AppError> {
+  let Some(id) = reference_id(value) else { return Ok(value.to_string()) };
+  get(id)?.ok_or_else(|| AppError::invalid_config(format!("no stored secret named '{}'", id)))
+}
+
+fn vault_exists() -> bool {
+  vault_path().get().is_some_and(|p| p.is_file())
+}
+
+fn locked() -> AppError {
+  AppError::new(ErrorCode::VaultLocked, "the secret vault is locked; unlock it with your passphrase")
+}
+
+fn validate_id(id: &str) -> Result<(), AppError> {
+  if id.is_empty() || id.len() > 128 || id.chars().any(|c| c.is_control()) {
+    return Err(AppError::invalid_config(format!("invalid secret id '{}'", id)));
+  }
+  Ok(())
+}
+
+impl Vault {
+  fn create(passphrase: &str) -> Result<Self, AppError> {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let kdf = KdfParams::default();
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt, kdf)?);
+    let mut vault = Vault {
+      cipher,
+      file: VaultFile {
+        version: VAULT_VERSION,
+        kdf,
+        salt: hex(&salt),
+        check: Sealed { nonce: String::new(), data: String::new() },
+        entries: BTreeMap::new(),
+      },
+    };
+    vault.file.check = vault.seal("", CHECK_PLAINTEXT)?;
+    Ok(vault)
+  }
+
+  fn open(file: VaultFile, passphrase: &str) -> Result<Self, AppError> {
+    if file.version > VAULT_VERSION {
+      return Err(AppError::invalid_config(format!("secret vault version {} is newer than this app", file.version)));
+    }
+    let salt = unhex(&file.salt)?;
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt, file.kdf)?);
+    let vault = Vault { cipher, file };
+    match vault.open_sealed("", &vault.file.check) {
+      Some(check) if check == CHECK_PLAINTEXT => Ok(vault),
+      _ => Err(AppError::new(ErrorCode::VaultLocked, "wrong passphrase for the secret vault")),
+    }
+  }
+
+  fn seal(&self, id: &str, plaintext: &[u8]) -> Result<Sealed, AppError> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let data = self
+      .cipher
+      .encrypt(&nonce, Payload { msg: plaintext, aad: id.as_bytes() })
+      .map_err(|_| AppError::internal("cannot encrypt secret"))?;
+    Ok(Sealed { nonce: hex(&nonce), data: hex(&data) })
+  }
+
+  fn open_sealed(&self, id: &str, sealed: &Sealed) -> Option<Vec<u8>> {
+    let nonce = unhex(&sealed.nonce).ok().filter(|n| n.len() == 12)?;
+    let data = unhex(&sealed.data).ok()?;
+    self.cipher.decrypt(Nonce::from_slice(&nonce), Payload { msg: &data, aad: id.as_bytes() }).ok()
+  }
+
+  fn unseal(&self, id: &str, sealed: &Sealed) -> Result<String, AppError> {
+    self
+      .open_sealed(id, sealed)
+      .and_then(|bytes| String::from_utf8(bytes).ok())
+      .ok_or_else(|| AppError::internal(format!("stored secret '{}' is damaged", id)))
+  }
+
+  /// Writes to a temporary file first so a crash never leaves half a vault.
+  fn save(&self, path: &Path) -> Result<(), AppError> {
+    if let Some(dir) = path.parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("vault.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(&self.file)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+  }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], kdf: KdfParams) -> Result<Key, AppError> {
+  let params = argon2::Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(32))
+    .map_err(|e| AppError::invalid_config(format!("bad vault key parameters: {}", e)))?;
+  let mut key = Key::default();
+  Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+    .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+    .map_err(|e| AppError::internal(format!("cannot derive vault key: {}", e)))?;
+  Ok(key)
+}
+
+fn hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &str) -> Result<Vec<u8>, AppError> {
+  let bad = || AppError::invalid_config("secret vault is damaged");
+  if !s.len().is_multiple_of(2) {
+    return Err(bad());
+  }
+  (0..s.len())
+    .step_by(2)
+    .map(|i| s.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()).ok_or_else(bad))
+    .collect()
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+fn keyring_available() -> bool {
+  keyring::Entry::new(SERVICE, "probe").is_ok()
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+fn keyring_set(id: &str, value: &str) -> bool {
+  keyring::Entry::new(SERVICE, id).and_then(|e| e.set_password(value)).is_ok()
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+fn keyring_get(id: &str) -> Option<String> {
+  keyring::Entry::new(SERVICE, id).and_then(|e| e.get_password()).ok()
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+fn keyring_delete(id: &str) -> bool {
+  keyring::Entry::new(SERVICE, id).and_then(|e| e.delete_credential()).is_ok()
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn keyring_available() -> bool {
+  false
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn keyring_set(_id: &str, _value: &str) -> bool {
+  false
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn keyring_get(_id: &str) -> Option<String> {
+  None
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn keyring_delete(_id: &str) -> bool {
+  false
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// A copy of `vault`'s file as it would be read back from disk.
+  fn reread(vault: &Vault) -> VaultFile {
+    serde_json::from_slice(&serde_json::to_vec(&vault.file).unwrap()).unwrap()
+  }
+
+  #[test]
+  fn reference_id_only_reads_references() {
+    assert_eq!(reference_id("secret:openai"), Some("openai"));
+    assert_eq!(reference_id(&reference("profile/Work")), Some("profile/Work"));
+    assert_eq!(reference_id("sk-proj-abc123"), None);
+    assert_eq!(reference_id("AIzaSyExample"), None);
+    assert_eq!(reference_id("Secret:openai"), None);
+    assert_eq!(reference_id("secret:"), None);
+    assert_eq!(reference_id(""), None);
+  }
+
+  #[test]
+  fn sealed_secrets_round_trip() {
+    let vault = Vault::create("correct horse").unwrap();
+    let sealed = vault.seal("openai", "sk-test-123".as_bytes()).unwrap();
+    assert!(!sealed.data.contains(&hex(b"sk-test")));
+    assert_eq!(vault.unseal("openai", &sealed).unwrap(), "sk-test-123");
+    // the id is bound in, so an entry cannot be moved to another name
+    assert_eq!(vault.unseal("anthropic", &sealed).unwrap_err().code, ErrorCode::Internal);
+  }
+
+  #[test]
+  fn reopening_needs_the_same_passphrase() {
+    let mut vault = Vault::create("correct horse").unwrap();
+    let sealed = vault.seal("openai", b"sk-test-123").unwrap();
+    vault.file.entries.insert("openai".into(), sealed);
+
+    let Err(wrong) = Vault::open(reread(&vault), "battery staple") else { panic!("opened with the wrong passphrase") };
+    assert_eq!(wrong.code, ErrorCode::VaultLocked);
+    let reopened = Vault::open(reread(&vault), "correct horse").unwrap();
+    assert_eq!(reopened.unseal("openai", &reopened.file.entries["openai"]).unwrap(), "sk-test-123");
+
+    let mut newer = reread(&vault);
+    newer.version = VAULT_VERSION + 1;
+    let Err(newer) = Vault::open(newer, "correct horse") else { panic!("opened a newer vault") };
+    assert_eq!(newer.code, ErrorCode::InvalidConfig);
+  }
+
+  #[test]
+  fn unhex_rejects_damage() {
+    assert_eq!(unhex(&hex(&[0, 1, 0xab, 0xff])).unwrap(), [0, 1, 0xab, 0xff]);
+    assert!(unhex("abc").is_err());
+    assert!(unhex("zz").is_err());
+    assert!(unhex("é0").is_err());
+  }
+
+  /// Uses the process-wide vault; on Windows and macOS the keyring would take the secrets.
+  #[cfg(not(any(windows, target_os = "macos")))]
+  #[test]
+  fn references_resolve_through_the_vault() {
+    let dir = std::env::temp_dir().join(format!("yao-secrets-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    init(&dir);
+    assert_eq!(resolve("secret:openai").unwrap_err().code, ErrorCode::InvalidConfig);
+    assert_eq!(set("openai", "sk-test-123").unwrap_err().code, ErrorCode::VaultLocked);
+
+    unlock("correct horse").unwrap();
+    set("openai", "sk-test-123").unwrap();
+    assert_eq!(resolve("secret:openai").unwrap(), "sk-test-123");
+    assert_eq!(resolve("sk-plain-key").unwrap(), "sk-plain-key");
+    assert_eq!(resolve("secret:missing").unwrap_err().code, ErrorCode::InvalidConfig);
+
+    lock().unwrap();
+    assert_eq!(resolve("secret:openai").unwrap_err().code, ErrorCode::VaultLocked);
+    assert_eq!(unlock("battery staple").unwrap_err().code, ErrorCode::VaultLocked);
+    unlock("correct horse").unwrap();
+    assert_eq!(resolve("secret:openai").unwrap(), "sk-test-123");
+    assert!(delete("openai").unwrap());
+    assert_eq!(get("openai").unwrap(), None);
+    lock().unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -0,0 +1,327 @@
+//! `settings.json`: the backend reads, upgrades, validates and writes it, so the
+//! frontend never touches the file.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::Manager;
+
+use crate::error::AppError;
+use crate::logger::{self, Level};
+use crate::profiles::DEFAULT_PROFILE;
+use crate::AppConfig;
+
+/// Version written by this build; older files are migrated on load.
+pub const CURRENT_VERSION: u32 = 4;
+/// Files written before settings carried a version.
+const UNVERSIONED: u32 = 1;
+
+/// `MIGRATIONS[i]` upgrades a version `i + 1` file to version `i + 2`.
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[v1_to_v2, v2_to_v3, v3_to_v4];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpServerConfig {
+  pub id: String,
+  pub name: String,
+  pub command: String,
+  #[serde(default)]
+  pub args: Vec<String>,
+  #[serde(default)]
+  pub env: BTreeMap<String, String>,
+  #[serde(default)]
+  pub enabled: bool,
+  #[serde(default)]
+  pub description: Option<String>,
+}
+
+/// `settings.json` in the app data dir, which is created if missing.
+pub fn path(app: &tauri::AppHandle) -> Result<PathBuf, AppError> {
+  let base = app.path().app_local_data_dir()?;
+  std::fs::create_dir_all(&base)?;
+  Ok(base.join("settings.json"))
+}
+
+/// Reads `path`, migrating and rewriting it when it was written by an older version.
+/// A missing file gives the defaults; one from a newer version is refused, since
+/// saving it again would drop whatever this build does not know.
+pub fn load(path: &Path) -> Result<AppConfig, AppError> {
+  let bytes = match std::fs::read(path) {
+    Ok(bytes) => bytes,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(AppConfig::default()),
+    Err(e) => return Err(e.into()),
+  };
+  let mut value: Value = serde_json::from_slice(&bytes)?;
+  let Value::Object(map) = &mut value else {
+    return Err(AppError::invalid_config("settings.json does not hold a JSON object"));
+  };
+  let from = map.get("version").and_then(Value::as_u64).map_or(UNVERSIONED, |v| v as u32);
+  if from > CURRENT_VERSION {
+    return Err(AppError::invalid_config(format!(
+      "settings.json is version {}, newer than this build reads ({}); update the app",
+      from, CURRENT_VERSION
+    )));
+  }
+  let migrated = from < CURRENT_VERSION;
+  if migrated {
+    // keep the original in case a migration gets something wrong
+    std::fs::write(path.with_file_name(format!("settings.v{}.json", from)), &bytes)?;
+    for migrate in MIGRATIONS.iter().skip(from.saturating_sub(UNVERSIONED) as usize) {
+      migrate(map);
+    }
+    map.insert("version".to_string(), CURRENT_VERSION.into());
+  }
+  let config: AppConfig = serde_json::from_value(value)?;
+  if migrated {
+    write(path, &config)?;
+    logger::log(Level::Info, "settings", None, "migrated", serde_json::json!({ "from": from, "to": CURRENT_VERSION }));
+  }
+  Ok(config)
+}
+
+/// Validates `config` and writes it as the current version; returns what was written.
+pub fn save(path: &Path, mut config: AppConfig) -> Result<AppConfig, AppError> {
+  config.sync_active_profile();
+  validate(&config)?;
+  config.version = CURRENT_VERSION;
+  write(path, &config)?;
+  Ok(config)
+}
+
+/// Writes to a temporary file first so a crash never leaves half a settings file.
+fn write(path: &Path, config: &AppConfig) -> Result<(), AppError> {
+  let tmp = path.with_extension("json.tmp");
+  std::fs::write(&tmp, serde_json::to_vec_pretty(config)?)?;
+  std::fs::rename(&tmp, path)?;
+  Ok(())
+}
+
+pub fn validate(config: &AppConfig) -> Result<(), AppError> {
+  check_url("baseUrl", &config.base_url)?;
+  for m in config.models.iter().flatten() {
+    if m.name.trim().is_empty() {
+      return Err(AppError::invalid_config("every model needs a name"));
+    }
+    if !m.base_url.is_empty() {
+      check_url(&format!("baseUrl of model '{}'", m.name), &m.base_url)?;
+    }
+  }
+  check_temperature(config.temperature)?;
+  for (i, p) in config.profiles.iter().enumerate() {
+    if p.name.trim().is_empty() {
+      return Err(AppError::invalid_config("every profile needs a name"));
+    }
+    if config.profiles[..i].iter().any(|q| q.name == p.name) {
+      return Err(AppError::invalid_config(format!("there are two profiles named '{}'", p.name)));
+    }
+    check_url(&format!("baseUrl of profile '{}'", p.name), &p.base_url)?;
+    check_temperature(p.temperature)?;
+  }
+  if let Some(active) = &config.active_profile {
+    config.profile(active)?;
+  }
+  Ok(())
+}
+
+fn check_temperature(temperature: Option<f64>) -> Result<(), AppError> {
+  match temperature {
+    Some(t) if !(0.0..=2.0).contains(&t) => {
+      Err(AppError::invalid_config(format!("temperature must be between 0.0 and 2.0, got {}", t)))
+    }
+    _ => Ok(()),
+  }
+}
+
+fn check_url(field: &str, url: &str) -> Result<(), AppError> {
+  let parsed = reqwest::Url::parse(url.trim()).map_err(|e| AppError::invalid_config(format!("{} '{}' is not a valid URL: {}", field, url, e)))?;
+  if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+    return Err(AppError::invalid_config(format!("{} '{}' must be an http:// or https:// address", field, url)));
+  }
+  Ok(())
+}
+
+/// Unversioned files: empty API keys meant "none", MCP `args` could be a single
+/// string, `null` stood in for empty lists and temperatures were not range-checked.
+fn v1_to_v2(map: &mut Map<String, Value>) {
+  for key in ["timeouts", "retry", "logPrompts", "mcpServers", "mcpServerInfos"] {
+    if map.get(key).is_some_and(Value::is_null) {
+      map.remove(key);
+    }
+  }
+  let drop_empty_key = |obj: &mut Map<String, Value>| {
+    if obj.get("apiKey").and_then(Value::as_str).is_some_and(|k| k.trim().is_empty()) {
+      obj.remove("apiKey");
+    }
+  };
+  drop_empty_key(map);
+  if let Some(Value::Array(models)) = map.get_mut("models") {
+    models.iter_mut().filter_map(Value::as_object_mut).for_each(drop_empty_key);
+  }
+  if let Some(Value::Array(servers)) = map.get_mut("mcpServers") {
+    for server in servers.iter_mut().filter_map(Value::as_object_mut) {
+      server.retain(|k, v| !(v.is_null() && matches!(k.as_str(), "args" | "env" | "enabled")));
+      if let Some(Value::String(args)) = server.get("args") {
+        let split = args.split_whitespace().map(|a| Value::String(a.to_string())).collect();
+        server.insert("args".to_string(), Value::Array(split));
+      }
+      if !server.contains_key("id") {
+        let name = server.get("name").and_then(Value::as_str).unwrap_or("mcp").to_string();
+        server.insert("id".to_string(), Value::String(name));
+      }
+    }
+  }
+  if let Some(t) = map.get("temperature").and_then(Value::as_f64) {
+    map.insert("temperature".to_string(), t.clamp(0.0, 2.0).into());
+  }
+}
+
+/// Version 2 had a single set of connection settings; it becomes the "Default" profile.
+fn v2_to_v3(map: &mut Map<String, Value>) {
+  if map.contains_key("profiles") {
+    return;
+  }
+  let defaults = serde_json::to_value(AppConfig::default()).unwrap_or_default();
+  let mut profile = Map::new();
+  profile.insert("name".to_string(), DEFAULT_PROFILE.into());
+  for key in ["provider", "baseUrl", "apiKey", "model", "temperature", "deployment", "apiVersion"] {
+    if let Some(value) = map.get(key).or_else(|| defaults.get(key)) {
+      profile.insert(key.to_string(), value.clone());
+    }
+  }
+  map.insert("profiles".to_string(), Value::Array(vec![Value::Object(profile)]));
+  map.insert("activeProfile".to_string(), DEFAULT_PROFILE.into());
+}
+
+/// Version 3 cut history to a number of messages; version 4 fits it to the model's
+/// context window in tokens, so the count has no successor to carry over to.
+fn v3_to_v4(map: &mut Map<String, Value>) {
+  map.remove("maxContextMessages");
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::error::ErrorCode;
+
+  fn object(v: Value) -> Map<String, Value> {
+    match v {
+      Value::Object(map) => map,
+      other => panic!("not an object: {}", other),
+    }
+  }
+
+  /// An empty directory under the system temp dir for one test.
+  fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("yao-settings-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn v1_to_v2_cleans_up_loose_values() {
+    let mut map = object(serde_json::json!({
+      "apiKey": "  ",
+      "timeouts": null,
+      "temperature": 3.5,
+      "models": [{ "name": "a", "provider": "openai", "apiKey": "" }, { "name": "b", "provider": "openai", "apiKey": "sk-b" }],
+      "mcpServers": [{ "name": "files", "command": "npx", "args": "-y server-files /tmp", "env": null }],
+    }));
+    v1_to_v2(&mut map);
+    assert!(!map.contains_key("apiKey") && !map.contains_key("timeouts"));
+    assert_eq!(map["temperature"], 2.0);
+    assert_eq!(map["models"][0].get("apiKey"), None);
+    assert_eq!(map["models"][1]["apiKey"], "sk-b");
+    assert_eq!(map["mcpServers"][0], serde_json::json!({ "id": "files", "name": "files", "command": "npx", "args": ["-y", "server-files", "/tmp"] }));
+  }
+
+  #[test]
+  fn v2_to_v3_makes_the_default_profile() {
+    let mut map = object(serde_json::json!({ "provider": "openai", "baseUrl": "https://api.openai.com/v1", "apiKey": "sk-x" }));
+    v2_to_v3(&mut map);
+    let profile = &map["profiles"][0];
+    assert_eq!((profile["name"].as_str(), profile["provider"].as_str(), profile["apiKey"].as_str()), (Some(DEFAULT_PROFILE), Some("openai"), Some("sk-x")));
+    // what the file did not set comes from the defaults
+    assert_eq!(profile["temperature"], 0.6);
+    assert_eq!(map["activeProfile"], DEFAULT_PROFILE);
+
+    let mut has_profiles = object(serde_json::json!({ "profiles": [], "activeProfile": null }));
+    v2_to_v3(&mut has_profiles);
+    assert_eq!(has_profiles["profiles"], serde_json::json!([]));
+  }
+
+  #[test]
+  fn v3_to_v4_drops_the_message_limit() {
+    let mut map = object(serde_json::json!({ "maxContextMessages": 20, "model": "qwen3" }));
+    v3_to_v4(&mut map);
+    assert_eq!(Value::Object(map), serde_json::json!({ "model": "qwen3" }));
+  }
+
+  #[test]
+  fn v1_file_migrates_to_the_current_version() {
+    let dir = temp_dir("v1");
+    let path = dir.join("settings.json");
+    let v1 = serde_json::json!({
+      "provider": "openai",
+      "baseUrl": "https://api.openai.com/v1",
+      "apiKey": "sk-test",
+      "model": "gpt-4o",
+      "maxContextMessages": 10,
+      "models": [{ "name": "gpt-4o", "provider": "openai", "baseUrl": "", "apiKey": "" }],
+    });
+    std::fs::write(&path, v1.to_string()).unwrap();
+
+    let config = load(&path).unwrap();
+    assert_eq!(config.version, CURRENT_VERSION);
+    assert_eq!((config.api_key.as_deref(), config.model.as_deref()), (Some("sk-test"), Some("gpt-4o")));
+    let models = config.models.as_deref().unwrap();
+    assert_eq!((models.len(), models[0].name.as_str(), models[0].api_key.as_deref()), (1, "gpt-4o", None));
+    assert_eq!(config.active_profile.as_deref(), Some(DEFAULT_PROFILE));
+    assert_eq!(config.profiles[0].api_key.as_deref(), Some("sk-test"));
+
+    // the original is kept and the file is rewritten as the current version
+    assert_eq!(std::fs::read_to_string(dir.join("settings.v1.json")).unwrap(), v1.to_string());
+    let written: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(written["version"], CURRENT_VERSION);
+    assert!(written.get("maxContextMessages").is_none());
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn newer_file_is_refused() {
+    let dir = temp_dir("newer");
+    let path = dir.join("settings.json");
+    let newer = serde_json::json!({ "version": CURRENT_VERSION + 1, "provider": "ollama" }).to_string();
+    std::fs::write(&path, &newer).unwrap();
+    assert_eq!(load(&path).unwrap_err().code, ErrorCode::InvalidConfig);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), newer);
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn missing_file_gives_the_defaults() {
+    let dir = temp_dir("missing");
+    let config = load(&dir.join("settings.json")).unwrap();
+    assert_eq!((config.version, config.provider.as_str()), (CURRENT_VERSION, "ollama"));
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn validate_rejects_bad_values() {
+    let invalid = |change: &dyn Fn(&mut AppConfig)| {
+      let mut config = AppConfig::default();
+      change(&mut config);
+      validate(&config).map_err(|e| e.code)
+    };
+    assert_eq!(invalid(&|_| {}), Ok(()));
+    assert_eq!(invalid(&|c| c.base_url = "localhost:11434".into()), Err(ErrorCode::InvalidConfig));
+    assert_eq!(invalid(&|c| c.base_url = "ftp://example.com".into()), Err(ErrorCode::InvalidConfig));
+    assert_eq!(invalid(&|c| c.temperature = Some(2.5)), Err(ErrorCode::InvalidConfig));
+    assert_eq!(invalid(&|c| c.models.as_mut().unwrap()[0].name = " ".into()), Err(ErrorCode::InvalidConfig));
+    assert_eq!(invalid(&|c| c.models.as_mut().unwrap()[0].base_url = "nope".into()), Err(ErrorCode::InvalidConfig));
+    assert_eq!(invalid(&|c| c.active_profile = Some("missing".into())), Err(ErrorCode::InvalidConfig));
+    assert_eq!(invalid(&|c| { let p = c.profiles[0].clone(); c.profiles.push(p) }), Err(ErrorCode::InvalidConfig));
+  }
+}
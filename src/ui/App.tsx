@@ -2,27 +2,39 @@ import React, { useEffect, useMemo, useRef, useState } from 'react'
 import { SettingsDrawer } from './SettingsDrawer'
 import { ChatBubble } from './ChatBubble'
 import { useStore } from '../utils/store'
-import { fetchModels, streamChat, streamChatWithMCP } from '../utils/proxy'
+import { fetchModels, stopChat, streamChat, streamChatWithMCP } from '../utils/proxy'
 import { t, setLocale, getCurrentLocale } from '../utils/i18n'
 import { IconSend, IconStop, IconGlobe, IconCloud, IconList, IconEdit, IconBrain, IconLanguage, IconMCP } from './icons'
 import { Dropdown } from './Dropdown'
-import { createConversationId, loadConversations, saveConversations, type Conversation } from '../utils/conversations'
+import { appendMessages, createConversation, createConversationId, displayContent, exportConversation, EXPORT_EXTENSIONS, forkConversation, getConversation, importConversations, listConversations, regenerateMessage, searchConversations, switchBranch, type ChatMessage, type Conversation, type ConversationSummary, type ExportFormat, type SearchHit } from '../utils/conversations'
 import { log } from '../utils/log'
+import { ChatError, errorMessage } from '../utils/errors'
 import { ModelPullDialog } from './ModelPullDialog'
 // Loading现在在HTML中处理，不需要React组件
 import { invoke } from '@tauri-apps/api/core'
+import { open, save } from '@tauri-apps/plugin-dialog'
 
 export type Message = {
   role: 'user' | 'assistant'
   content: string
+  // database id and alternatives, once the message is stored
+  id?: number
+  siblings?: number[]
 }
 
+const toMessages = (c: Conversation): Message[] =>
+  c.messages.map(m => ({ role: m.role, content: displayContent(m), id: m.id, siblings: m.siblings }))
+
 export const App: React.FC = () => {
   const [showSettings, setShowSettings] = useState(false)
   const [messages, setMessages] = useState<Message[]>([])
-  const [conversations, setConversations] = useState<Conversation[]>([])
+  const [conversations, setConversations] = useState<ConversationSummary[]>([])
   const [currentCid, setCurrentCid] = useState<string>('')
+  const [searchQuery, setSearchQuery] = useState('')
+  const [searchHits, setSearchHits] = useState<SearchHit[]>([])
   const [input, setInput] = useState('')
+  // index of the prompt being edited; sending forks the conversation there
+  const [editingIndex, setEditingIndex] = useState<number | null>(null)
   const [models, setModels] = useState<string[]>([])
   const [isSidebarCollapsed, setSidebarCollapsed] = useState(false)
   const [typingIndex, setTypingIndex] = useState<number | null>(null)
@@ -48,11 +60,11 @@ export const App: React.FC = () => {
       try {
         const list = await fetchModels(config)
         setModels(list)
-        const cs = await loadConversations()
+        const cs = await listConversations()
         setConversations(cs)
         if (cs.length) {
           setCurrentCid(cs[0].id)
-          setMessages(cs[0].messages.map(m=>({ role:m.role, content:m.content })))
+          await openConversation(cs[0].id)
         }
       } catch (error) {
         setModels([])
@@ -133,33 +145,74 @@ export const App: React.FC = () => {
 
   const sleep = (ms: number) => new Promise((r) => setTimeout(r, ms))
 
+  const openConversation = async (id: string) => {
+    try {
+      const c = await getConversation(id)
+      setMessages(toMessages(c))
+      setEditingIndex(null)
+    } catch (error) {
+      await log('ERROR', 'conversation_load_failed', { id, error: errorMessage(error) })
+    }
+  }
+
+  // 搜索结果可能在未激活的分支上，先切换到该分支再显示
+  const openSearchHit = async (h: SearchHit) => {
+    try {
+      setMessages(toMessages(await switchBranch(h.conversationId, h.messageId)))
+      setEditingIndex(null)
+    } catch (error) {
+      await log('ERROR', 'conversation_switch_branch_failed', { id: h.conversationId, messageId: h.messageId, error: errorMessage(error) })
+      await openConversation(h.conversationId)
+    }
+  }
+
   const handleStop = () => {
     if (abortControllerRef.current) {
       abortControllerRef.current.abort()
       abortControllerRef.current = null
     }
+    stopChat()
     setIsGenerating(false)
     setTypingIndex(null)
     setThinkStartAt(null)
     log('INFO', 'chat_generation_stopped_by_user', {})
   }
 
-  const handleSend = async () => {
-    if (!input.trim()) return
+  // 切换到同一位置的另一条分支（编辑过的提问或重新生成的回答）
+  const handleSwitchBranch = async (idx: number, step: number) => {
+    const m = messages[idx]
+    if (isGenerating || !currentCid || m.id === undefined || !m.siblings) return
+    const target = m.siblings[m.siblings.indexOf(m.id) + step]
+    if (target === undefined) return
+    try {
+      setMessages(toMessages(await switchBranch(currentCid, target)))
+      setEditingIndex(null)
+      setConversations(await listConversations())
+    } catch (error) {
+      await log('ERROR', 'conversation_switch_branch_failed', { id: currentCid, messageId: target, error: errorMessage(error) })
+    }
+  }
+
+  // regenerateIndex: answer the prompt before that reply again and keep both replies
+  const handleSend = async (regenerateIndex?: number) => {
+    const regenerating = regenerateIndex !== undefined
+    if (!regenerating && !input.trim()) return
     if (isGenerating) {
       handleStop()
       return
     }
     if (view === 'starter') setView('main')
-    const newMessages = [...messages, { role: 'user', content: input.trim() }]
+    // the message a new branch starts at, when there is one
+    const editIndex = regenerating ? null : editingIndex
+    const branchAt = regenerating ? messages[regenerateIndex] : editIndex !== null ? messages[editIndex] : undefined
+    const base = regenerating ? messages.slice(0, regenerateIndex) : editIndex !== null ? messages.slice(0, editIndex) : messages
+    const newMessages: Message[] = regenerating ? base : [...base, { role: 'user', content: input.trim() }]
     setMessages(newMessages)
-    setInput('')
+    setEditingIndex(null)
+    if (!regenerating) setInput('')
     const cid = currentCid || createConversationId()
     if (!currentCid) setCurrentCid(cid)
-    // 根据上下文限制截断
-    const contextLimit = config.maxContextMessages ?? 20
-    const history = newMessages.slice(Math.max(0, newMessages.length - contextLimit))
-    await log('INFO', 'chat_send_start', { model: currentModel, think: thinkEnabled, input: input.trim() })
+    await log('INFO', 'chat_send_start', { model: currentModel, think: thinkEnabled, inputLen: input.trim().length, ...(config.logPrompts ? { input: input.trim() } : {}) })
     // 找到当前模型的配置，使用其特定的baseUrl和provider
     const modelConfig = config.models?.find(m => m.name === currentModel)
     const modelBaseUrl = modelConfig?.baseUrl || config.baseUrl
@@ -185,7 +238,7 @@ export const App: React.FC = () => {
           return
         }
       } catch (error) {
-        await log('ERROR', 'model_check_failed', { model: currentModel, error: String(error) })
+        await log('ERROR', 'model_check_failed', { model: currentModel, error: errorMessage(error) })
         // Continue with chat attempt even if check fails
       }
     }
@@ -207,7 +260,9 @@ export const App: React.FC = () => {
         ...config, 
         baseUrl: modelBaseUrl, 
         provider: modelProvider,
-        apiKey: modelConfig?.apiKey || config.apiKey
+        apiKey: modelConfig?.apiKey || config.apiKey,
+        deployment: modelConfig?.deployment || config.deployment,
+        apiVersion: modelConfig?.apiVersion || config.apiVersion
       }
       
       await log('INFO', 'chat_stream_start', { 
@@ -221,7 +276,8 @@ export const App: React.FC = () => {
       
       for await (const chunk of streamChatWithMCP({
         config: modelSpecificConfig,
-        messages: history,
+        // 整段历史；后端按模型上下文长度裁剪
+        messages: newMessages,
         model: currentModel,
         think: thinkEnabled,
         mcpEnabled: mcpEnabled,
@@ -241,27 +297,47 @@ export const App: React.FC = () => {
       setIsGenerating(false)
       abortControllerRef.current = null
       await log('INFO', 'chat_send_end', { model: currentModel, outputLen: assistant.content.length })
-      // persist conversation
-      const updated: Conversation = {
-        id: cid,
-        title: newMessages[0]?.content.slice(0, 24) || '对话',
-        model: currentModel,
-        provider: config.provider as any,
-        updatedAt: Date.now(),
-        messages: [...newMessages, assistant].map(m=>({ role:m.role, content:m.content, createdAt: Date.now() }))
+      // persist conversation: only the messages the database does not have yet
+      const now = Date.now()
+      const all: ChatMessage[] = [...newMessages, assistant].map(m => ({
+        role: m.role as ChatMessage['role'],
+        content: m.content,
+        createdAt: now,
+        ...(m === assistant ? { model: currentModel, provider: modelProvider as any } : {}),
+      }))
+      const stored = conversations.find(c => c.id === cid)
+      const reply = all[all.length - 1]
+      try {
+        if (stored && regenerating && branchAt?.id !== undefined) {
+          await regenerateMessage(cid, branchAt.id, reply)
+        } else if (stored && branchAt?.id !== undefined) {
+          await forkConversation(cid, branchAt.id, all[all.length - 2])
+          await appendMessages(cid, [reply])
+        } else if (stored) {
+          await appendMessages(cid, all.slice(stored.messageCount))
+        } else {
+          await createConversation({
+            id: cid,
+            title: newMessages[0]?.content.slice(0, 24) || '对话',
+            model: currentModel,
+            provider: modelProvider as any,
+            messages: all,
+          })
+        }
+        setConversations(await listConversations())
+        // picks up the ids and alternatives of the stored messages
+        await openConversation(cid)
+      } catch (error) {
+        await log('ERROR', 'conversation_save_failed', { id: cid, error: errorMessage(error) })
       }
-      const others = conversations.filter(c=>c.id!==cid)
-      const saved = [updated, ...others].sort((a,b)=> b.updatedAt - a.updatedAt)
-      setConversations(saved)
-      await saveConversations(saved)
     } catch (err) {
-      assistant.content += '\n[Error] Request failed.'
+      assistant.content += `\n[Error] ${errorMessage(err) || 'Request failed.'}`
       setMessages(prev => prev.map((m, i) => (i === assistantIndex ? assistant : m)))
       setTypingIndex(null)
       setThinkStartAt(null)
       setIsGenerating(false)
       abortControllerRef.current = null
-      await log('ERROR', 'chat_send_error', { error: String(err) })
+      await log('ERROR', 'chat_send_error', { error: errorMessage(err), code: err instanceof ChatError ? err.code : undefined })
     }
   }
 
@@ -269,10 +345,13 @@ export const App: React.FC = () => {
     <div className="h-[72px] bg-gray-100 rounded-[24px] flex items-center px-6 gap-3">
       <input
         className="flex-1 bg-transparent outline-none text-gray-700 placeholder-gray-400"
-        placeholder={t('chat.send_message')}
+        placeholder={editingIndex !== null ? t('chat.editing') : t('chat.send_message')}
         value={input}
         onChange={(e) => setInput(e.target.value)}
-        onKeyDown={(e) => { if (e.key==='Enter' && !e.shiftKey){ e.preventDefault(); handleSend()} }}
+        onKeyDown={(e) => {
+          if (e.key==='Enter' && !e.shiftKey){ e.preventDefault(); handleSend() }
+          if (e.key==='Escape' && editingIndex !== null){ setEditingIndex(null); setInput('') }
+        }}
       />
       <button
         className={`w-10 h-10 rounded-full flex items-center justify-center border ${thinkEnabled ? 'bg-gray-900 text-white border-gray-900' : 'bg-white text-gray-700 border-gray-200'}`}
@@ -303,7 +382,7 @@ export const App: React.FC = () => {
             ? 'bg-gray-900 text-white hover:bg-gray-800' 
             : 'bg-gray-900 text-white hover:bg-gray-800'
         }`}
-        onClick={handleSend}
+        onClick={() => handleSend()}
         aria-label={isGenerating ? t('chat.stop') : t('chat.send')}
       >
         {isGenerating ? (
@@ -315,6 +394,38 @@ export const App: React.FC = () => {
     </div>
   )
 
+  const handleExport = async (format: ExportFormat) => {
+    if (!currentCid) return
+    const title = conversations.find(c => c.id === currentCid)?.title || currentCid
+    // 文件名里不能出现的字符换成下划线
+    const name = title.replace(/[\\/:*?"<>|\n\r]+/g, '_').slice(0, 80)
+    const extension = EXPORT_EXTENSIONS[format]
+    try {
+      const path = await save({ defaultPath: `${name}.${extension}`, filters: [{ name: format.toUpperCase(), extensions: [extension] }] })
+      if (!path) return
+      await exportConversation(currentCid, format, path)
+    } catch (error) {
+      await log('ERROR', 'conversation_export_failed', { id: currentCid, format, error: errorMessage(error) })
+      alert(t('chat.export_failed') + errorMessage(error))
+    }
+  }
+
+  const handleImport = async () => {
+    try {
+      const path = await open({ multiple: false, directory: false, filters: [{ name: 'JSON', extensions: ['json'] }] })
+      if (typeof path !== 'string') return
+      const report = await importConversations(path)
+      setConversations(await listConversations())
+      const lines = [t('chat.import_done').replace('{imported}', String(report.imported.length)).replace('{skipped}', String(report.skipped.length))]
+      for (const s of report.skipped.slice(0, 10)) lines.push(`• ${s.title || s.id || '?'}: ${s.reason}`)
+      if (report.skippedMessages) lines.push(t('chat.import_skipped_messages').replace('{count}', String(report.skippedMessages)))
+      alert(lines.join('\n'))
+    } catch (error) {
+      await log('ERROR', 'conversation_import_failed', { error: errorMessage(error) })
+      alert(t('chat.import_failed') + errorMessage(error))
+    }
+  }
+
   const TopBar = (
     <div className="h-12 flex items-center justify-between px-4">
       <div className="flex items-center gap-4 text-gray-700">
@@ -322,7 +433,7 @@ export const App: React.FC = () => {
         <button className="w-8 h-8 rounded-full bg-gray-100 flex items-center justify-center" onClick={() => setDrawerOpen(true)} aria-label={t('chat.menu')}>
           <IconList className="w-4 h-4" />
         </button>
-        <button className="w-8 h-8 rounded-full bg-gray-100 flex items-center justify-center" onClick={() => { setView('starter'); setMessages([]); setCurrentCid('') }} aria-label={t('chat.new_chat')}>
+        <button className="w-8 h-8 rounded-full bg-gray-100 flex items-center justify-center" onClick={() => { setView('starter'); setMessages([]); setCurrentCid(''); setEditingIndex(null) }} aria-label={t('chat.new_chat')}>
           <IconEdit className="w-4 h-4" />
         </button>
         <button className="px-3 h-8 rounded-full bg-gray-100 flex items-center justify-center text-xs font-medium text-gray-700 hover:bg-gray-200" onClick={toggleLanguage} aria-label={t('chat.language')}>
@@ -330,7 +441,19 @@ export const App: React.FC = () => {
         </button>
         <div className="text-sm text-gray-600">Yao</div>
       </div>
-      <div />
+      {currentCid ? (
+        <Dropdown
+          options={[
+            { label: 'Markdown', value: 'markdown' },
+            { label: 'HTML', value: 'html' },
+            { label: 'JSON', value: 'json' },
+          ]}
+          onChange={(v) => handleExport(v as ExportFormat)}
+          placeholder={t('chat.export')}
+          align="right"
+          buttonClassName="!h-8"
+        />
+      ) : <div />}
     </div>
   )
 
@@ -346,21 +469,59 @@ export const App: React.FC = () => {
         }`}
       >
         <div className="space-y-3">
-          <button className="w-full h-11 rounded-ollama bg-gray-100 text-gray-800 text-left px-3" onClick={() => { setView('starter'); setMessages([]); setCurrentCid(''); setDrawerOpen(false) }}>{t('chat.new_chat')}</button>
+          <button className="w-full h-11 rounded-ollama bg-gray-100 text-gray-800 text-left px-3" onClick={() => { setView('starter'); setMessages([]); setCurrentCid(''); setEditingIndex(null); setDrawerOpen(false) }}>{t('chat.new_chat')}</button>
           <button className="w-full h-11 rounded-ollama bg-gray-100 text-gray-800 text-left px-3" onClick={() => { setShowSettings(true); setDrawerOpen(false) }}>{t('chat.settings')}</button>
+          <button className="w-full h-11 rounded-ollama bg-gray-100 text-gray-800 text-left px-3" onClick={() => { setDrawerOpen(false); handleImport() }}>{t('chat.import')}</button>
         </div>
-        <div className="pt-4 text-xs text-gray-500">{t('chat.this_week')}</div>
-        <div className="flex-1 overflow-y-auto space-y-3 pt-2">
-          {conversations.map((c) => (
-            <div key={c.id} className={`text-sm truncate cursor-pointer ${currentCid===c.id?'text-gray-900':'text-gray-800'}`} onClick={()=>{ setCurrentCid(c.id); setMessages(c.messages.map(m=>({role:m.role, content:m.content}))); setView('main'); setDrawerOpen(false) }}>
-              {c.title}
+        <input
+          className="mt-4 w-full h-10 rounded-ollama border border-gray-200 px-3 text-sm outline-none focus:border-gray-400"
+          placeholder={t('chat.search_placeholder')}
+          value={searchQuery}
+          onChange={(e) => setSearchQuery(e.target.value)}
+        />
+        {searchQuery.trim() ? (
+          <div className="flex-1 overflow-y-auto space-y-3 pt-3">
+            {searchHits.length === 0 && <div className="text-sm text-gray-500">{t('chat.search_empty')}</div>}
+            {searchHits.map((h) => (
+              <div key={`${h.conversationId}:${h.messageIndex}`} className="cursor-pointer" onClick={()=>{ setCurrentCid(h.conversationId); openSearchHit(h); setView('main'); setDrawerOpen(false) }}>
+                <div className="text-sm text-gray-900 truncate">{h.conversationTitle}</div>
+                {/* snippets are escaped by the backend; only <mark> is markup */}
+                <div className="text-xs text-gray-500 line-clamp-2 [&_mark]:bg-yellow-200 [&_mark]:text-gray-900" dangerouslySetInnerHTML={{ __html: h.snippet }} />
+              </div>
+            ))}
+          </div>
+        ) : (
+          <>
+            <div className="pt-4 text-xs text-gray-500">{t('chat.this_week')}</div>
+            <div className="flex-1 overflow-y-auto space-y-3 pt-2">
+              {conversations.map((c) => (
+                <div key={c.id} className={`text-sm truncate cursor-pointer ${currentCid===c.id?'text-gray-900':'text-gray-800'}`} onClick={()=>{ setCurrentCid(c.id); openConversation(c.id); setView('main'); setDrawerOpen(false) }}>
+                  {c.title}
+                </div>
+              ))}
             </div>
-          ))}
-        </div>
+          </>
+        )}
       </div>
     </div>
   )
 
+  // 抽屉里的搜索：输入停顿后再查询
+  useEffect(() => {
+    const query = searchQuery.trim()
+    if (!query) { setSearchHits([]); return }
+    let cancelled = false
+    const timer = setTimeout(async () => {
+      try {
+        const hits = await searchConversations(query)
+        if (!cancelled) setSearchHits(hits)
+      } catch (e) {
+        await log('WARN', 'conversation_search_failed', { error: errorMessage(e) })
+      }
+    }, 200)
+    return () => { cancelled = true; clearTimeout(timer) }
+  }, [searchQuery])
+
   // Esc 关闭抽屉 & 打开时锁定滚动
   useEffect(() => {
     const onKeyDown = (e: KeyboardEvent) => {
@@ -412,7 +573,14 @@ export const App: React.FC = () => {
                   thinkingMs={!assistantOutputStarted && typingIndex === idx && m.role === 'assistant' ? thinkingMs : undefined}
                   thinkEnabled={thinkEnabled}
                   onCopy={()=> navigator.clipboard.writeText(m.content)}
-                  onRetry={m.role==='assistant'? ()=>{ setInput(messages.filter((_,i)=>i<idx).map(m=>m.content).join('\n')); handleSend() } : undefined}
+                  onRetry={m.role==='assistant' && !isGenerating ? ()=> handleSend(idx) : undefined}
+                  onEdit={m.role==='user' && m.id !== undefined && !isGenerating ? ()=>{ setEditingIndex(idx); setInput(m.content) } : undefined}
+                  branch={m.id !== undefined && m.siblings ? {
+                    index: m.siblings.indexOf(m.id),
+                    count: m.siblings.length,
+                    onPrev: () => handleSwitchBranch(idx, -1),
+                    onNext: () => handleSwitchBranch(idx, 1),
+                  } : undefined}
                 />
               ))}
             </div>
@@ -1,7 +1,7 @@
 import React, { useState, useEffect } from 'react'
 import ReactMarkdown from 'react-markdown'
 import remarkGfm from 'remark-gfm'
-import { IconCopy, IconRefresh, IconCheck, IconBot, IconUser } from './icons'
+import { IconCopy, IconRefresh, IconCheck, IconBot, IconUser, IconEdit } from './icons'
 import { ThinkingBadge } from './Thinking'
 
 export const ChatBubble: React.FC<{
@@ -13,7 +13,10 @@ export const ChatBubble: React.FC<{
   thinkEnabled?: boolean
   onCopy?: () => void
   onRetry?: () => void
-}> = ({ role, content, createdAt, isStreaming, thinkingMs, thinkEnabled = true, onCopy, onRetry }) => {
+  onEdit?: () => void
+  // position among the alternatives of this message, when it has any
+  branch?: { index: number; count: number; onPrev: () => void; onNext: () => void }
+}> = ({ role, content, createdAt, isStreaming, thinkingMs, thinkEnabled = true, onCopy, onRetry, onEdit, branch }) => {
   const isUser = role === 'user'
   const time = createdAt ? new Date(createdAt).toLocaleTimeString() : ''
   const [copied, setCopied] = useState(false)
@@ -147,6 +150,13 @@ export const ChatBubble: React.FC<{
   const ActionButtons = () => {
     return (
       <div className="flex items-center gap-1">
+        {branch && branch.count > 1 && (
+          <div className="flex items-center text-xs text-gray-500 select-none">
+            <button onClick={branch.onPrev} disabled={branch.index <= 0} className="px-1 rounded hover:bg-gray-100 disabled:opacity-30">‹</button>
+            <span>{branch.index + 1}/{branch.count}</span>
+            <button onClick={branch.onNext} disabled={branch.index >= branch.count - 1} className="px-1 rounded hover:bg-gray-100 disabled:opacity-30">›</button>
+          </div>
+        )}
         {onCopy && (
           <button
             onClick={handleCopy}
@@ -165,6 +175,15 @@ export const ChatBubble: React.FC<{
             )}
           </button>
         )}
+        {isUser && onEdit && (
+          <button
+            onClick={onEdit}
+            className="p-1.5 rounded-lg hover:bg-gray-100 text-gray-400 hover:text-gray-600 transition-all duration-200"
+            title="编辑"
+          >
+            <IconEdit className="w-3.5 h-3.5" />
+          </button>
+        )}
         {!isUser && onRetry && (
           <button
             onClick={onRetry}
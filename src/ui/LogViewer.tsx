@@ -0,0 +1,109 @@
+import React, { useEffect, useState } from 'react'
+import { queryLogs, tailLogs, type LogEntry, type LogLevel, type LogQuery } from '../utils/log'
+import { errorMessage } from '../utils/errors'
+import { t } from '../utils/i18n'
+
+const LEVELS: LogLevel[] = ['DEBUG', 'INFO', 'WARN', 'ERROR']
+const COMPONENTS = ['chat', 'model-pull', 'model-check', 'frontend']
+const MAX_SHOWN = 1000
+
+const levelColor: Record<LogLevel, string> = {
+  DEBUG: 'text-gray-400',
+  INFO: 'text-gray-700',
+  WARN: 'text-amber-600',
+  ERROR: 'text-red-600',
+}
+
+// datetime-local inputs hold local time without a zone
+const toIso = (local: string) => (local ? new Date(local).toISOString() : undefined)
+
+export const LogViewer: React.FC<{ onClose: () => void; streamId?: string }> = ({ onClose, streamId }) => {
+  const [level, setLevel] = useState<LogLevel | ''>('')
+  const [component, setComponent] = useState('')
+  const [id, setId] = useState(streamId || '')
+  const [since, setSince] = useState('')
+  const [until, setUntil] = useState('')
+  const [follow, setFollow] = useState(true)
+  const [entries, setEntries] = useState<LogEntry[]>([])
+  const [error, setError] = useState('')
+
+  useEffect(() => {
+    const query: LogQuery = {
+      level: level || undefined,
+      component: component || undefined,
+      id: id.trim() || undefined,
+      since: toIso(since),
+      until: toIso(until),
+      limit: MAX_SHOWN,
+    }
+    let stop: (() => void) | undefined
+    let cancelled = false
+    const run = async () => {
+      try {
+        setError('')
+        setEntries(await queryLogs(query))
+        // an upper bound in the past can never match new entries
+        if (follow && !query.until && !cancelled) {
+          stop = await tailLogs(query, (entry) => setEntries(prev => [...prev.slice(-(MAX_SHOWN - 1)), entry]))
+          if (cancelled) stop()
+        }
+      } catch (err) {
+        setError(errorMessage(err))
+      }
+    }
+    run()
+    return () => {
+      cancelled = true
+      stop?.()
+    }
+  }, [level, component, id, since, until, follow])
+
+  return (
+    <div className="fixed inset-0 bg-black/20 z-50 flex items-center justify-center p-6">
+      <div className="w-full max-w-[960px] h-[80vh] bg-white rounded-ollama border border-gray-200 shadow-xl overflow-hidden flex flex-col">
+        <div className="h-14 px-4 border-b border-gray-200 flex items-center justify-between">
+          <div className="text-lg font-medium text-gray-800">{t('logs.title')}</div>
+          <button className="btn h-9 px-3" onClick={onClose}>{t('logs.close')}</button>
+        </div>
+
+        <div className="px-4 py-3 border-b border-gray-200 flex flex-wrap items-center gap-2 text-sm">
+          <select className="input h-9" value={level} onChange={e => setLevel(e.target.value as LogLevel | '')}>
+            <option value="">{t('logs.all_levels')}</option>
+            {LEVELS.map(l => <option key={l} value={l}>{l}+</option>)}
+          </select>
+          <select className="input h-9" value={component} onChange={e => setComponent(e.target.value)}>
+            <option value="">{t('logs.all_components')}</option>
+            {COMPONENTS.map(c => <option key={c} value={c}>{c}</option>)}
+          </select>
+          <input className="input h-9 w-56" placeholder={t('logs.stream_id')} value={id} onChange={e => setId(e.target.value)} />
+          <input className="input h-9" type="datetime-local" title={t('logs.since')} value={since} onChange={e => setSince(e.target.value)} />
+          <input className="input h-9" type="datetime-local" title={t('logs.until')} value={until} onChange={e => setUntil(e.target.value)} />
+          <label className="flex items-center gap-1">
+            <input type="checkbox" checked={follow} onChange={e => setFollow(e.target.checked)} />
+            {t('logs.follow')}
+          </label>
+        </div>
+
+        <div className="flex-1 overflow-auto bg-gray-50 p-3 font-mono text-xs">
+          {error && <div className="text-red-600 mb-2">{error}</div>}
+          {entries.length === 0 && !error ? (
+            <div className="text-gray-500">{t('logs.empty')}</div>
+          ) : (
+            entries.map((e, i) => (
+              <div key={`${e.ts}-${i}`} className="whitespace-pre-wrap break-all leading-relaxed">
+                <span className="text-gray-400">{new Date(e.ts).toLocaleString()}</span>{' '}
+                <span className={levelColor[e.level]}>{e.level}</span>{' '}
+                <span className="text-blue-600">{e.component}</span>
+                {e.id && <span className="text-gray-500"> {e.id}</span>}{' '}
+                <span>{e.message}</span>
+                {e.fields && Object.keys(e.fields).length > 0 && (
+                  <span className="text-gray-500"> {JSON.stringify(e.fields)}</span>
+                )}
+              </div>
+            ))
+          )}
+        </div>
+      </div>
+    </div>
+  )
+}
@@ -1,6 +1,7 @@
 import React, { useEffect, useState } from 'react'
 import { invoke } from '@tauri-apps/api/core'
 import { log } from '../utils/log'
+import { errorMessage, type BackendError } from '../utils/errors'
 
 interface ProgressData {
   status: string
@@ -96,8 +97,8 @@ export const ModelPullDialog: React.FC<{ baseUrl: string; model: string; onClose
         setTimeout(onClose, 2000)
       }))
       
-      unsubs.push(await listen<string>(`model-pull-error:${pullId}`, async (e) => {
-        const errorMsg = `错误: ${e.payload}`
+      unsubs.push(await listen<BackendError>(`model-pull-error:${pullId}`, async (e) => {
+        const errorMsg = `错误: ${errorMessage(e.payload)}`
         setLines(prev => [...prev, errorMsg])
         setStatus('下载失败')
         await log('ERROR', 'model_pull_dialog_error', { 
@@ -1,7 +1,11 @@
 import React, { useState } from 'react'
 import { useStore } from '../utils/store'
-import type { ModelConfig, MCPConfig, MCPServerInfo } from '../utils/types'
+import { PROVIDERS, type ModelConfig, type MCPConfig, type MCPServerInfo } from '../utils/types'
 import { Dropdown } from './Dropdown'
+import { LogViewer } from './LogViewer'
+import { isSecretRef } from '../utils/secrets'
+import { errorMessage } from '../utils/errors'
+import { activateProfile, deleteProfile, duplicateProfile } from '../utils/profiles'
 import { invoke } from '@tauri-apps/api/core'
 import { Command } from '@tauri-apps/plugin-shell'
 import { t, getCurrentLocale } from '../utils/i18n'
@@ -15,6 +19,7 @@ export const SettingsDrawer: React.FC<{ close: () => void }> = ({ close }) => {
   const [modelList, setModelList] = useState<ModelConfig[]>(config.models || [])
   const [mcpList, setMcpList] = useState<MCPConfig[]>(config.mcpServers || [])
   const [currentLang, setCurrentLang] = useState(getCurrentLocale())
+  const [showLogs, setShowLogs] = useState(false)
   
   // 确保local状态与最新的config同步
   React.useEffect(() => {
@@ -62,6 +67,38 @@ export const SettingsDrawer: React.FC<{ close: () => void }> = ({ close }) => {
     }
   }
 
+  // profile changes are saved by the backend at once; unsaved edits here are dropped
+  const switchProfile = async (name: string) => {
+    if (name === config.activeProfile) return
+    try {
+      await activateProfile(name)
+    } catch (error) {
+      alert(t('settings.profile_failed') + errorMessage(error))
+    }
+  }
+
+  const saveAsProfile = async () => {
+    const from = config.activeProfile || config.profiles?.[0]?.name
+    const name = window.prompt(t('settings.profile_name_prompt'))?.trim()
+    if (!from || !name) return
+    try {
+      await duplicateProfile(from, name)
+      await activateProfile(name)
+    } catch (error) {
+      alert(t('settings.profile_failed') + errorMessage(error))
+    }
+  }
+
+  const removeProfile = async () => {
+    const name = config.activeProfile
+    if (!name || !window.confirm(t('settings.profile_delete_confirm').replace('{name}', name))) return
+    try {
+      await deleteProfile(name)
+    } catch (error) {
+      alert(t('settings.profile_failed') + errorMessage(error))
+    }
+  }
+
   const saveSettings = async () => {
     try {
       console.log('保存前的配置:', { ...local, models: modelList, mcpServers: mcpList })
@@ -72,7 +109,7 @@ export const SettingsDrawer: React.FC<{ close: () => void }> = ({ close }) => {
       location.reload()
     } catch (error) {
       console.error('保存失败:', error)
-      alert('保存配置失败: ' + error)
+      alert('保存配置失败: ' + errorMessage(error))
     }
   }
 
@@ -141,11 +178,29 @@ export const SettingsDrawer: React.FC<{ close: () => void }> = ({ close }) => {
         <div className="flex-1 overflow-auto p-6">
           {tab === 'models' && (
             <div className="space-y-6 max-w-[760px]">
+              <div className="space-y-2">
+                <div className="text-sm text-gray-600">{t('settings.profile')}</div>
+                <div className="flex items-center gap-2">
+                  <Dropdown
+                    className="flex-1"
+                    value={config.activeProfile || ''}
+                    options={(config.profiles || []).map(p => ({ label: p.name, value: p.name }))}
+                    onChange={switchProfile}
+                    placeholder={t('settings.profile_none')}
+                  />
+                  <button className="btn h-10 px-3" onClick={saveAsProfile}>
+                    {t('settings.profile_duplicate')}
+                  </button>
+                  <button className="btn h-10 px-3" onClick={removeProfile} disabled={!config.activeProfile}>
+                    {t('settings.delete')}
+                  </button>
+                </div>
+              </div>
               <div className="space-y-2">
                 <div className="text-sm text-gray-600">{t('settings.default_provider')}</div>
                 <Dropdown 
                   value={local.provider} 
-                  options={[{label:'ollama',value:'ollama'},{label:'openai',value:'openai'}]} 
+                  options={PROVIDERS.map(p => ({ label: p, value: p }))} 
                   onChange={(v) => setLocal({...local, provider: v as any})} 
                 />
               </div>
@@ -188,7 +243,7 @@ export const SettingsDrawer: React.FC<{ close: () => void }> = ({ close }) => {
                       <div className="text-xs text-gray-500">Provider</div>
                       <Dropdown 
                         value={m.provider} 
-                        options={[{label:'ollama',value:'ollama'},{label:'openai',value:'openai'}]} 
+                        options={PROVIDERS.map(p => ({ label: p, value: p }))} 
                         onChange={(v) => {
                           const next = [...modelList]
                           next[idx] = {...m, provider: v as any}
@@ -209,13 +264,14 @@ export const SettingsDrawer: React.FC<{ close: () => void }> = ({ close }) => {
                         }} 
                       />
                     </div>
-                    {m.provider === 'openai' && (
+                    {m.provider !== 'ollama' && (
                       <div className="space-y-1 md:col-span-2">
                         <div className="text-xs text-gray-500">API Key</div>
                         <input 
                           className="input h-10 w-full" 
-                          placeholder={t('settings.api_key_placeholder')} 
-                          value={m.apiKey || ''} 
+                          type="password"
+                          placeholder={isSecretRef(m.apiKey) ? t('settings.api_key_stored') : t('settings.api_key_placeholder')} 
+                          value={isSecretRef(m.apiKey) ? '' : m.apiKey || ''} 
                           onChange={(e) => {
                             const next = [...modelList]
                             next[idx] = {...m, apiKey: e.target.value}
@@ -224,6 +280,49 @@ export const SettingsDrawer: React.FC<{ close: () => void }> = ({ close }) => {
                         />
                       </div>
                     )}
+                    {m.provider === 'azure' && (
+                      <>
+                        <div className="space-y-1">
+                          <div className="text-xs text-gray-500">{t('settings.deployment')}</div>
+                          <input 
+                            className="input h-10 w-full" 
+                            placeholder={m.name} 
+                            value={m.deployment || ''} 
+                            onChange={(e) => {
+                              const next = [...modelList]
+                              next[idx] = {...m, deployment: e.target.value}
+                              setModelList(next)
+                            }} 
+                          />
+                        </div>
+                        <div className="space-y-1">
+                          <div className="text-xs text-gray-500">{t('settings.api_version')}</div>
+                          <input 
+                            className="input h-10 w-full" 
+                            placeholder="2024-10-21" 
+                            value={m.apiVersion || ''} 
+                            onChange={(e) => {
+                              const next = [...modelList]
+                              next[idx] = {...m, apiVersion: e.target.value}
+                              setModelList(next)
+                            }} 
+                          />
+                        </div>
+                      </>
+                    )}
+                    <div className="space-y-1 md:col-span-2">
+                      <div className="text-xs text-gray-500">{t('settings.fallbacks')}</div>
+                      <input 
+                        className="input h-10 w-full" 
+                        placeholder="gpt-4o-mini, qwen3:0.6b" 
+                        value={(m.fallbacks || []).join(', ')} 
+                        onChange={(e) => {
+                          const next = [...modelList]
+                          next[idx] = {...m, fallbacks: e.target.value.split(',').map(s => s.trim())}
+                          setModelList(next)
+                        }} 
+                      />
+                    </div>
                     <div className="md:col-span-2 flex justify-end">
                       <button 
                         className="btn h-9 px-3" 
@@ -271,14 +370,23 @@ export const SettingsDrawer: React.FC<{ close: () => void }> = ({ close }) => {
                   />
                   {t('settings.default_think')}
                 </label>
+                <label className="flex items-center gap-2 text-sm text-gray-700">
+                  <input 
+                    type="checkbox" 
+                    checked={!!local.logPrompts} 
+                    onChange={(e) => setLocal({ ...local, logPrompts: e.target.checked })} 
+                  />
+                  {t('settings.log_prompts')}
+                </label>
                 <div className="space-y-1">
-                  <div className="text-sm text-gray-600">{t('settings.max_context_messages')}</div>
+                  <div className="text-sm text-gray-600">{t('settings.context_tokens')}</div>
                   <input 
                     type="number" 
                     min={0} 
                     className="input w-[160px]" 
-                    value={local.maxContextMessages ?? 20} 
-                    onChange={(e) => setLocal({ ...local, maxContextMessages: Number(e.target.value) || 0 })} 
+                    placeholder={t('settings.context_tokens_auto')}
+                    value={local.contextTokens ?? ''} 
+                    onChange={(e) => setLocal({ ...local, contextTokens: Number(e.target.value) || undefined })} 
                   />
                 </div>
                 <div className="space-y-1">
@@ -490,6 +598,9 @@ export const SettingsDrawer: React.FC<{ close: () => void }> = ({ close }) => {
           <button className="btn h-10 px-3" onClick={saveSettings}>
             {t('settings.save_and_restart')}
           </button>
+          <button className="btn h-10 px-3" onClick={() => setShowLogs(true)}>
+            {t('settings.view_logs')}
+          </button>
           <button className="btn h-10 px-3" onClick={openLogDirectory}>
             {t('settings.open_log_dir')}
           </button>
@@ -498,6 +609,7 @@ export const SettingsDrawer: React.FC<{ close: () => void }> = ({ close }) => {
           </button>
         </div>
       </div>
+      {showLogs && <LogViewer onClose={() => setShowLogs(false)} />}
     </div>
   )
 }
\ No newline at end of file
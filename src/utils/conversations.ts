@@ -1,10 +1,22 @@
 import { invoke } from '@tauri-apps/api/core'
 import type { Provider } from './types'
 
+// conversations live in the backend's SQLite database; conversations.json from
+// older versions is imported on first use
+
 export type ChatMessage = {
+  // set on messages read back from the database
+  id?: number
   role: 'user' | 'assistant'
+  // the answer without its reasoning; inline <think> sections are split off when saved
   content: string
+  thinking?: string
   createdAt: number
+  // who wrote an assistant message
+  model?: string
+  provider?: Provider
+  // ids of this message and its alternatives (edits, regenerations), oldest first
+  siblings?: number[]
 }
 
 export type Conversation = {
@@ -12,39 +24,106 @@ export type Conversation = {
   title: string
   model: string
   provider: Provider
+  createdAt?: number
   updatedAt: number
   messages: ChatMessage[]
+  metadata?: Record<string, string>
+}
+
+export type ConversationSummary = Omit<Conversation, 'messages' | 'metadata'> & { messageCount: number }
+
+export type SearchHit = {
+  conversationId: string
+  conversationTitle: string
+  messageIndex: number
+  messageId: number
+  role: 'user' | 'assistant'
+  // HTML-escaped excerpt, matches wrapped in <mark>
+  snippet: string
+  score: number
+  createdAt: number
 }
 
 export async function getConversationsPath(): Promise<string> {
   return await invoke<string>('get_conversations_path')
 }
 
-export async function loadConversations(): Promise<Conversation[]> {
-  try {
-    const fs = await import('@tauri-apps/plugin-fs')
-    const path = await getConversationsPath()
-    const text = await fs.readTextFile(path).catch(() => '[]')
-    const data = JSON.parse(text)
-    if (Array.isArray(data)) return data as Conversation[]
-    return []
-  } catch {
-    return []
-  }
+export async function listConversations(): Promise<ConversationSummary[]> {
+  return await invoke<ConversationSummary[]>('list_conversations')
 }
 
-export async function saveConversations(conversations: Conversation[]): Promise<void> {
-  try {
-    const fs = await import('@tauri-apps/plugin-fs')
-    const path = await getConversationsPath()
-    await fs.writeTextFile(path, JSON.stringify(conversations, null, 2))
-  } catch {
-    // ignore
-  }
+export async function getConversation(id: string): Promise<Conversation> {
+  return await invoke<Conversation>('get_conversation', { id })
 }
 
-export function createConversationId(): string {
-  return `c_${Date.now()}_${Math.random().toString(36).slice(2, 8)}`
+export async function createConversation(conversation: Omit<Conversation, 'updatedAt'> & { updatedAt?: number }): Promise<Conversation> {
+  return await invoke<Conversation>('create_conversation', { conversation })
+}
+
+export async function appendMessages(id: string, messages: ChatMessage[]): Promise<void> {
+  await invoke('append_messages', { id, messages })
+}
+
+export async function updateConversation(id: string, patch: { title?: string; model?: string; provider?: Provider }): Promise<void> {
+  await invoke('update_conversation', { id, patch })
+}
+
+export async function setConversationMetadata(id: string, key: string, value: string | null): Promise<void> {
+  await invoke('set_conversation_metadata', { id, key, value })
+}
+
+// conversations are trees: an edited prompt or a regenerated reply becomes a sibling of
+// the original, and `messages` of a conversation is always the active branch
+
+export async function forkConversation(id: string, messageId: number, message: ChatMessage): Promise<Conversation> {
+  return await invoke<Conversation>('fork_conversation', { id, messageId, message })
+}
+
+export async function regenerateMessage(id: string, messageId: number, reply: ChatMessage): Promise<Conversation> {
+  return await invoke<Conversation>('regenerate_message', { id, messageId, reply })
 }
 
+export async function switchBranch(id: string, messageId: number): Promise<Conversation> {
+  return await invoke<Conversation>('switch_branch', { id, messageId })
+}
+
+export async function deleteConversation(id: string): Promise<boolean> {
+  return await invoke<boolean>('delete_conversation', { id })
+}
+
+export async function searchConversations(query: string, limit?: number): Promise<SearchHit[]> {
+  return await invoke<SearchHit[]>('search_conversations', { query, limit })
+}
+
+export type ExportFormat = 'markdown' | 'html' | 'json'
+
+export const EXPORT_EXTENSIONS: Record<ExportFormat, string> = { markdown: 'md', html: 'html', json: 'json' }
+
+// writes the conversation to `path`; json is the versioned layout other tools can read
+export async function exportConversation(id: string, format: ExportFormat, path: string): Promise<void> {
+  await invoke('export_conversation', { id, format, path })
+}
+
+export type ImportSource = 'chatgpt' | 'openWebui'
 
+export type ImportReport = {
+  source: ImportSource
+  imported: string[]
+  skipped: { id?: string; title?: string; reason: string }[]
+  // system prompts, tool calls and attachments left out of imported conversations
+  skippedMessages: number
+}
+
+// reads a ChatGPT conversations.json or an Open WebUI export; the source is detected when omitted
+export async function importConversations(path: string, source?: ImportSource): Promise<ImportReport> {
+  return await invoke<ImportReport>('import_conversations', { path, source })
+}
+
+// the text ChatBubble renders: reasoning goes back inside <think> tags
+export function displayContent(m: Pick<ChatMessage, 'content' | 'thinking'>): string {
+  return m.thinking ? `<think>${m.thinking}</think>${m.content}` : m.content
+}
+
+export function createConversationId(): string {
+  return `c_${Date.now()}_${Math.random().toString(36).slice(2, 8)}`
+}
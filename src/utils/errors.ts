@@ -0,0 +1,45 @@
+// Errors returned by backend commands and carried by chat-error / model-pull-error events
+
+export type ErrorCode =
+  | 'ConnectionRefused'
+  | 'Unauthorized'
+  | 'ModelNotFound'
+  | 'RateLimited'
+  | 'Timeout'
+  | 'ProviderError'
+  | 'InvalidConfig'
+  | 'NotFound'
+  | 'VaultLocked'
+  | 'Internal'
+
+export type BackendError = {
+  code: ErrorCode
+  message: string
+  provider?: string
+  status?: number
+}
+
+export function isBackendError(e: unknown): e is BackendError {
+  return typeof e === 'object' && e !== null && 'code' in e && 'message' in e
+}
+
+// an Error that keeps the backend code, so callers can branch on it
+export class ChatError extends Error {
+  code: ErrorCode
+  provider?: string
+  status?: number
+
+  constructor(e: BackendError) {
+    super(e.message)
+    this.name = 'ChatError'
+    this.code = e.code
+    this.provider = e.provider
+    this.status = e.status
+  }
+}
+
+export function errorMessage(e: unknown): string {
+  if (isBackendError(e)) return e.message
+  if (e instanceof Error) return e.message
+  return String(e)
+}
@@ -39,3 +39,40 @@ function safeJson(v: unknown): string {
 }
 
 
+
+export type LogLevel = 'DEBUG' | 'INFO' | 'WARN' | 'ERROR'
+
+/** One structured entry of `logs/app.log`. */
+export interface LogEntry {
+  ts: string
+  level: LogLevel
+  component: string
+  id?: string
+  message: string
+  fields?: Record<string, unknown>
+}
+
+/** Unset fields match everything; `level` is a minimum; `since`/`until` are ISO timestamps. */
+export interface LogQuery {
+  level?: LogLevel
+  component?: string
+  id?: string
+  since?: string
+  until?: string
+  limit?: number
+}
+
+export async function queryLogs(query: LogQuery = {}): Promise<LogEntry[]> {
+  return await invoke<LogEntry[]>('query_logs', { query })
+}
+
+/** Calls `onEntry` for every new matching entry; resolves to a function that stops tailing. */
+export async function tailLogs(query: LogQuery, onEntry: (entry: LogEntry) => void): Promise<() => void> {
+  const { listen } = await import('@tauri-apps/api/event')
+  const id = await invoke<string>('tail_logs', { query })
+  const unlisten = await listen<LogEntry>(`log-entry:${id}`, (e) => onEntry(e.payload))
+  return () => {
+    unlisten()
+    invoke('stop_tail_logs', { id }).catch(() => {})
+  }
+}
@@ -0,0 +1,30 @@
+import { invoke } from '@tauri-apps/api/core'
+import { useStore, type AppConfig } from './store'
+import type { Profile } from './types'
+
+// named connection settings; the backend keeps them in settings.json and each
+// change is saved right away, replacing the config in the store
+
+export type ProfileList = { active: string | null; profiles: Profile[] }
+
+export async function listProfiles(): Promise<ProfileList> {
+  return await invoke<ProfileList>('list_profiles')
+}
+
+async function apply(command: string, args: Record<string, unknown>): Promise<AppConfig> {
+  const config = await invoke<AppConfig>(command, args)
+  useStore.setState({ config })
+  return config
+}
+
+export async function activateProfile(name: string): Promise<AppConfig> {
+  return await apply('activate_profile', { name })
+}
+
+export async function duplicateProfile(from: string, name: string): Promise<AppConfig> {
+  return await apply('duplicate_profile', { from, name })
+}
+
+export async function deleteProfile(name: string): Promise<AppConfig> {
+  return await apply('delete_profile', { name })
+}
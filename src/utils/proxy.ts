@@ -4,6 +4,7 @@ import type { AppConfig } from './store'
 import type { Message } from '../ui/App'
 import type { MCPConfig, MCPToolCall, MCPToolResult, ReActStep, MCPTool, MCPServerInfo, ReActCycle, TaskExecution } from './types'
 import { log } from './log'
+import { ChatError, isBackendError, type BackendError } from './errors'
 
 export async function fetchModels(config: AppConfig): Promise<string[]> {
   // If provider is ollama, probe server and try to start if not running
@@ -42,31 +43,78 @@ export async function fetchModels(config: AppConfig): Promise<string[]> {
   }
 }
 
+// id of the backend chat stream currently being consumed by streamChat
+let activeStreamId: string | null = null
+
+export async function stopChat(): Promise<void> {
+  const id = activeStreamId
+  if (!id) return
+  try {
+    await invoke<boolean>('stop_chat_stream', { id })
+  } catch (e) {
+    log('ERROR', 'stop_chat_stream failed', e)
+  }
+}
+
+// payload of chat-retry:{id}, sent before the backend waits for another attempt
+export type ChatRetry = {
+  attempt: number
+  maxAttempts: number
+  delayMs: number
+  error: string
+}
+
 export async function* streamChat(params: {
   config: AppConfig
   messages: Message[]
   model: string
   think?: boolean
+  // overrides the fallbacks of the model's entry in config.models
+  fallbacks?: string[]
+  // answer with the connection settings of this profile instead of the active one
+  profile?: string
+  onRetry?: (retry: ChatRetry) => void
 }): AsyncGenerator<string, void, unknown> {
-  if (params.config.provider === 'ollama') {
+  const profile = params.profile ? params.config.profiles?.find(p => p.name === params.profile) : undefined
+  const server = profile ? { ...params.config, ...profile } : params.config
+  if (server.provider === 'ollama') {
     try {
-      const ok = await invoke<boolean>('ensure_ollama', { config: params.config })
+      const ok = await invoke<boolean>('ensure_ollama', { config: server })
       if (!ok) throw new Error('Ollama is not running')
     } catch (e) {
       log('ERROR', 'ensure_ollama tauri failed', e)
-      throw e as Error
+      throw isBackendError(e) ? new ChatError(e) : e as Error
     }
   }
   try {
     const streamId = await invoke<string>('start_chat_stream', { body: JSON.stringify(params) })
+    activeStreamId = streamId
     const { listen } = await import('@tauri-apps/api/event')
     const unsubs: Array<() => void> = []
     const queue: string[] = []
     const done = { v: false }
-    const err = { v: '' }
-    unsubs.push(await listen<string>(`chat-chunk:${streamId}`, (e)=>{ queue.push(e.payload) }))
-    unsubs.push(await listen<string>(`chat-end:${streamId}`, ()=>{ done.v = true }))
-    unsubs.push(await listen<string>(`chat-error:${streamId}`, (e)=>{ err.v = e.payload; done.v = true }))
+    const err: { v: BackendError | null } = { v: null }
+    // reasoning arrives on its own channel; ChatBubble renders it from <think> tags
+    const thinking = { open: false }
+    unsubs.push(await listen<string>(`chat-thinking:${streamId}`, (e)=>{
+      if (!thinking.open) { queue.push('<think>'); thinking.open = true }
+      queue.push(e.payload)
+    }))
+    unsubs.push(await listen<string>(`chat-chunk:${streamId}`, (e)=>{
+      if (thinking.open) { queue.push('</think>'); thinking.open = false }
+      queue.push(e.payload)
+    }))
+    unsubs.push(await listen<{ model: string; provider: string }>(`chat-end:${streamId}`, (e)=>{
+      if (thinking.open) { queue.push('</think>'); thinking.open = false }
+      if (e.payload.model !== params.model) log('WARN', 'chat answered by fallback model', e.payload)
+      done.v = true
+    }))
+    unsubs.push(await listen<BackendError>(`chat-error:${streamId}`, (e)=>{ err.v = e.payload; done.v = true }))
+    unsubs.push(await listen<string>(`chat-cancelled:${streamId}`, ()=>{ done.v = true }))
+    unsubs.push(await listen<ChatRetry>(`chat-retry:${streamId}`, (e)=>{
+      log('WARN', `chat retrying ${e.payload.attempt}/${e.payload.maxAttempts}`, e.payload)
+      params.onRetry?.(e.payload)
+    }))
     while (!done.v || queue.length) {
       if (queue.length) {
         yield queue.shift()!
@@ -75,9 +123,14 @@ export async function* streamChat(params: {
       }
     }
     unsubs.forEach(u=>u())
-    if (err.v) throw new Error(err.v)
+    if (activeStreamId === streamId) activeStreamId = null
+    if (err.v) throw new ChatError(err.v)
     return
-  } catch {}
+  } catch (e) {
+    // the stream itself failed: report it rather than asking again without streaming
+    if (e instanceof ChatError) throw e
+    if (isBackendError(e)) throw new ChatError(e)
+  }
   const handle = await invoke<string>('proxy_chat_stream', { body: JSON.stringify(params) })
   const text = await invoke<string>('proxy_chat', { handle })
   yield text
@@ -0,0 +1,86 @@
+import { invoke } from '@tauri-apps/api/core'
+import { log } from './log'
+import { isBackendError } from './errors'
+import { t } from './i18n'
+import type { AppConfig } from './store'
+
+// API keys live in the backend secret store (OS keyring, or a vault file unlocked
+// by a passphrase); the config only keeps `secret:<id>` references to them
+
+const REF_PREFIX = 'secret:'
+
+export type SecretStatus = { keyring: boolean; vaultExists: boolean; vaultUnlocked: boolean }
+
+export function isSecretRef(value?: string): boolean {
+  return !!value && value.startsWith(REF_PREFIX)
+}
+
+export async function setSecret(id: string, value: string): Promise<string> {
+  return await invoke<string>('set_secret', { id, value })
+}
+
+export async function getSecret(id: string): Promise<string | null> {
+  return await invoke<string | null>('get_secret', { id })
+}
+
+export async function deleteSecret(id: string): Promise<boolean> {
+  return await invoke<boolean>('delete_secret', { id })
+}
+
+export async function secretStatus(): Promise<SecretStatus> {
+  return await invoke<SecretStatus>('secret_store_status')
+}
+
+export async function unlockSecrets(passphrase: string): Promise<void> {
+  await invoke('unlock_secrets', { passphrase })
+}
+
+// asks for the vault passphrase until it opens; false if the user gives up
+export async function promptUnlock(): Promise<boolean> {
+  const status = await secretStatus()
+  if (status.vaultUnlocked) return true
+  let message = t(status.vaultExists ? 'secrets.unlock_prompt' : 'secrets.create_prompt')
+  for (;;) {
+    const passphrase = window.prompt(message)
+    if (!passphrase) return false
+    try {
+      await unlockSecrets(passphrase)
+      return true
+    } catch (e) {
+      log('WARN', 'secret vault unlock failed', e)
+      message = t('secrets.unlock_retry').replace('{error}', isBackendError(e) ? e.message : String(e))
+    }
+  }
+}
+
+// stores `value` under `id` unless it is empty or already a reference; returns what the config should hold
+async function storeKey(id: string, value?: string): Promise<string | undefined> {
+  if (!value || isSecretRef(value)) return value
+  try {
+    return await setSecret(id, value)
+  } catch (e) {
+    if (isBackendError(e) && e.code === 'VaultLocked' && await promptUnlock()) {
+      return await setSecret(id, value)
+    }
+    throw e
+  }
+}
+
+// moves plain-text API keys into the secret store, returning the config with references in their place
+export async function storeApiKeys(config: AppConfig): Promise<AppConfig> {
+  // each profile keeps its own key
+  const apiKey = await storeKey(config.activeProfile ? `apiKey:profile:${config.activeProfile}` : 'apiKey:default', config.apiKey)
+  // one at a time, so a locked vault is only asked for once
+  let models: AppConfig['models']
+  if (config.models) {
+    models = []
+    for (const m of config.models) {
+      models.push({ ...m, apiKey: await storeKey(`apiKey:model:${m.name}`, m.apiKey) })
+    }
+  }
+  return { ...config, apiKey, models }
+}
+
+export function usesSecretRefs(config: AppConfig): boolean {
+  return isSecretRef(config.apiKey) || (config.models || []).some(m => isSecretRef(m.apiKey))
+}
@@ -1,20 +1,32 @@
 import { log } from './log'
 import { create } from 'zustand'
 import { invoke } from '@tauri-apps/api/core'
-import type { ModelConfig, Provider, MCPConfig, MCPServerInfo } from './types'
+import type { ModelConfig, Provider, MCPConfig, MCPServerInfo, Profile } from './types'
+import { promptUnlock, secretStatus, storeApiKeys, usesSecretRefs } from './secrets'
 
 export type AppConfig = {
+  // settings.json schema version; set by the backend on save
+  version?: number
   provider: Provider
   baseUrl: string
+  // a `secret:<id>` reference once saved; the key itself is in the backend secret store
   apiKey?: string
   model?: string
+  deployment?: string
+  apiVersion?: string
   ollamaPath?: string
   models?: ModelConfig[]
   // chat options
   streamingEnabled?: boolean
   defaultThink?: boolean
-  maxContextMessages?: number
+  // context window in tokens that history is trimmed to; unset uses the model's own
+  contextTokens?: number
   temperature?: number
+  // network options, in seconds; the backend fills in defaults
+  timeouts?: { connectSecs?: number; firstByteSecs?: number; idleSecs?: number }
+  retry?: { maxAttempts?: number; baseDelayMs?: number; maxDelayMs?: number }
+  // write chat messages to app.log verbatim; off by default
+  logPrompts?: boolean
   // ui options
   language?: 'zh-CN' | 'en'
   // mcp options
@@ -22,6 +34,9 @@ export type AppConfig = {
   mcpServerInfos?: Record<string, MCPServerInfo>
   mcpMaxRetries?: number
   mcpReflectionEnabled?: boolean
+  // saved connection settings; the fields above belong to activeProfile
+  profiles?: Profile[]
+  activeProfile?: string | null
 }
 
 type StoreState = {
@@ -40,8 +55,8 @@ export const useStore = create<StoreState>((set, get) => ({
     models: [ { name: 'qwen3:0.6b', provider: 'ollama', baseUrl: 'http://localhost:11434' } ],
     streamingEnabled: true,
     defaultThink: true,
-    maxContextMessages: 20,
     temperature: 0.6,
+    logPrompts: false,
     language: 'zh-CN',
     mcpServers: [],
     mcpMaxRetries: 3,
@@ -54,80 +69,49 @@ export const useStore = create<StoreState>((set, get) => ({
   },
   async persist() {
     try {
-      console.log('persist开始，当前配置:', get().config);
-      const fs = await import('@tauri-apps/plugin-fs')
-      const path = await invoke<string>('get_config_path')
-      console.log('配置文件路径:', path);
-      const configJson = JSON.stringify(get().config, null, 2);
-      console.log('准备写入的JSON:', configJson);
-      await fs.writeTextFile(path, configJson)
-      console.log('文件写入成功');
-      log('INFO', 'settings saved', get().config)
-      
-      // 验证写入是否成功
-      try {
-        const readBack = await fs.readTextFile(path);
-        console.log('验证读取回的内容:', readBack);
-        const parsed = JSON.parse(readBack);
-        console.log('解析后的配置:', parsed);
-      } catch (verifyError) {
-        console.error('验证读取失败:', verifyError);
-      }
+      // keep API keys out of settings.json
+      const config = await storeApiKeys(get().config)
+      // the backend validates, stamps the schema version and writes the file
+      const saved = await invoke<AppConfig>('save_settings', { config })
+      set({ config: saved })
+      log('INFO', 'settings saved')
     } catch (error) {
-      console.error('persist失败:', error);
-      log('ERROR', 'persist failed', error);
+      log('ERROR', 'persist failed', error)
       // 重新抛出错误，让调用者知道保存失败
-      throw error;
+      throw error
     }
   },
 }))
 
 export async function bootstrapConfig() {
   try {
-    const fs = await import('@tauri-apps/plugin-fs')
-    const path = await invoke<string>('get_config_path')
-    let value: AppConfig | null = null
-    try {
-      const text = await fs.readTextFile(path)
-      value = JSON.parse(text)
-    } catch {}
-    if (value) {
-      const hydrated: AppConfig = {
-        provider: value.provider || 'ollama',
-        baseUrl: value.baseUrl || 'http://localhost:11434',
-        apiKey: value.apiKey || '',
-        model: value.model && value.model.length > 0 ? value.model : 'gpt-oss:20b',
-        ollamaPath: value.ollamaPath || '',
-        models: value.models && value.models.length ? value.models : [ { name: 'qwen3:0.6b', provider: 'ollama', baseUrl: 'http://localhost:11434' } ],
-        streamingEnabled: value.streamingEnabled ?? true,
-        defaultThink: value.defaultThink ?? true,
-        maxContextMessages: value.maxContextMessages ?? 20,
-        temperature: value.temperature ?? 0.6,
-        language: value.language ?? 'zh-CN',
-        mcpServers: (value.mcpServers || []).map(mcp => ({
-          ...mcp,
-          args: mcp.args || [],
-          env: mcp.env || {}
-        })),
-        mcpServerInfos: value.mcpServerInfos || {},
-        mcpMaxRetries: value.mcpMaxRetries ?? 3,
-        mcpReflectionEnabled: value.mcpReflectionEnabled ?? true
-      }
-      useStore.setState({ config: hydrated })
-      log('INFO', 'settings loaded', hydrated)
-      
-      // 初始化MCP服务器（如果有启用的服务器）
-      const enabledMCPServers = hydrated.mcpServers?.filter(mcp => mcp.enabled) || []
-      if (enabledMCPServers.length > 0) {
-        // 异步初始化MCP服务器，不阻塞应用启动
-        initializeMCPServersAsync(hydrated.mcpServers || [])
+    // the backend fills in defaults and upgrades files written by older versions
+    const loaded = await invoke<AppConfig>('load_settings')
+    const hydrated: AppConfig = {
+      ...loaded,
+      apiKey: loaded.apiKey || '',
+      ollamaPath: loaded.ollamaPath || '',
+    }
+    useStore.setState({ config: hydrated })
+    log('INFO', 'settings loaded', hydrated)
+
+    // keys kept in the vault need its passphrase before the first request
+    if (usesSecretRefs(hydrated)) {
+      const status = await secretStatus().catch(() => null)
+      if (status && !status.keyring && status.vaultExists && !status.vaultUnlocked) {
+        await promptUnlock()
       }
-    } else {
-      // keep defaults
-      log('INFO', 'settings not found, using defaults')
     }
-  } catch {
-    // ignore read errors
+
+    // 初始化MCP服务器（如果有启用的服务器）
+    const enabledMCPServers = hydrated.mcpServers?.filter(mcp => mcp.enabled) || []
+    if (enabledMCPServers.length > 0) {
+      // 异步初始化MCP服务器，不阻塞应用启动
+      initializeMCPServersAsync(hydrated.mcpServers || [])
+    }
+  } catch (error) {
+    // keep defaults
+    log('ERROR', 'settings load failed, using defaults', error)
   }
 }
 
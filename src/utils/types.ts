@@ -1,10 +1,29 @@
-export type Provider = 'ollama' | 'openai'
+export type Provider = 'ollama' | 'openai' | 'anthropic' | 'gemini' | 'azure'
+
+export const PROVIDERS: Provider[] = ['ollama', 'openai', 'anthropic', 'gemini', 'azure']
 
 export type ModelConfig = {
   name: string
   provider: Provider
   baseUrl?: string
   apiKey?: string
+  // azure openai only; deployment defaults to the model name
+  deployment?: string
+  apiVersion?: string
+  // names of other models to try, in order, when this one cannot answer
+  fallbacks?: string[]
+}
+
+// a named set of connection settings, switched with activate_profile
+export type Profile = {
+  name: string
+  provider: Provider
+  baseUrl: string
+  apiKey?: string | null
+  model?: string | null
+  temperature?: number | null
+  deployment?: string | null
+  apiVersion?: string | null
 }
 
 export type MCPConfig = {